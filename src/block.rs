@@ -1,175 +1,604 @@
-use iced::{Element, widget::{column, row, text, button, container}};
-use std::path::PathBuf;
+use iced::{Element, widget::{column, row, text, button, container, mouse_area, pick_list, text_input, Space}};
+use std::future::Future;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 use uuid::Uuid;
 use chrono::{DateTime, Utc};
 
-use crate::{Message, BlockMessage};
+use crate::{Message, BlockMessage, OUTPUT_FONT_SIZE, OUTPUT_LINE_HEIGHT};
+use crate::config::{self, BlockState, ColorScheme, HyperlinkBehavior, TerminalPreferences, ThemeConfig};
+use crate::diagnostics::{self, Diagnosis, Suggestion};
+use crate::export::{CopyTarget, ExportFormat};
+use crate::links;
+use crate::preview::{self, Preview};
+use crate::pty::{ResourceUsage, Signal};
+use crate::search::{Match, MatchLocation};
+use crate::renderer;
+use crate::shell::ProcessHandle;
+use crate::terminal::{self, Terminal};
+use crate::terminal::view::{Highlight, LinkSpan};
+
+/// Height of a row of output, as the terminal grid lays it out
+pub const ROW_HEIGHT: f32 = OUTPUT_FONT_SIZE * OUTPUT_LINE_HEIGHT;
+
+// Rough heights of the parts of a block's card, for leaving room for blocks and rows that are not drawn
+const CARD_PADDING: f32 = 12.0;
+const HEADER_HEIGHT: f32 = 21.0;
+const SECTION_SPACING: f32 = 8.0;
+const COMMAND_LINE_HEIGHT: f32 = 14.0 * OUTPUT_LINE_HEIGHT;
+const SMALL_LINE_HEIGHT: f32 = 12.0 * OUTPUT_LINE_HEIGHT;
+const ACTIONS_HEIGHT: f32 = 26.0;
+const IMAGE_HEIGHT: f32 = 400.0;
 
 #[derive(Debug, Clone)]
 pub struct Block {
     pub id: Uuid,
     pub content: BlockContent,
     pub timestamp: DateTime<Utc>,
+    /// When the command finished
+    pub finished_at: Option<DateTime<Utc>>,
     pub exit_code: Option<i32>,
+    /// What the finished command used, where the platform reports it
+    pub usage: Option<ResourceUsage>,
+    /// Handle to the running command, dropped once it finishes
+    pub process: Option<ProcessHandle>,
+    /// Last signal the user sent, used to explain how the command ended
+    pub last_signal: Option<Signal>,
+    /// Line being typed for the running command's stdin
+    pub stdin: String,
+    /// The block this one re-ran
+    pub rerun_of: Option<Uuid>,
+    /// Only the header and command are shown
+    pub collapsed: bool,
+    /// Output over the collapse threshold is shown in full instead of just its head and tail
+    pub show_full_output: bool,
+    /// Kept above the other blocks of the pane
+    pub pinned: bool,
+    /// Listed among the pane's bookmarks
+    pub bookmarked: bool,
+    /// A note written about the block
+    pub annotation: Option<String>,
+    /// The note is being written or changed
+    pub editing_annotation: bool,
+}
+
+/// What the header reports about a block's command
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlockStatus {
+    Running,
+    Stopped,
+    Succeeded,
+    Failed(i32),
+    Interrupted,
+    Killed,
 }
 
 #[derive(Debug, Clone)]
 pub enum BlockContent {
     Command { 
         input: String, 
+        /// Plain text of the output with control sequences applied, filled in once the command finishes
         output: String,
         working_dir: PathBuf,
+        /// Screen state the output is rendered from
+        terminal: Terminal,
     },
     Markdown(String),
     FilePreview {
         path: PathBuf,
+        /// The file's text, or a hex dump of a binary file
         content: String,
         file_type: FileType,
+        /// Size of the whole file, of which at most [`preview::MAX_PREVIEW_BYTES`] are shown
+        size: u64,
+        preview: Preview,
     },
     Error {
         message: String,
         details: Option<String>,
+        /// Follow-ups offered below the error
+        suggestions: Vec<Suggestion>,
     },
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum FileType {
     Text,
+    Markdown,
     Json,
     Yaml,
-    Code(String), // language
+    Code(String), // file extension the syntax is known by
     Image,
     Binary,
 }
 
 impl Block {
-    pub fn new_command(input: String) -> Self {
+    fn with_content(content: BlockContent) -> Self {
         Self {
             id: Uuid::new_v4(),
-            content: BlockContent::Command {
-                input,
-                output: String::new(),
-                working_dir: std::env::current_dir().unwrap_or_default(),
-            },
+            content,
             timestamp: Utc::now(),
+            finished_at: None,
             exit_code: None,
+            usage: None,
+            process: None,
+            last_signal: None,
+            stdin: String::new(),
+            rerun_of: None,
+            collapsed: false,
+            show_full_output: false,
+            pinned: false,
+            bookmarked: false,
+            annotation: None,
+            editing_annotation: false,
         }
     }
 
+    /// A block for a command about to run in `working_dir`
+    pub fn new_command(input: String, working_dir: PathBuf, cols: usize, rows: usize, scrollback_lines: usize) -> Self {
+        Self::with_content(BlockContent::Command {
+            input,
+            output: String::new(),
+            working_dir,
+            terminal: Terminal::new(cols, rows, scrollback_lines),
+        })
+    }
+
     pub fn new_markdown(content: String) -> Self {
-        Self {
-            id: Uuid::new_v4(),
-            content: BlockContent::Markdown(content),
-            timestamp: Utc::now(),
-            exit_code: None,
+        Self::with_content(BlockContent::Markdown(content))
+    }
+
+    /// A preview of a file from its first bytes and full size, with code highlighted in the colors of `theme`
+    pub fn new_file_preview(path: PathBuf, bytes: &[u8], size: u64, theme: &ThemeConfig) -> Self {
+        let file_type = Self::detect_file_type(&path, bytes);
+        let (content, preview) = preview::prepare(&file_type, bytes, theme);
+
+        Self::with_content(BlockContent::FilePreview {
+            path,
+            content,
+            file_type,
+            size,
+            preview,
+        })
+    }
+
+    /// A preview of the file at `path`, or an error block saying why it cannot be shown
+    pub fn preview_file(path: PathBuf, theme: &ThemeConfig) -> Self {
+        match preview::read(&path) {
+            Ok((bytes, size)) => Self::new_file_preview(path, &bytes, size, theme),
+            Err(e) => Self::new_error(format!("Cannot preview {}", path.display()), Some(e.to_string())),
         }
     }
 
-    pub fn new_file_preview(path: PathBuf, content: String) -> Self {
-        let file_type = Self::detect_file_type(&path);
-        
+    pub fn new_error(message: String, details: Option<String>) -> Self {
+        Self::with_content(BlockContent::Error {
+            message,
+            details,
+            suggestions: Vec::new(),
+        })
+    }
+
+    /// An error block explaining why a command failed
+    pub fn from_diagnosis(diagnosis: Diagnosis) -> Self {
+        Self::with_content(BlockContent::Error {
+            message: diagnosis.message,
+            details: diagnosis.details,
+            suggestions: diagnosis.suggestions,
+        })
+    }
+
+    /// Recreate a command block saved by a previous run
+    pub fn restore(state: BlockState, cols: usize, rows: usize, scrollback_lines: usize) -> Self {
+        let mut terminal = Terminal::new(cols, rows, scrollback_lines);
+        terminal.feed(state.output.replace('\n', "\r\n").as_bytes());
+
         Self {
             id: Uuid::new_v4(),
-            content: BlockContent::FilePreview {
-                path,
-                content,
-                file_type,
+            content: BlockContent::Command {
+                input: state.input,
+                output: terminal.text(),
+                working_dir: state.working_dir,
+                terminal,
             },
-            timestamp: Utc::now(),
-            exit_code: None,
+            timestamp: state.timestamp,
+            finished_at: state.finished_at,
+            // Commands still running at exit were hung up on along with their terminal
+            exit_code: Some(state.exit_code.unwrap_or(128 + 1)),
+            usage: state.cpu_time_ms.map(|cpu_time_ms| ResourceUsage {
+                cpu_time: Duration::from_millis(cpu_time_ms),
+                peak_rss_kb: state.peak_rss_kb,
+            }),
+            process: None,
+            last_signal: None,
+            stdin: String::new(),
+            rerun_of: None,
+            collapsed: state.collapsed,
+            show_full_output: false,
+            pinned: state.pinned,
+            bookmarked: state.bookmarked,
+            annotation: state.annotation,
+            editing_annotation: false,
+        }
+    }
+
+    /// The parts of a command block worth keeping across restarts, with at most `scrollback_lines` of output
+    pub fn save(&self, scrollback_lines: usize) -> Option<BlockState> {
+        match &self.content {
+            BlockContent::Command { input, working_dir, terminal, .. } => Some(BlockState {
+                input: input.clone(),
+                output: config::last_lines(&terminal.ansi_text(), scrollback_lines).to_string(),
+                working_dir: working_dir.clone(),
+                exit_code: self.exit_code,
+                timestamp: self.timestamp,
+                finished_at: self.finished_at,
+                cpu_time_ms: self.usage.map(|usage| usage.cpu_time.as_millis() as u64),
+                peak_rss_kb: self.usage.and_then(|usage| usage.peak_rss_kb),
+                collapsed: self.collapsed,
+                pinned: self.pinned,
+                bookmarked: self.bookmarked,
+                annotation: self.annotation.clone(),
+            }),
+            _ => None,
+        }
+    }
+
+    /// Feed a chunk of output from the still running command
+    pub fn append_output(&mut self, bytes: &[u8]) {
+        if let BlockContent::Command { terminal, .. } = &mut self.content {
+            terminal.feed(bytes);
         }
     }
 
-    pub fn set_output(&mut self, output: String, exit_code: i32) {
-        if let BlockContent::Command { output: ref mut out, .. } = &mut self.content {
-            *out = output;
+    /// The emulated terminal of a command block
+    pub fn terminal(&self) -> Option<&Terminal> {
+        match &self.content {
+            BlockContent::Command { terminal, .. } => Some(terminal),
+            _ => None,
+        }
+    }
+
+    pub fn terminal_mut(&mut self) -> Option<&mut Terminal> {
+        match &mut self.content {
+            BlockContent::Command { terminal, .. } => Some(terminal),
+            _ => None,
+        }
+    }
+
+    pub fn finish(&mut self, exit_code: i32, usage: Option<ResourceUsage>) {
+        if let BlockContent::Command { output, terminal, .. } = &mut self.content {
+            *output = terminal.text();
+            self.finished_at = Some(Utc::now());
             self.exit_code = Some(exit_code);
+            self.usage = usage;
+            self.process = None;
+        }
+    }
+
+    /// A one-line description of the block for lists such as the bookmarks: its note, or else what it shows
+    pub fn label(&self) -> String {
+        let label = match (&self.annotation, &self.content) {
+            (Some(annotation), _) => annotation.lines().next().unwrap_or_default().to_string(),
+            (None, BlockContent::Command { input, .. }) => format!("$ {}", input),
+            (None, BlockContent::Markdown(content)) => content.lines().next().unwrap_or_default().to_string(),
+            (None, BlockContent::FilePreview { path, .. }) => path.display().to_string(),
+            (None, BlockContent::Error { message, .. }) => message.clone(),
+        };
+        if label.trim().is_empty() {
+            self.timestamp.format("%H:%M:%S").to_string()
+        } else {
+            label
+        }
+    }
+
+    /// Start writing or changing the block's note
+    pub fn edit_annotation(&mut self) {
+        self.annotation.get_or_insert_with(String::new);
+        self.editing_annotation = true;
+    }
+
+    pub fn annotation_input_id(&self) -> text_input::Id {
+        text_input::Id::new(format!("note-{}", self.id))
+    }
+
+    /// Finish writing the note, dropping it if it was left empty
+    pub fn save_annotation(&mut self) {
+        self.editing_annotation = false;
+        if self.annotation.as_deref().map_or(false, |annotation| annotation.trim().is_empty()) {
+            self.annotation = None;
         }
     }
 
-    pub fn view(&self) -> Element<Message> {
+    /// Work out why the finished command failed, if it did, from its output; the diagnosis is
+    /// `None` when the output doesn't say so in a way we recognize
+    pub fn diagnose(&self) -> Option<impl Future<Output = Option<Diagnosis>>> {
+        let BlockStatus::Failed(exit_code) = self.status() else {
+            return None;
+        };
+        match &self.content {
+            BlockContent::Command { input, output, working_dir, .. } => Some(diagnostics::diagnose(
+                input.clone(),
+                output.clone(),
+                exit_code,
+                working_dir.clone(),
+            )),
+            _ => None,
+        }
+    }
+
+    /// Write raw bytes to the running command
+    pub fn send_input(&self, bytes: &[u8]) {
+        if let Some(process) = &self.process {
+            if let Err(e) = process.write(bytes) {
+                eprintln!("Failed to write to command: {}", e);
+            }
+        }
+    }
+
+    pub fn send_signal(&mut self, signal: Signal) {
+        if let Some(process) = &self.process {
+            match process.signal(signal) {
+                Ok(()) => self.last_signal = Some(signal),
+                Err(e) => eprintln!("Failed to send {:?}: {}", signal, e),
+            }
+        }
+    }
+
+    /// How long the command ran, once it has finished
+    pub fn duration(&self) -> Option<chrono::Duration> {
+        self.finished_at.map(|finished_at| finished_at - self.timestamp)
+    }
+
+    /// How long the command has been running so far, or ran in total
+    pub fn elapsed(&self) -> chrono::Duration {
+        self.finished_at.unwrap_or_else(Utc::now) - self.timestamp
+    }
+
+    /// Whether this is a command that has not finished yet
+    pub fn is_running(&self) -> bool {
+        matches!(self.content, BlockContent::Command { .. }) && self.exit_code.is_none()
+    }
+
+    pub fn status(&self) -> BlockStatus {
+        // Shells report death by signal N as exit status 128 + N
+        match (self.exit_code, self.last_signal) {
+            (None, Some(Signal::Stop)) => BlockStatus::Stopped,
+            (None, _) => BlockStatus::Running,
+            // A suspended job can only be resumed while its process is still held
+            (Some(148), _) if self.process.is_some() => BlockStatus::Stopped,
+            // A command that caught the interrupt and exited cleanly was not interrupted
+            (Some(130), _) => BlockStatus::Interrupted,
+            (Some(code), Some(Signal::Interrupt)) if code != 0 => BlockStatus::Interrupted,
+            (Some(137), _) | (Some(143), _) => BlockStatus::Killed,
+            (Some(_), Some(Signal::Kill)) | (Some(_), Some(Signal::Terminate)) => BlockStatus::Killed,
+            (Some(0), _) => BlockStatus::Succeeded,
+            (Some(code), _) => BlockStatus::Failed(code),
+        }
+    }
+
+    /// The block's card; `focused` marks the block cursor, `selected` membership in the selection,
+    /// `matches` the find bar's matches in this block with whether each is the current one, and
+    /// `visible` the part of the card, measured from its top, worth drawing rows of output for
+    pub fn view(
+        &self,
+        theme: &ThemeConfig,
+        preferences: &TerminalPreferences,
+        focused: bool,
+        selected: bool,
+        matches: &[(&Match, bool)],
+        visible: Range<f32>,
+    ) -> Element<Message> {
         let header = self.create_header();
-        let content = self.create_content();
-        let actions = self.create_actions();
 
-        container(
-            column![header, content, actions]
+        let input_matches: Vec<(Range<usize>, bool)> = matches
+            .iter()
+            .filter(|(m, _)| m.location == MatchLocation::Input)
+            .map(|(m, current)| (m.columns.clone(), *current))
+            .collect();
+        let output_matches: Vec<Highlight> = matches
+            .iter()
+            .filter_map(|(m, current)| match m.location {
+                MatchLocation::Line(row) => Some(Highlight { row, columns: m.columns.clone(), current: *current }),
+                MatchLocation::Input | MatchLocation::Annotation(_) => None,
+            })
+            .collect();
+
+        let annotation = self
+            .annotation
+            .as_deref()
+            .map(|annotation| self.create_annotation(annotation, matches, &theme.colors));
+
+        let body = if self.collapsed {
+            let mut body = column![header].push_maybe(annotation).spacing(8);
+            if let BlockContent::Command { input, .. } = &self.content {
+                body = body.push(Self::create_command_line(input, &input_matches, &theme.colors));
+            }
+            // Running commands stay reachable while their output is hidden
+            if self.process.is_some() {
+                body = body.push(self.create_process_controls());
+            }
+            body
+        } else {
+            column![header]
+                .push_maybe(annotation)
+                .push(self.create_content(theme, preferences, &input_matches, &output_matches, visible))
+                .push(self.create_actions())
                 .spacing(8)
-        )
+        };
+
+        let card = container(body)
         .padding(12)
-        .style(|theme| {
+        .style(move |theme| {
+            let palette = theme.palette();
             container::Appearance {
-                background: Some(theme.palette().background.into()),
+                background: Some(if selected {
+                    palette.primary.scale_alpha(0.1).into()
+                } else {
+                    palette.background.into()
+                }),
                 border: iced::Border {
-                    color: theme.palette().text,
-                    width: 1.0,
+                    color: if focused || selected { palette.primary } else { palette.text },
+                    width: if focused { 2.0 } else { 1.0 },
                     radius: 8.0.into(),
                 },
                 ..Default::default()
             }
-        })
-        .into()
+        });
+
+        // Clicks that none of the buttons inside handle pick the block
+        mouse_area(card)
+            .on_press(Message::BlockAction(self.id, BlockMessage::Select))
+            .into()
     }
 
     fn create_header(&self) -> Element<Message> {
-        let timestamp_str = self.timestamp.format("%H:%M:%S").to_string();
-        let status_indicator = match self.exit_code {
-            Some(0) => "✓",
-            Some(_) => "✗",
-            None => "⏳",
+        let timestamp_str = match self.finished_at {
+            // Commands can run past midnight, so the end shows its date when it differs
+            Some(finished_at) if finished_at.date_naive() != self.timestamp.date_naive() => format!(
+                "{} → {}",
+                self.timestamp.format("%H:%M:%S"),
+                finished_at.format("%b %d %H:%M:%S")
+            ),
+            Some(finished_at) => format!(
+                "{} → {}",
+                self.timestamp.format("%H:%M:%S"),
+                finished_at.format("%H:%M:%S")
+            ),
+            None => self.timestamp.format("%H:%M:%S").to_string(),
+        };
+        let (status_indicator, status_label) = match (&self.content, self.status()) {
+            // Only commands have a status to report
+            (BlockContent::Command { .. }, status) => match status {
+                BlockStatus::Succeeded => ("✓", String::new()),
+                BlockStatus::Failed(code) => ("✗", format!("exit {}", code)),
+                BlockStatus::Running => ("⏳", String::new()),
+                BlockStatus::Stopped => ("⏸", "Stopped".to_string()),
+                BlockStatus::Interrupted => ("⏹", "Interrupted".to_string()),
+                BlockStatus::Killed => ("☠", "Killed".to_string()),
+            },
+            _ => ("", String::new()),
         };
 
-        row![
+        let mut header = row![
+            button(text(if self.collapsed { "▸" } else { "▾" }).size(12))
+                .on_press(Message::BlockAction(self.id, BlockMessage::ToggleCollapsed))
+                .style(button::text)
+                .padding(0),
             text(status_indicator).size(16),
+            text(status_label).size(12),
             text(timestamp_str).size(12),
         ]
-        .spacing(8)
-        .into()
+        .spacing(8);
+
+        if matches!(self.content, BlockContent::Command { .. }) {
+            // Running commands count up every second until they finish
+            let mut details = vec![format_duration(self.elapsed())];
+            if let Some(usage) = self.usage {
+                let cpu_time = chrono::Duration::from_std(usage.cpu_time).unwrap_or_default();
+                details.push(format!("CPU {}", format_duration(cpu_time)));
+                if let Some(peak_rss_kb) = usage.peak_rss_kb {
+                    details.push(format!("{} peak", format_memory(peak_rss_kb)));
+                }
+            }
+
+            header = header.push(
+                text(details.join(" · "))
+                    .size(12)
+                    .style(|theme| text::Appearance {
+                        color: Some(theme.palette().text.scale_alpha(0.7)),
+                    })
+            );
+        }
+
+        if self.rerun_of.is_some() {
+            header = header.push(
+                text("↻ rerun")
+                    .size(12)
+                    .style(|theme| text::Appearance {
+                        color: Some(theme.palette().text.scale_alpha(0.7)),
+                    })
+            );
+        }
+
+        let toggle = |label, active: bool, message| {
+            button(text(label).size(12))
+                .on_press(Message::BlockAction(self.id, message))
+                .style(if active { button::secondary } else { button::text })
+                .padding(2)
+        };
+        header
+            .push(Space::with_width(iced::Length::Fill))
+            .push(toggle("📌", self.pinned, BlockMessage::TogglePinned))
+            .push(toggle("🔖", self.bookmarked, BlockMessage::ToggleBookmark))
+            .push(toggle("📝", self.annotation.is_some(), BlockMessage::EditAnnotation))
+            .into()
     }
 
-    fn create_content(&self) -> Element<Message> {
+    fn create_content(
+        &self,
+        theme: &ThemeConfig,
+        preferences: &TerminalPreferences,
+        input_matches: &[(Range<usize>, bool)],
+        output_matches: &[Highlight],
+        visible: Range<f32>,
+    ) -> Element<Message> {
+        // Rows of output are placed from the first of them
+        let offset = self.output_offset();
+        let visible = visible.start - offset..visible.end - offset;
         match &self.content {
-            BlockContent::Command { input, output, working_dir } => {
+            BlockContent::Command { input, terminal, working_dir, .. } => {
                 column![
-                    text(format!("$ {}", input))
-                        .size(14)
-                        .style(|theme| text::Appearance {
-                            color: Some(theme.palette().primary),
-                        }),
-                    if !output.is_empty() {
-                        text(output)
-                            .size(12)
-                            .style(|theme| text::Appearance {
-                                color: Some(theme.palette().text),
-                            })
+                    Self::create_command_line(input, input_matches, &theme.colors),
+                    if !terminal.is_empty() {
+                        self.create_output(terminal, theme, preferences, output_matches, working_dir, visible)
                     } else {
-                        text("Running...")
+                        // Commands such as `cd` or `true` finish without printing anything
+                        text(match self.status() {
+                            BlockStatus::Running => "Running...",
+                            BlockStatus::Stopped => "Stopped",
+                            _ => "No output",
+                        })
                             .size(12)
                             .style(|theme| text::Appearance {
                                 color: Some(theme.palette().text.scale_alpha(0.7)),
                             })
+                            .into()
                     }
                 ]
                 .spacing(4)
                 .into()
             }
-            BlockContent::Markdown(content) => {
-                // Implement markdown rendering
-                text(content).size(14).into()
-            }
-            BlockContent::FilePreview { path, content, file_type } => {
+            BlockContent::Markdown(content) => preview::markdown_view(content, theme),
+            BlockContent::FilePreview { path, content, file_type, size, preview } => {
+                let mut description = format!("📁 {} · {}", path.display(), format_bytes(*size));
+                if *file_type == FileType::Binary && *size > preview::HEX_DUMP_BYTES as u64 {
+                    description.push_str(&format!(" · first {}", format_bytes(preview::HEX_DUMP_BYTES as u64)));
+                } else if *size > preview::MAX_PREVIEW_BYTES {
+                    description.push_str(&format!(" · first {}", format_bytes(preview::MAX_PREVIEW_BYTES)));
+                }
+
+                let body = match preview {
+                    Preview::Markdown => preview::markdown_view(content, theme),
+                    Preview::Image => preview::image_view(path),
+                    Preview::Lines(terminal) => {
+                        let dir = path.parent().unwrap_or(path);
+                        self.create_output(terminal, theme, preferences, output_matches, dir, visible)
+                    }
+                    Preview::Tree(tree) => preview::tree_view(self.id, tree, &theme.colors),
+                };
+
                 column![
-                    text(format!("📁 {}", path.display()))
+                    text(description)
                         .size(12)
                         .style(|theme| text::Appearance {
                             color: Some(theme.palette().text.scale_alpha(0.8)),
                         }),
-                    text(content).size(12)
+                    body,
                 ]
                 .spacing(4)
                 .into()
             }
-            BlockContent::Error { message, details } => {
+            BlockContent::Error { message, details, suggestions } => {
                 let mut elements = vec![
                     text(format!("❌ {}", message))
                         .size(14)
@@ -190,31 +619,320 @@ impl Block {
                     );
                 }
 
+                if !suggestions.is_empty() {
+                    elements.push(self.create_suggestions(suggestions));
+                }
+
                 column(elements).spacing(4).into()
             }
         }
     }
 
+    /// An error's suggestions: those with a command put it in the input when clicked, the rest are hints
+    fn create_suggestions<'a>(&self, suggestions: &'a [Suggestion]) -> Element<'a, Message> {
+        let suggestions = suggestions.iter().map(|suggestion| match &suggestion.command {
+            Some(command) => button(text(&suggestion.label).size(12))
+                .on_press(Message::BlockAction(self.id, BlockMessage::UseSuggestion(command.clone())))
+                .style(button::secondary)
+                .into(),
+            None => text(format!("💡 {}", suggestion.label)).size(12).into(),
+        });
+
+        row(suggestions).spacing(8).align_items(iced::Alignment::Center).into()
+    }
+
+    /// The command with the find bar's matches in it marked
+    fn create_command_line<'a>(
+        input: &'a str,
+        matches: &[(Range<usize>, bool)],
+        colors: &ColorScheme,
+    ) -> Element<'a, Message> {
+        Self::create_marked_line("$ ", input, matches, colors, 14, |theme| text::Appearance {
+            color: Some(theme.palette().primary),
+        })
+    }
+
+    /// A line of text after `prefix`, with the characters in `matches` on a highlight
+    fn create_marked_line<'a>(
+        prefix: &str,
+        line: &'a str,
+        matches: &[(Range<usize>, bool)],
+        colors: &ColorScheme,
+        size: u16,
+        style: fn(&iced::Theme) -> text::Appearance,
+    ) -> Element<'a, Message> {
+        let span = |content: String| text(content).size(size).style(style);
+        if matches.is_empty() {
+            return span(format!("{}{}", prefix, line)).into();
+        }
+
+        let chars: Vec<char> = line.chars().collect();
+        let mut spans: Vec<Element<'a, Message>> = vec![span(prefix.to_string()).into()];
+        let mut col = 0;
+        for (columns, current) in matches {
+            if columns.start > col {
+                spans.push(span(chars[col..columns.start].iter().collect()).into());
+            }
+            let background: iced::Color = if *current {
+                colors.warning.clone().into()
+            } else {
+                colors.terminal_selection.clone().into()
+            };
+            spans.push(
+                container(span(chars[columns.clone()].iter().collect()))
+                    .style(move |_theme| container::Appearance {
+                        background: Some(background.into()),
+                        ..Default::default()
+                    })
+                    .into()
+            );
+            col = columns.end;
+        }
+        if col < chars.len() {
+            spans.push(span(chars[col..].iter().collect()).into());
+        }
+
+        row(spans).into()
+    }
+
+    /// The block's note: an input while it is being written, otherwise its lines, which start editing when clicked
+    fn create_annotation<'a>(
+        &'a self,
+        annotation: &'a str,
+        matches: &[(&Match, bool)],
+        colors: &ColorScheme,
+    ) -> Element<'a, Message> {
+        let id = self.id;
+        if self.editing_annotation {
+            return row![
+                text_input("Add a note...", annotation)
+                    .id(self.annotation_input_id())
+                    .on_input(move |note| Message::BlockAction(id, BlockMessage::AnnotationChanged(note)))
+                    .on_submit(Message::BlockAction(id, BlockMessage::SaveAnnotation))
+                    .size(12),
+                button(text("Done").size(12)).on_press(Message::BlockAction(id, BlockMessage::SaveAnnotation)),
+            ]
+            .spacing(8)
+            .align_items(iced::Alignment::Center)
+            .into();
+        }
+
+        let lines = annotation.lines().enumerate().map(|(index, line)| {
+            let line_matches: Vec<(Range<usize>, bool)> = matches
+                .iter()
+                .filter(|(m, _)| m.location == MatchLocation::Annotation(index))
+                .map(|(m, current)| (m.columns.clone(), *current))
+                .collect();
+            let prefix = if index == 0 { "📝 " } else { "   " };
+            Self::create_marked_line(prefix, line, &line_matches, colors, 12, |theme| text::Appearance {
+                color: Some(theme.palette().text.scale_alpha(0.8)),
+            })
+        });
+
+        button(column(lines).spacing(2))
+            .on_press(Message::BlockAction(id, BlockMessage::EditAnnotation))
+            .style(button::text)
+            .padding(0)
+            .into()
+    }
+
+    /// The output, cut down to its head and tail while it is longer than the collapse threshold;
+    /// of output shown in full, only the rows in `visible` are drawn, with space left for the rest
+    fn create_output<'a>(
+        &'a self,
+        terminal: &'a Terminal,
+        theme: &ThemeConfig,
+        preferences: &TerminalPreferences,
+        matches: &[Highlight],
+        working_dir: &Path,
+        visible: Range<f32>,
+    ) -> Element<'a, Message> {
+        let line_count = terminal.line_count();
+        let colors = &theme.colors;
+        let view = |rows: Range<usize>| {
+            let lines: Vec<&'a terminal::Row> = terminal.lines().skip(rows.start).take(rows.len()).collect();
+            let links = if matches!(preferences.hyperlink_behavior, HyperlinkBehavior::Disabled) {
+                Vec::new()
+            } else {
+                self.link_spans(terminal, rows.start, &lines, working_dir, preferences.url_detection)
+            };
+            terminal::view::view(
+                lines.into_iter().enumerate().map(move |(index, line)| (rows.start + index, line)),
+                colors,
+                OUTPUT_FONT_SIZE,
+                matches,
+                &links,
+            )
+        };
+        let virtualized = || -> Element<'a, Message> {
+            let shown = rows_in(&visible, line_count);
+            column![
+                Space::with_height(shown.start as f32 * ROW_HEIGHT),
+                view(shown.clone()),
+                Space::with_height((line_count - shown.end) as f32 * ROW_HEIGHT),
+            ]
+            .into()
+        };
+        let context = preferences.collapsed_context_lines;
+        if !Self::cuts_output(line_count, preferences) {
+            return virtualized();
+        }
+
+        let toggle = |label: String| {
+            button(text(label).size(12))
+                .on_press(Message::BlockAction(self.id, BlockMessage::ToggleFullOutput))
+                .style(button::text)
+                .padding(0)
+        };
+
+        let hidden = line_count - 2 * context;
+        if self.show_full_output {
+            column![
+                virtualized(),
+                toggle(format!("Hide {} lines", hidden)),
+            ]
+            .spacing(4)
+            .into()
+        } else {
+            column![
+                view(0..context),
+                toggle(format!("Show {} hidden lines", hidden)),
+                view(line_count - context..line_count),
+            ]
+            .spacing(4)
+            .into()
+        }
+    }
+
+    /// Whether output this many lines long is over the collapse threshold
+    fn cuts_output(line_count: usize, preferences: &TerminalPreferences) -> bool {
+        preferences
+            .collapse_output_after
+            .map_or(false, |max_lines| line_count > max_lines.max(2 * preferences.collapsed_context_lines))
+    }
+
+    /// Height of the output as [`Block::create_output`] draws it
+    fn output_height(&self, terminal: &Terminal, preferences: &TerminalPreferences) -> f32 {
+        let line_count = terminal.line_count();
+        if !Self::cuts_output(line_count, preferences) {
+            line_count as f32 * ROW_HEIGHT
+        } else if self.show_full_output {
+            line_count as f32 * ROW_HEIGHT + 4.0 + SMALL_LINE_HEIGHT
+        } else {
+            (2 * preferences.collapsed_context_lines) as f32 * ROW_HEIGHT + 8.0 + SMALL_LINE_HEIGHT
+        }
+    }
+
+    /// Roughly how tall the card is drawn, so that space can stand in for it while it is out of view
+    pub fn estimated_height(&self, preferences: &TerminalPreferences) -> f32 {
+        let chrome = 2.0 * CARD_PADDING + HEADER_HEIGHT + self.annotation_height();
+        if self.collapsed {
+            let command = match self.content {
+                BlockContent::Command { .. } => SECTION_SPACING + COMMAND_LINE_HEIGHT,
+                _ => 0.0,
+            };
+            let controls = if self.process.is_some() { SECTION_SPACING + ACTIONS_HEIGHT } else { 0.0 };
+            return chrome + command + controls;
+        }
+
+        let content = match &self.content {
+            BlockContent::Command { terminal, .. } if terminal.is_empty() => COMMAND_LINE_HEIGHT + 4.0 + SMALL_LINE_HEIGHT,
+            BlockContent::Command { terminal, .. } => COMMAND_LINE_HEIGHT + 4.0 + self.output_height(terminal, preferences),
+            BlockContent::Markdown(content) => content.lines().count() as f32 * COMMAND_LINE_HEIGHT,
+            BlockContent::FilePreview { content, preview, .. } => {
+                SMALL_LINE_HEIGHT + 4.0 + match preview {
+                    Preview::Lines(terminal) => self.output_height(terminal, preferences),
+                    Preview::Image => IMAGE_HEIGHT,
+                    Preview::Tree(tree) => tree.lines().len() as f32 * ROW_HEIGHT,
+                    Preview::Markdown => content.lines().count() as f32 * COMMAND_LINE_HEIGHT,
+                }
+            }
+            BlockContent::Error { details, suggestions, .. } => {
+                let details = details.as_ref().map_or(0.0, |details| 4.0 + details.lines().count() as f32 * SMALL_LINE_HEIGHT);
+                let suggestions = if suggestions.is_empty() { 0.0 } else { 4.0 + ACTIONS_HEIGHT };
+                COMMAND_LINE_HEIGHT + details + suggestions
+            }
+        };
+        chrome + SECTION_SPACING + content + SECTION_SPACING + ACTIONS_HEIGHT
+    }
+
+    /// Roughly how far the first row of output is from the top of the card
+    pub fn output_offset(&self) -> f32 {
+        let above = match self.content {
+            BlockContent::Command { .. } => COMMAND_LINE_HEIGHT + 4.0,
+            BlockContent::FilePreview { .. } => SMALL_LINE_HEIGHT + 4.0,
+            _ => 0.0,
+        };
+        CARD_PADDING + HEADER_HEIGHT + self.annotation_height() + SECTION_SPACING + above
+    }
+
+    fn annotation_height(&self) -> f32 {
+        self.annotation.as_deref().map_or(0.0, |annotation| {
+            SECTION_SPACING + annotation.lines().count().max(1) as f32 * (SMALL_LINE_HEIGHT + 2.0)
+        })
+    }
+
+    /// The clickable links in rows of output numbered from `start`
+    fn link_spans(
+        &self,
+        terminal: &Terminal,
+        start: usize,
+        rows: &[&terminal::Row],
+        working_dir: &Path,
+        detect: bool,
+    ) -> Vec<LinkSpan<Message>> {
+        rows.iter()
+            .enumerate()
+            .flat_map(|(index, row)| {
+                links::row_links(row, terminal, working_dir, detect)
+                    .into_iter()
+                    .map(move |link| LinkSpan {
+                        row: start + index,
+                        columns: link.columns,
+                        on_press: Message::BlockAction(self.id, BlockMessage::OpenLink(link.target)),
+                    })
+            })
+            .collect()
+    }
+
     fn create_actions(&self) -> Element<Message> {
+        let id = self.id;
         let mut actions = Vec::new();
 
         match &self.content {
+            BlockContent::Command { .. } if self.process.is_some() => {
+                return self.create_process_controls();
+            }
             BlockContent::Command { .. } => {
                 actions.push(
                     button("Rerun")
                         .on_press(Message::BlockAction(self.id, BlockMessage::Rerun))
                         .into()
                 );
+                actions.push(
+                    pick_list(&CopyTarget::ALL[..], None::<CopyTarget>, move |target| {
+                        Message::BlockAction(id, BlockMessage::Copy(target))
+                    })
+                    .placeholder("Copy")
+                    .into()
+                );
+                actions.push(
+                    pick_list(&ExportFormat::ALL[..], None::<ExportFormat>, move |format| {
+                        Message::BlockAction(id, BlockMessage::Export(format))
+                    })
+                    .placeholder("Export")
+                    .into()
+                );
+            }
+            _ => {
+                actions.push(
+                    button("Copy")
+                        .on_press(Message::BlockAction(self.id, BlockMessage::Copy(CopyTarget::Output)))
+                        .into()
+                );
             }
-            _ => {}
         }
 
-        actions.push(
-            button("Copy")
-                .on_press(Message::BlockAction(self.id, BlockMessage::Copy))
-                .into()
-        );
-
         actions.push(
             button("Delete")
                 .on_press(Message::BlockAction(self.id, BlockMessage::Delete))
@@ -224,16 +942,153 @@ impl Block {
         row(actions).spacing(8).into()
     }
 
-    fn detect_file_type(path: &PathBuf) -> FileType {
-        match path.extension().and_then(|ext| ext.to_str()) {
+    /// Stdin and signal controls shown while the command runs
+    fn create_process_controls(&self) -> Element<Message> {
+        let id = self.id;
+        let signal_button = |label, signal| {
+            button(label).on_press(Message::BlockAction(id, BlockMessage::Signal(signal)))
+        };
+        let stop_or_continue = if self.status() == BlockStatus::Stopped {
+            signal_button("Resume", Signal::Continue)
+        } else {
+            signal_button("Ctrl-Z", Signal::Stop)
+        };
+
+        row![
+            text_input("Send input...", &self.stdin)
+                .on_input(move |input| Message::BlockAction(id, BlockMessage::InputChanged(input)))
+                .on_submit(Message::BlockAction(id, BlockMessage::SubmitInput))
+                .size(12),
+            signal_button("Ctrl-C", Signal::Interrupt),
+            stop_or_continue,
+            signal_button("Terminate", Signal::Terminate),
+            signal_button("Kill", Signal::Kill),
+        ]
+        .spacing(8)
+        .into()
+    }
+
+    /// What kind of file this is, from its extension or else from whether its first bytes are text
+    fn detect_file_type(path: &Path, bytes: &[u8]) -> FileType {
+        let extension = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(str::to_lowercase);
+
+        match extension.as_deref() {
             Some("json") => FileType::Json,
             Some("yaml") | Some("yml") => FileType::Yaml,
-            Some("rs") => FileType::Code("rust".to_string()),
-            Some("js") | Some("ts") => FileType::Code("javascript".to_string()),
-            Some("py") => FileType::Code("python".to_string()),
-            Some("png") | Some("jpg") | Some("jpeg") | Some("gif") => FileType::Image,
-            Some("txt") | Some("md") => FileType::Text,
-            _ => FileType::Binary,
+            Some("md") | Some("markdown") => FileType::Markdown,
+            Some("png") | Some("jpg") | Some("jpeg") | Some("gif") | Some("bmp") | Some("webp") | Some("ico") => {
+                FileType::Image
+            }
+            _ if is_binary(bytes) => FileType::Binary,
+            Some(ext) if renderer::syntax_highlighter().has_syntax(ext) => FileType::Code(ext.to_string()),
+            _ => FileType::Text,
         }
     }
-}
\ No newline at end of file
+}
+
+/// A short human readable duration, e.g. `850ms`, `4.2s`, `3m 05s` or `2h 10m`
+pub fn format_duration(duration: chrono::Duration) -> String {
+    let millis = duration.num_milliseconds().max(0);
+    match millis {
+        0..=999 => format!("{}ms", millis),
+        1_000..=59_999 => format!("{:.1}s", millis as f64 / 1000.0),
+        60_000..=3_599_999 => format!("{}m {:02}s", millis / 60_000, millis / 1000 % 60),
+        _ => format!("{}h {:02}m", millis / 3_600_000, millis / 60_000 % 60),
+    }
+}
+
+fn format_memory(kilobytes: u64) -> String {
+    format_bytes(kilobytes * 1024)
+}
+
+pub(crate) fn format_bytes(bytes: u64) -> String {
+    match bytes {
+        0..=1023 => format!("{} B", bytes),
+        1024..=1_048_575 => format!("{:.1} KB", bytes as f64 / 1024.0),
+        1_048_576..=1_073_741_823 => format!("{:.1} MB", bytes as f64 / 1_048_576.0),
+        _ => format!("{:.1} GB", bytes as f64 / 1_073_741_824.0),
+    }
+}
+
+/// Text has no NUL bytes and is valid UTF-8, apart from a character cut off at the end
+fn is_binary(bytes: &[u8]) -> bool {
+    bytes.contains(&0)
+        || std::str::from_utf8(bytes).map_or_else(|e| e.error_len().is_some(), |_| false)
+}
+
+/// The rows, out of `count`, that fall in a span of pixels measured from the first row
+fn rows_in(span: &Range<f32>, count: usize) -> Range<usize> {
+    let start = ((span.start / ROW_HEIGHT).floor().max(0.0) as usize).min(count);
+    let end = ((span.end / ROW_HEIGHT).ceil().max(0.0) as usize).clamp(start, count);
+    start..end
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn command() -> Block {
+        Block::new_command("sleep 30".to_string(), PathBuf::from("/"), 80, 24, 100)
+    }
+
+    fn finished(exit_code: i32, signal: Option<Signal>) -> BlockStatus {
+        let mut block = command();
+        block.last_signal = signal;
+        block.finish(exit_code, None);
+        block.status()
+    }
+
+    #[test]
+    fn test_status_tells_signals_apart_from_failures() {
+        let mut running = command();
+        assert_eq!(running.status(), BlockStatus::Running);
+        running.last_signal = Some(Signal::Stop);
+        assert_eq!(running.status(), BlockStatus::Stopped);
+
+        assert_eq!(finished(130, None), BlockStatus::Interrupted);
+        assert_eq!(finished(1, Some(Signal::Interrupt)), BlockStatus::Interrupted);
+        // Caught the interrupt and exited cleanly
+        assert_eq!(finished(0, Some(Signal::Interrupt)), BlockStatus::Succeeded);
+        assert_eq!(finished(137, None), BlockStatus::Killed);
+        assert_eq!(finished(1, Some(Signal::Terminate)), BlockStatus::Killed);
+        assert_eq!(finished(2, None), BlockStatus::Failed(2));
+        // Without its process a suspended job cannot be resumed, so it is reported as it ended
+        assert_eq!(finished(148, Some(Signal::Stop)), BlockStatus::Failed(148));
+    }
+
+    #[test]
+    fn test_durations_and_sizes_are_short() {
+        let millis = chrono::Duration::milliseconds;
+        assert_eq!(format_duration(millis(-5)), "0ms");
+        assert_eq!(format_duration(millis(850)), "850ms");
+        assert_eq!(format_duration(millis(4_200)), "4.2s");
+        assert_eq!(format_duration(millis(60_000)), "1m 00s");
+        assert_eq!(format_duration(millis(185_000)), "3m 05s");
+        assert_eq!(format_duration(millis(7_800_000)), "2h 10m");
+
+        assert_eq!(format_bytes(1023), "1023 B");
+        assert_eq!(format_bytes(1536), "1.5 KB");
+        assert_eq!(format_memory(20 * 1024), "20.0 MB");
+        assert_eq!(format_memory(3 * 1024 * 1024), "3.0 GB");
+    }
+
+    #[test]
+    fn test_timing_and_usage_survive_a_restart() {
+        let mut block = command();
+        let usage = ResourceUsage {
+            cpu_time: Duration::from_millis(1500),
+            peak_rss_kb: Some(2048),
+        };
+        block.finish(0, Some(usage));
+        assert!(block.duration().is_some_and(|duration| duration >= chrono::Duration::zero()));
+        assert_eq!(block.elapsed(), block.duration().unwrap());
+
+        let restored = Block::restore(block.save(100).unwrap(), 80, 24, 100);
+        assert_eq!(restored.usage, Some(usage));
+        assert_eq!(restored.timestamp, block.timestamp);
+        assert_eq!(restored.duration(), block.duration());
+    }
+}
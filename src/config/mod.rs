@@ -5,12 +5,14 @@ use iced::Color;
 
 pub mod theme;
 pub mod preferences;
+pub mod profile;
 pub mod storage;
 pub mod yaml_theme;
 pub mod yaml_theme_manager;
 
 pub use theme::*;
 pub use preferences::*;
+pub use profile::*;
 pub use storage::*;
 pub use yaml_theme::*;
 pub use yaml_theme_manager::*;
@@ -21,6 +23,8 @@ pub struct AppConfig {
     pub preferences: UserPreferences,
     pub keybindings: KeyBindings,
     pub plugins: PluginConfig,
+    #[serde(default)]
+    pub profiles: Vec<ShellProfile>,
     
     // YAML theme settings
     pub yaml_themes_enabled: bool,
@@ -34,6 +38,7 @@ impl Default for AppConfig {
             preferences: UserPreferences::default(),
             keybindings: KeyBindings::default(),
             plugins: PluginConfig::default(),
+            profiles: Vec::new(),
             yaml_themes_enabled: true,
             active_yaml_theme: None,
         }
@@ -87,6 +92,10 @@ impl AppConfig {
         Ok(())
     }
 
+    pub fn profile(&self, name: &str) -> Option<&ShellProfile> {
+        self.profiles.iter().find(|profile| profile.name == name)
+    }
+
     pub fn config_path() -> Result<PathBuf, ConfigError> {
         let config_dir = dirs::config_dir()
             .ok_or(ConfigError::ConfigDirNotFound)?
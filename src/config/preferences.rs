@@ -1,5 +1,7 @@
+use iced::keyboard::{Key, Modifiers};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::fmt;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct UserPreferences {
@@ -15,6 +17,15 @@ pub struct UserPreferences {
 pub struct GeneralPreferences {
     pub startup_behavior: StartupBehavior,
     pub default_shell: Option<String>,
+    /// Extra arguments for the shell
+    #[serde(default)]
+    pub shell_args: Vec<String>,
+    /// Start shells as login shells, so they read the login profile
+    #[serde(default)]
+    pub login_shell: bool,
+    /// Variables set in every new session
+    #[serde(default)]
+    pub environment: HashMap<String, String>,
     pub working_directory: WorkingDirectoryBehavior,
     pub auto_update: bool,
     pub telemetry_enabled: bool,
@@ -49,6 +60,12 @@ pub struct TerminalPreferences {
     pub word_separators: String,
     pub url_detection: bool,
     pub hyperlink_behavior: HyperlinkBehavior,
+    /// Output longer than this many lines is shown as its head and tail until expanded
+    #[serde(default = "default_collapse_output_after")]
+    pub collapse_output_after: Option<usize>,
+    /// Lines shown before and after the hidden part of collapsed output
+    #[serde(default = "default_collapsed_context_lines")]
+    pub collapsed_context_lines: usize,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -118,6 +135,9 @@ pub struct PerformancePreferences {
     pub background_throttling: bool,
     pub lazy_rendering: bool,
     pub texture_atlas_size: u32,
+    /// Seconds of CPU profile a flamegraph saved from the performance overlay covers
+    #[serde(default = "default_profile_window")]
+    pub profile_window: u64,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -152,6 +172,34 @@ pub struct KeyBinding {
     pub when: Option<String>, // Context condition
 }
 
+impl KeyBindings {
+    /// The action bound to a key press, if any
+    pub fn action_for(&self, key: &Key, modifiers: Modifiers) -> Option<&Action> {
+        self.bindings
+            .values()
+            .find(|binding| binding.matches(key, modifiers))
+            .map(|binding| &binding.action)
+    }
+}
+
+impl KeyBinding {
+    /// Whether a key press triggers this binding; the modifiers must match exactly
+    pub fn matches(&self, key: &Key, modifiers: Modifiers) -> bool {
+        let name = match key.as_ref() {
+            Key::Character(",") => "comma".to_string(),
+            Key::Character(ch) => ch.to_lowercase(),
+            Key::Named(named) => format!("{:?}", named),
+            Key::Unidentified => return false,
+        };
+
+        name.eq_ignore_ascii_case(&self.key)
+            && modifiers.control() == self.modifiers.iter().any(|m| matches!(m, Modifier::Ctrl))
+            && modifiers.alt() == self.modifiers.iter().any(|m| matches!(m, Modifier::Alt))
+            && modifiers.shift() == self.modifiers.iter().any(|m| matches!(m, Modifier::Shift))
+            && modifiers.logo() == self.modifiers.iter().any(|m| matches!(m, Modifier::Super))
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Modifier {
     Ctrl,
@@ -164,21 +212,29 @@ pub enum Modifier {
 pub enum Action {
     // Terminal actions
     NewTab,
+    /// Open a tab using the shell profile with this name
+    NewTabWithProfile(String),
     CloseTab,
     NextTab,
     PreviousTab,
     SplitHorizontal,
     SplitVertical,
     CloseSplit,
+    /// Collapse or expand the latest block of the focused pane
+    ToggleBlockCollapsed,
+    CollapseAllBlocks,
+    ExpandAllBlocks,
     
     // Edit actions
     Copy,
     Paste,
     Cut,
     SelectAll,
+    /// Open the focused pane's find bar
     Find,
     FindNext,
     FindPrevious,
+    CloseFind,
     
     // Navigation
     ScrollUp,
@@ -186,6 +242,39 @@ pub enum Action {
     ScrollToTop,
     ScrollToBottom,
     
+    // Blocks
+    FocusPreviousBlock,
+    FocusNextBlock,
+    /// Move the block cursor, extending the selection to the block it lands on
+    SelectPreviousBlock,
+    SelectNextBlock,
+    /// Add or remove the focused block from the selection
+    ToggleBlockSelection,
+    ClearBlockSelection,
+    CopySelectedBlocks(CopyTarget),
+    ExportSelectedBlocks(ExportFormat),
+    /// Run the selected commands again, one after another
+    RerunSelectedBlocks,
+    /// Put the selected blocks on the clipboard as Markdown, ready to paste elsewhere
+    ShareSelectedBlocks,
+    DeleteSelectedBlocks,
+    /// Keep the focused block above the others, or stop doing so
+    TogglePinBlock,
+    ToggleBookmarkBlock,
+    /// Write or change the focused block's note
+    AnnotateBlock,
+    /// Show or hide the focused pane's list of bookmarks
+    ToggleBookmarks,
+    /// Jump to the next bookmarked block after the block cursor, wrapping around
+    NextBookmark,
+    PreviousBookmark,
+    
+    // Diagnostics
+    /// Show or hide the overlay with frame times, memory and throughput
+    TogglePerformanceOverlay,
+    /// Save a flamegraph of the last seconds of CPU profile, while the overlay is shown
+    SaveFlamegraph,
+    
     // Application
     ToggleFullscreen,
     ToggleSettings,
@@ -195,6 +284,68 @@ pub enum Action {
     Command(String),
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ExportFormat {
+    /// Each command and its output as fenced code
+    Markdown,
+    /// A self-contained page keeping the output's colors
+    Html,
+    Json,
+    /// An asciinema v2 recording
+    Asciicast,
+}
+
+impl ExportFormat {
+    pub const ALL: [ExportFormat; 4] = [
+        ExportFormat::Markdown,
+        ExportFormat::Html,
+        ExportFormat::Json,
+        ExportFormat::Asciicast,
+    ];
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            ExportFormat::Markdown => "md",
+            ExportFormat::Html => "html",
+            ExportFormat::Json => "json",
+            ExportFormat::Asciicast => "cast",
+        }
+    }
+}
+
+impl fmt::Display for ExportFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ExportFormat::Markdown => "Markdown",
+            ExportFormat::Html => "HTML",
+            ExportFormat::Json => "JSON",
+            ExportFormat::Asciicast => "asciicast",
+        })
+    }
+}
+
+/// Which part of a block to put on the clipboard
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CopyTarget {
+    Command,
+    Output,
+    Both,
+}
+
+impl CopyTarget {
+    pub const ALL: [CopyTarget; 3] = [CopyTarget::Command, CopyTarget::Output, CopyTarget::Both];
+}
+
+impl fmt::Display for CopyTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            CopyTarget::Command => "Command",
+            CopyTarget::Output => "Output",
+            CopyTarget::Both => "Command and output",
+        })
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PluginConfig {
     pub enabled_plugins: Vec<String>,
@@ -221,6 +372,9 @@ impl Default for GeneralPreferences {
         Self {
             startup_behavior: StartupBehavior::NewSession,
             default_shell: None,
+            shell_args: Vec::new(),
+            login_shell: false,
+            environment: HashMap::new(),
             working_directory: WorkingDirectoryBehavior::Home,
             auto_update: true,
             telemetry_enabled: false,
@@ -244,10 +398,20 @@ impl Default for TerminalPreferences {
             word_separators: " \t\n\"'`()[]{}".to_string(),
             url_detection: true,
             hyperlink_behavior: HyperlinkBehavior::CtrlClick,
+            collapse_output_after: default_collapse_output_after(),
+            collapsed_context_lines: default_collapsed_context_lines(),
         }
     }
 }
 
+fn default_collapse_output_after() -> Option<usize> {
+    Some(200)
+}
+
+fn default_collapsed_context_lines() -> usize {
+    10
+}
+
 impl Default for EditorPreferences {
     fn default() -> Self {
         Self {
@@ -293,10 +457,15 @@ impl Default for PerformancePreferences {
             background_throttling: true,
             lazy_rendering: true,
             texture_atlas_size: 1024,
+            profile_window: default_profile_window(),
         }
     }
 }
 
+fn default_profile_window() -> u64 {
+    30
+}
+
 impl Default for PrivacyPreferences {
     fn default() -> Self {
         Self {
@@ -343,6 +512,125 @@ impl Default for KeyBindings {
             when: None,
         });
         
+        bindings.insert("split_vertical".to_string(), KeyBinding {
+            key: "d".to_string(),
+            modifiers: vec![Modifier::Ctrl, Modifier::Shift],
+            action: Action::SplitVertical,
+            when: None,
+        });
+        
+        bindings.insert("split_horizontal".to_string(), KeyBinding {
+            key: "e".to_string(),
+            modifiers: vec![Modifier::Ctrl, Modifier::Shift],
+            action: Action::SplitHorizontal,
+            when: None,
+        });
+        
+        bindings.insert("close_split".to_string(), KeyBinding {
+            key: "w".to_string(),
+            modifiers: vec![Modifier::Ctrl, Modifier::Shift],
+            action: Action::CloseSplit,
+            when: None,
+        });
+        
+        bindings.insert("toggle_block_collapsed".to_string(), KeyBinding {
+            key: "o".to_string(),
+            modifiers: vec![Modifier::Ctrl, Modifier::Shift],
+            action: Action::ToggleBlockCollapsed,
+            when: None,
+        });
+        
+        bindings.insert("collapse_all_blocks".to_string(), KeyBinding {
+            key: "[".to_string(),
+            modifiers: vec![Modifier::Ctrl, Modifier::Alt],
+            action: Action::CollapseAllBlocks,
+            when: None,
+        });
+        
+        bindings.insert("expand_all_blocks".to_string(), KeyBinding {
+            key: "]".to_string(),
+            modifiers: vec![Modifier::Ctrl, Modifier::Alt],
+            action: Action::ExpandAllBlocks,
+            when: None,
+        });
+        
+        bindings.insert("focus_previous_block".to_string(), KeyBinding {
+            key: "k".to_string(),
+            modifiers: vec![Modifier::Ctrl],
+            action: Action::FocusPreviousBlock,
+            when: None,
+        });
+        
+        bindings.insert("focus_next_block".to_string(), KeyBinding {
+            key: "j".to_string(),
+            modifiers: vec![Modifier::Ctrl],
+            action: Action::FocusNextBlock,
+            when: None,
+        });
+        
+        bindings.insert("select_previous_block".to_string(), KeyBinding {
+            key: "k".to_string(),
+            modifiers: vec![Modifier::Ctrl, Modifier::Shift],
+            action: Action::SelectPreviousBlock,
+            when: None,
+        });
+        
+        bindings.insert("select_next_block".to_string(), KeyBinding {
+            key: "j".to_string(),
+            modifiers: vec![Modifier::Ctrl, Modifier::Shift],
+            action: Action::SelectNextBlock,
+            when: None,
+        });
+        
+        bindings.insert("toggle_block_selection".to_string(), KeyBinding {
+            key: "Space".to_string(),
+            modifiers: vec![Modifier::Ctrl],
+            action: Action::ToggleBlockSelection,
+            when: None,
+        });
+        
+        bindings.insert("toggle_block_pinned".to_string(), KeyBinding {
+            key: "p".to_string(),
+            modifiers: vec![Modifier::Ctrl, Modifier::Alt],
+            action: Action::TogglePinBlock,
+            when: None,
+        });
+        
+        bindings.insert("toggle_block_bookmark".to_string(), KeyBinding {
+            key: "b".to_string(),
+            modifiers: vec![Modifier::Ctrl, Modifier::Alt],
+            action: Action::ToggleBookmarkBlock,
+            when: None,
+        });
+        
+        bindings.insert("annotate_block".to_string(), KeyBinding {
+            key: "n".to_string(),
+            modifiers: vec![Modifier::Ctrl, Modifier::Alt],
+            action: Action::AnnotateBlock,
+            when: None,
+        });
+        
+        bindings.insert("toggle_bookmarks".to_string(), KeyBinding {
+            key: "b".to_string(),
+            modifiers: vec![Modifier::Ctrl, Modifier::Shift],
+            action: Action::ToggleBookmarks,
+            when: None,
+        });
+        
+        bindings.insert("next_bookmark".to_string(), KeyBinding {
+            key: "F2".to_string(),
+            modifiers: vec![],
+            action: Action::NextBookmark,
+            when: None,
+        });
+        
+        bindings.insert("previous_bookmark".to_string(), KeyBinding {
+            key: "F2".to_string(),
+            modifiers: vec![Modifier::Shift],
+            action: Action::PreviousBookmark,
+            when: None,
+        });
+        
         // Edit shortcuts
         bindings.insert("copy".to_string(), KeyBinding {
             key: "c".to_string(),
@@ -365,6 +653,42 @@ impl Default for KeyBindings {
             when: None,
         });
         
+        bindings.insert("find_next".to_string(), KeyBinding {
+            key: "F3".to_string(),
+            modifiers: vec![],
+            action: Action::FindNext,
+            when: None,
+        });
+        
+        bindings.insert("find_previous".to_string(), KeyBinding {
+            key: "F3".to_string(),
+            modifiers: vec![Modifier::Shift],
+            action: Action::FindPrevious,
+            when: None,
+        });
+        
+        bindings.insert("close_find".to_string(), KeyBinding {
+            key: "Escape".to_string(),
+            modifiers: vec![],
+            action: Action::CloseFind,
+            when: None,
+        });
+        
+        // Diagnostics shortcuts
+        bindings.insert("toggle_performance_overlay".to_string(), KeyBinding {
+            key: "F12".to_string(),
+            modifiers: vec![Modifier::Ctrl, Modifier::Shift],
+            action: Action::TogglePerformanceOverlay,
+            when: None,
+        });
+        
+        bindings.insert("save_flamegraph".to_string(), KeyBinding {
+            key: "F12".to_string(),
+            modifiers: vec![Modifier::Ctrl, Modifier::Shift, Modifier::Alt],
+            action: Action::SaveFlamegraph,
+            when: None,
+        });
+        
         // Application shortcuts
         bindings.insert("fullscreen".to_string(), KeyBinding {
             key: "F11".to_string(),
@@ -0,0 +1,30 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// A named set of shell settings that tabs can be opened with, e.g. "work-k8s" or "rust-dev"
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ShellProfile {
+    pub name: String,
+    /// Shell binary, or the default shell when unset
+    #[serde(default)]
+    pub shell: Option<String>,
+    /// Extra arguments for the shell, used instead of the general ones when
+    /// the profile sets a shell or any arguments
+    #[serde(default)]
+    pub args: Vec<String>,
+    /// Overrides whether the shell starts as a login shell
+    #[serde(default)]
+    pub login: Option<bool>,
+    /// Variables set on top of the general environment
+    #[serde(default)]
+    pub environment: HashMap<String, String>,
+    /// Directory sessions start in, overriding the working directory behavior; `~` is expanded
+    #[serde(default)]
+    pub working_directory: Option<String>,
+    /// YAML theme for this profile's panes
+    #[serde(default)]
+    pub theme: Option<String>,
+    /// Commands run in every new session of this profile
+    #[serde(default)]
+    pub startup_script: Option<String>,
+}
@@ -0,0 +1,284 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::PathBuf;
+
+use super::ConfigError;
+
+/// Parts of variable names that suggest a secret, which is never written to the session file
+const SECRET_NAME_PARTS: [&str; 8] = [
+    "TOKEN", "SECRET", "PASSWORD", "PASSWD", "CREDENTIAL", "API_KEY", "ACCESS_KEY", "PRIVATE_KEY",
+];
+/// Variables that only mean something to the processes running when the session was saved
+const VOLATILE_VARIABLES: [&str; 9] = [
+    "SSH_AUTH_SOCK",
+    "SSH_AGENT_PID",
+    "SSH_CONNECTION",
+    "SSH_CLIENT",
+    "GPG_AGENT_INFO",
+    "DBUS_SESSION_BUS_ADDRESS",
+    "DISPLAY",
+    "WAYLAND_DISPLAY",
+    "WINDOWID",
+];
+
+/// The tabs, panes and block history NeoTerm had open when it last exited
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SessionState {
+    pub tabs: Vec<TabState>,
+    pub active_tab: usize,
+    /// Where the last command finished, for `WorkingDirectoryBehavior::LastUsed`
+    #[serde(default)]
+    pub last_working_dir: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TabState {
+    pub layout: PaneLayout,
+    /// Index of the focused pane, counting panes in layout order
+    pub focus: usize,
+}
+
+/// How a tab is split into panes
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum PaneLayout {
+    Split {
+        axis: SplitAxis,
+        ratio: f32,
+        /// The left or top part
+        first: Box<PaneLayout>,
+        /// The right or bottom part
+        second: Box<PaneLayout>,
+    },
+    Pane(PaneState),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SplitAxis {
+    /// Panes stacked above each other
+    Horizontal,
+    /// Panes side by side
+    Vertical,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PaneState {
+    /// Name of the shell profile the pane was opened with
+    #[serde(default)]
+    pub profile: Option<String>,
+    pub working_dir: PathBuf,
+    /// Variables the session set or changed relative to NeoTerm's own
+    /// environment; `None` marks one it removed
+    pub environment: HashMap<String, Option<String>>,
+    pub blocks: Vec<BlockState>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BlockState {
+    pub input: String,
+    /// Output with SGR sequences for its colors, limited to the scrollback length
+    pub output: String,
+    pub working_dir: PathBuf,
+    /// `None` for a command that was still running at exit
+    pub exit_code: Option<i32>,
+    pub timestamp: DateTime<Utc>,
+    #[serde(default)]
+    pub finished_at: Option<DateTime<Utc>>,
+    /// CPU time the command used, when it was measured
+    #[serde(default)]
+    pub cpu_time_ms: Option<u64>,
+    #[serde(default)]
+    pub peak_rss_kb: Option<u64>,
+    #[serde(default)]
+    pub collapsed: bool,
+    #[serde(default)]
+    pub pinned: bool,
+    #[serde(default)]
+    pub bookmarked: bool,
+    #[serde(default)]
+    pub annotation: Option<String>,
+}
+
+impl SessionState {
+    /// The saved session, or `None` if there is none
+    pub fn load() -> Result<Option<Self>, ConfigError> {
+        let path = Self::session_path()?;
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        let content = std::fs::read_to_string(&path)
+            .map_err(|e| ConfigError::IoError(e.to_string()))?;
+        let state = serde_json::from_str(&content)
+            .map_err(|e| ConfigError::ParseError(e.to_string()))?;
+
+        Ok(Some(state))
+    }
+
+    pub fn save(&self) -> Result<(), ConfigError> {
+        let path = Self::session_path()?;
+
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .map_err(|e| ConfigError::IoError(e.to_string()))?;
+        }
+
+        let content = serde_json::to_string(self)
+            .map_err(|e| ConfigError::SerializeError(e.to_string()))?;
+
+        // Block output can hold anything, so only the user may read the file. It is written next
+        // to the old one and renamed over it, so a crash while saving leaves the old one intact.
+        let dir = path.parent().ok_or(ConfigError::ConfigDirNotFound)?;
+        let mut file = tempfile::NamedTempFile::new_in(dir)
+            .map_err(|e| ConfigError::IoError(e.to_string()))?;
+        file.write_all(content.as_bytes())
+            .and_then(|()| file.as_file().sync_all())
+            .map_err(|e| ConfigError::IoError(e.to_string()))?;
+        file.persist(&path)
+            .map_err(|e| ConfigError::IoError(e.to_string()))?;
+
+        Ok(())
+    }
+
+    pub fn session_path() -> Result<PathBuf, ConfigError> {
+        let data_dir = dirs::data_dir()
+            .ok_or(ConfigError::ConfigDirNotFound)?
+            .join("neoterm");
+
+        Ok(data_dir.join("session.json"))
+    }
+}
+
+/// Environment entries that differ between `base` and `environment`, leaving out values that
+/// are secrets or only valid for the running session
+pub fn environment_delta(
+    base: &HashMap<String, String>,
+    environment: &HashMap<String, String>,
+) -> HashMap<String, Option<String>> {
+    let changed = environment
+        .iter()
+        .filter(|(key, value)| base.get(*key) != Some(value) && is_persistable(key))
+        .map(|(key, value)| (key.clone(), Some(value.clone())));
+    let removed = base
+        .keys()
+        .filter(|key| !environment.contains_key(*key))
+        .map(|key| (key.clone(), None));
+
+    changed.chain(removed).collect()
+}
+
+/// Whether a variable's value may be written to the session file
+fn is_persistable(name: &str) -> bool {
+    let name = name.to_ascii_uppercase();
+    !VOLATILE_VARIABLES.contains(&name.as_str())
+        && !SECRET_NAME_PARTS.iter().any(|part| name.contains(part))
+}
+
+/// Apply a delta from [`environment_delta`] on top of `base`
+pub fn apply_environment_delta(
+    mut base: HashMap<String, String>,
+    delta: &HashMap<String, Option<String>>,
+) -> HashMap<String, String> {
+    for (key, value) in delta {
+        match value {
+            Some(value) => base.insert(key.clone(), value.clone()),
+            None => base.remove(key),
+        };
+    }
+    base
+}
+
+/// The last `max_lines` lines of `text`
+pub fn last_lines(text: &str, max_lines: usize) -> &str {
+    if max_lines == 0 {
+        return "";
+    }
+
+    match text.rmatch_indices('\n').nth(max_lines - 1) {
+        Some((index, _)) => &text[index + 1..],
+        None => text,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_last_lines() {
+        assert_eq!(last_lines("a\nb\nc", 2), "b\nc");
+        assert_eq!(last_lines("a\nb\nc", 3), "a\nb\nc");
+        assert_eq!(last_lines("a\nb\nc", 10), "a\nb\nc");
+        assert_eq!(last_lines("a\nb\nc", 0), "");
+    }
+
+    #[test]
+    fn test_environment_delta_round_trip() {
+        let base: HashMap<String, String> = [("HOME", "/home/me"), ("OLD", "1"), ("PATH", "/bin")]
+            .into_iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        let mut environment = base.clone();
+        environment.remove("OLD");
+        environment.insert("PATH".to_string(), "/opt/bin:/bin".to_string());
+        environment.insert("NEW".to_string(), "x".to_string());
+
+        let delta = environment_delta(&base, &environment);
+        assert_eq!(delta.len(), 3);
+        assert_eq!(delta["OLD"], None);
+        assert_eq!(apply_environment_delta(base, &delta), environment);
+    }
+
+    #[test]
+    fn test_environment_delta_leaves_out_secrets() {
+        let base = HashMap::new();
+        let environment: HashMap<String, String> = [
+            ("GITHUB_TOKEN", "ghp_x"),
+            ("AWS_SECRET_ACCESS_KEY", "x"),
+            ("db_password", "x"),
+            ("SSH_AUTH_SOCK", "/tmp/agent"),
+            ("EDITOR", "vim"),
+        ]
+        .into_iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect();
+
+        let delta = environment_delta(&base, &environment);
+        assert_eq!(delta.len(), 1);
+        assert_eq!(delta["EDITOR"].as_deref(), Some("vim"));
+    }
+
+    #[test]
+    fn test_layout_serialization() {
+        let pane = |dir: &str| PaneLayout::Pane(PaneState {
+            profile: None,
+            working_dir: PathBuf::from(dir),
+            environment: HashMap::new(),
+            blocks: Vec::new(),
+        });
+        let state = SessionState {
+            tabs: vec![TabState {
+                layout: PaneLayout::Split {
+                    axis: SplitAxis::Vertical,
+                    ratio: 0.25,
+                    first: Box::new(pane("/a")),
+                    second: Box::new(pane("/b")),
+                },
+                focus: 1,
+            }],
+            active_tab: 0,
+            last_working_dir: None,
+        };
+
+        let restored: SessionState = serde_json::from_str(&serde_json::to_string(&state).unwrap()).unwrap();
+        match &restored.tabs[0].layout {
+            PaneLayout::Split { axis, ratio, second, .. } => {
+                assert_eq!(*axis, SplitAxis::Vertical);
+                assert_eq!(*ratio, 0.25);
+                assert!(matches!(second.as_ref(), PaneLayout::Pane(pane) if pane.working_dir == PathBuf::from("/b")));
+            }
+            PaneLayout::Pane(_) => panic!("split was not preserved"),
+        }
+    }
+}
@@ -3,6 +3,7 @@ use std::path::{Path, PathBuf};
 use crate::config::{AppConfig, ThemeConfig, ConfigError};
 use super::yaml_theme::{YamlTheme, YamlThemeError};
 
+#[derive(Debug, Clone)]
 pub struct YamlThemeManager {
     themes_dir: PathBuf,
     loaded_themes: HashMap<String, YamlTheme>,
@@ -196,10 +197,6 @@ impl YamlThemeManager {
     /// Create example themes in the themes directory
     fn create_example_themes(themes_dir: &Path) -> Result<(), ConfigError> {
         let example_themes = vec![
-            ("dracula.yaml", include_str!("../../themes/dracula.yaml")),
-            ("monokai.yaml", include_str!("../../themes/monokai.yaml")),
-            ("solarized-dark.yaml", include_str!("../../themes/solarized-dark.yaml")),
-            ("solarized-light.yaml", include_str!("../../themes/solarized-light.yaml")),
             ("gruvbox-dark.yaml", include_str!("../../themes/gruvbox-dark.yaml")),
             ("nord.yaml", include_str!("../../themes/nord.yaml")),
         ];
@@ -0,0 +1,431 @@
+use regex::Regex;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+/// Why a command failed, and what might fix it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnosis {
+    pub message: String,
+    pub details: Option<String>,
+    pub suggestions: Vec<Suggestion>,
+}
+
+impl Diagnosis {
+    /// A failure with nothing to suggest
+    pub fn new(message: &str, details: Option<String>) -> Self {
+        Self {
+            message: message.to_string(),
+            details,
+            suggestions: Vec::new(),
+        }
+    }
+}
+
+/// A follow-up offered with an error
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Suggestion {
+    pub label: String,
+    /// Command line put into the input when the suggestion is picked; suggestions without one are only hints
+    pub command: Option<String>,
+}
+
+impl Suggestion {
+    fn command(label: String, command: String) -> Self {
+        Self { label, command: Some(command) }
+    }
+
+    fn hint(label: String) -> Self {
+        Self { label, command: None }
+    }
+}
+
+/// Failures recognized in a line of output, with the command or path they are about
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Failure {
+    CommandNotFound(String),
+    PermissionDenied(String),
+    MissingFile(String),
+}
+
+/// Shells offered in place of a missing one, in order of preference
+const FALLBACK_SHELLS: [&str; 4] = ["bash", "zsh", "fish", "sh"];
+
+/// Package managers to suggest installing a missing command with, when the shell did not name one
+const PACKAGE_MANAGERS: [(&str, &str); 5] = [
+    ("brew", "brew install"),
+    ("apt", "sudo apt install"),
+    ("dnf", "sudo dnf install"),
+    ("pacman", "sudo pacman -S"),
+    ("apk", "sudo apk add"),
+];
+
+/// Explain why `shell` could not be started to run a command in `working_dir`
+pub fn spawn_failure(shell: &str, working_dir: &Path, error: &str) -> Diagnosis {
+    let details = Some(error.to_string());
+
+    if !working_dir.is_dir() {
+        return Diagnosis {
+            message: format!("Working directory not found: {}", working_dir.display()),
+            details,
+            suggestions: Vec::new(),
+        };
+    }
+
+    let search_path = search_path();
+    if find_executable(shell, &search_path).is_none() {
+        let suggestions = FALLBACK_SHELLS
+            .iter()
+            .filter_map(|name| find_executable(name, &search_path))
+            .map(|path| Suggestion::hint(format!("Set default_shell to {} in preferences", path.display())))
+            .collect();
+        return Diagnosis {
+            message: format!("Shell not found: {}", shell),
+            details,
+            suggestions,
+        };
+    }
+
+    Diagnosis {
+        message: format!("Failed to start {}", shell),
+        details,
+        suggestions: Vec::new(),
+    }
+}
+
+/// Explain a command that exited with `exit_code`, if its output says why in a way we recognize.
+/// Looking for similar commands reads every directory on `$PATH`, so this runs on a blocking thread.
+pub async fn diagnose(input: String, output: String, exit_code: i32, working_dir: PathBuf) -> Option<Diagnosis> {
+    tokio::task::spawn_blocking(move || diagnose_with(&input, &output, exit_code, &working_dir, &search_path()))
+        .await
+        .ok()
+        .flatten()
+}
+
+fn diagnose_with(
+    input: &str,
+    output: &str,
+    exit_code: i32,
+    working_dir: &Path,
+    search_path: &[PathBuf],
+) -> Option<Diagnosis> {
+    if exit_code == 0 {
+        return None;
+    }
+    let (line, failure) = output
+        .lines()
+        .find_map(|line| recognize(line, exit_code).map(|failure| (line.trim(), failure)))?;
+    let details = Some(line.to_string());
+
+    let diagnosis = match failure {
+        Failure::CommandNotFound(name) => {
+            let mut suggestions = Vec::new();
+            if let Some(closest) = closest(&name, executables(search_path)) {
+                suggestions.push(Suggestion::command(
+                    format!("Did you mean {}?", closest),
+                    input.replacen(&name, &closest, 1),
+                ));
+            }
+            suggestions.extend(install_suggestions(&name, output, search_path));
+            Diagnosis {
+                message: format!("Command not found: {}", name),
+                details,
+                suggestions,
+            }
+        }
+        Failure::PermissionDenied(path) => {
+            let program = input.split_whitespace().next().unwrap_or_default();
+            let suggestion = if program == path && working_dir.join(&path).is_file() {
+                Suggestion::command("Make it executable".to_string(), format!("chmod +x {}", path))
+            } else if program == "sudo" {
+                Suggestion::hint("Check the permissions of the file and its directories".to_string())
+            } else {
+                Suggestion::command("Run it with sudo".to_string(), format!("sudo {}", input.trim()))
+            };
+            Diagnosis {
+                message: format!("Permission denied: {}", path),
+                details,
+                suggestions: vec![suggestion],
+            }
+        }
+        Failure::MissingFile(path) => {
+            let suggestions = similar_path(&path, working_dir)
+                .filter(|_| input.contains(&path))
+                .map(|similar| {
+                    Suggestion::command(format!("Did you mean {}?", similar), input.replacen(&path, &similar, 1))
+                })
+                .into_iter()
+                .collect();
+            Diagnosis {
+                message: format!("No such file or directory: {}", path),
+                details,
+                suggestions,
+            }
+        }
+    };
+    Some(diagnosis)
+}
+
+/// Recognize the messages shells and common tools print for a few kinds of failure
+fn recognize(line: &str, exit_code: i32) -> Option<Failure> {
+    // `bash: foo: command not found`, `zsh: command not found: foo`, `fish: Unknown command: foo`
+    if let Some(name) = subject(line, "command not found").or_else(|| subject(line, "unknown command")) {
+        return Some(Failure::CommandNotFound(name));
+    }
+    // dash: `sh: 1: foo: not found`; too vague to trust unless the shell reported a missing command
+    if exit_code == 127 {
+        if let Some(name) = subject(line, "not found") {
+            return Some(Failure::CommandNotFound(name));
+        }
+    }
+    if let Some(path) = subject(line, "permission denied") {
+        return Some(Failure::PermissionDenied(path));
+    }
+    subject(line, "no such file or directory").map(Failure::MissingFile)
+}
+
+/// What a message is about, in either of the forms `tool: subject: reason` or `tool: reason: subject`,
+/// with the quotes GNU tools put around paths removed
+fn subject(line: &str, reason: &str) -> Option<String> {
+    let line = line.trim();
+    // Lowercasing only ASCII keeps byte offsets valid for the original line
+    let lower = line.to_ascii_lowercase();
+
+    let suffix = format!(": {}", reason);
+    // The reason ends the line, apart from details such as `(os error 2)`
+    let suffixed = lower
+        .match_indices(&suffix)
+        .map(|(index, _)| index)
+        .find(|index| matches!(lower[index + suffix.len()..].chars().next(), None | Some(' ' | '.')));
+
+    let subject = if let Some(index) = suffixed {
+        let before = &line[..index];
+        match before.strip_suffix(['\'', '’', '"']) {
+            Some(quoted) => quoted.rsplit(['\'', '‘', '"']).next().unwrap_or(quoted),
+            None => before.rsplit(": ").next().unwrap_or(before),
+        }
+    } else if let Some(index) = lower.find(&format!("{}: ", reason)) {
+        &line[index + reason.len() + 2..]
+    } else {
+        return None;
+    };
+
+    let subject = subject.trim().trim_matches(['\'', '‘', '’', '"']);
+    // Anything with spaces is a sentence rather than a command or path
+    (!subject.is_empty() && !subject.contains(char::is_whitespace)).then(|| subject.to_string())
+}
+
+/// Install commands for a missing program: the ones the shell's own handler printed, or a guess
+/// for the first package manager found
+fn install_suggestions(name: &str, output: &str, search_path: &[PathBuf]) -> Vec<Suggestion> {
+    static INSTALL: OnceLock<Regex> = OnceLock::new();
+    let install = INSTALL.get_or_init(|| {
+        Regex::new(r"(?:sudo\s+)?(?:apt|apt-get|dnf|yum|brew|pacman|snap|zypper|apk)\s+(?:install|add|-S)\s+[\w.+-]+")
+            .unwrap()
+    });
+
+    let mut suggestions: Vec<Suggestion> = Vec::new();
+    for found in install.find_iter(output) {
+        let command = found.as_str().to_string();
+        if !suggestions.iter().any(|s| s.command.as_ref() == Some(&command)) {
+            suggestions.push(Suggestion::command(format!("Install with {}", command), command));
+        }
+    }
+    if suggestions.is_empty() {
+        if let Some((manager, install)) = PACKAGE_MANAGERS
+            .iter()
+            .find(|(manager, _)| find_executable(manager, search_path).is_some())
+        {
+            suggestions.push(Suggestion::command(
+                format!("Try installing it with {}", manager),
+                format!("{} {}", install, name),
+            ));
+        }
+    }
+    suggestions
+}
+
+/// The directories of `PATH`
+fn search_path() -> Vec<PathBuf> {
+    std::env::var_os("PATH")
+        .map(|path| std::env::split_paths(&path).collect())
+        .unwrap_or_default()
+}
+
+/// Where `program` would be run from: itself when it is a path, otherwise the first match on the search path
+fn find_executable(program: &str, search_path: &[PathBuf]) -> Option<PathBuf> {
+    if program.contains('/') {
+        let path = PathBuf::from(program);
+        return is_executable(&path).then_some(path);
+    }
+    search_path
+        .iter()
+        .map(|dir| dir.join(program))
+        .find(|path| is_executable(path))
+}
+
+fn is_executable(path: &Path) -> bool {
+    let Ok(metadata) = path.metadata() else {
+        return false;
+    };
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        metadata.is_file() && metadata.permissions().mode() & 0o111 != 0
+    }
+    #[cfg(not(unix))]
+    {
+        metadata.is_file()
+    }
+}
+
+/// Names of every program on the search path
+fn executables(search_path: &[PathBuf]) -> Vec<String> {
+    search_path
+        .iter()
+        .filter_map(|dir| std::fs::read_dir(dir).ok())
+        .flatten()
+        .filter_map(Result::ok)
+        .filter(|entry| is_executable(&entry.path()))
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .collect()
+}
+
+/// `path` with its last component replaced by the most similar name in its directory
+fn similar_path(path: &str, working_dir: &Path) -> Option<String> {
+    let (dir, name) = match path.rsplit_once('/') {
+        Some((dir, name)) => (Some(dir), name),
+        None => (None, path),
+    };
+    let listed = match dir {
+        Some("") => PathBuf::from("/"),
+        Some(dir) => working_dir.join(dir),
+        None => working_dir.to_path_buf(),
+    };
+    let names = std::fs::read_dir(listed)
+        .ok()?
+        .filter_map(Result::ok)
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .collect();
+
+    let similar = closest(name, names)?;
+    Some(match dir {
+        Some(dir) => format!("{}/{}", dir, similar),
+        None => similar,
+    })
+}
+
+/// The candidate most like `name`, if any is close enough to be a typo of it
+fn closest(name: &str, mut candidates: Vec<String>) -> Option<String> {
+    let limit = (name.chars().count() / 3).max(1);
+    // Sorted so ties always resolve the same way
+    candidates.sort();
+    candidates
+        .into_iter()
+        .filter(|candidate| candidate != name)
+        .map(|candidate| (edit_distance(name, &candidate), candidate))
+        .filter(|(distance, _)| *distance <= limit)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate)
+}
+
+/// Edits needed to turn one string into the other, counting a swap of neighbouring characters as one
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut distances = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in distances.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, distance) in distances[0].iter_mut().enumerate() {
+        *distance = j;
+    }
+
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            let mut distance = (distances[i - 1][j] + 1)
+                .min(distances[i][j - 1] + 1)
+                .min(distances[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                distance = distance.min(distances[i - 2][j - 2] + 1);
+            }
+            distances[i][j] = distance;
+        }
+    }
+    distances[a.len()][b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_recognizes_shell_and_tool_messages() {
+        let not_found = |name: &str| Some(Failure::CommandNotFound(name.to_string()));
+        assert_eq!(recognize("bash: gti: command not found", 127), not_found("gti"));
+        assert_eq!(recognize("bash: line 1: gti: command not found", 127), not_found("gti"));
+        assert_eq!(recognize("zsh: command not found: gti", 127), not_found("gti"));
+        assert_eq!(recognize("fish: Unknown command: gti", 127), not_found("gti"));
+        assert_eq!(recognize("sh: 1: gti: not found", 127), not_found("gti"));
+        assert_eq!(recognize("sh: 1: gti: not found", 1), None);
+
+        assert_eq!(
+            recognize("bash: ./build.sh: Permission denied", 126),
+            Some(Failure::PermissionDenied("./build.sh".to_string()))
+        );
+        assert_eq!(
+            recognize("zsh: permission denied: ./build.sh", 126),
+            Some(Failure::PermissionDenied("./build.sh".to_string()))
+        );
+        assert_eq!(
+            recognize("ls: cannot access 'notes.md': No such file or directory", 2),
+            Some(Failure::MissingFile("notes.md".to_string()))
+        );
+        assert_eq!(
+            recognize("touch: cannot touch ‘/etc/x’: Permission denied", 1),
+            Some(Failure::PermissionDenied("/etc/x".to_string()))
+        );
+        assert_eq!(recognize("error: could not compile `neoterm`", 101), None);
+    }
+
+    #[test]
+    fn test_suggests_similar_commands_and_paths() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        let bin = dir.join("bin");
+        fs::create_dir_all(&bin).unwrap();
+        fs::write(bin.join("git"), "").unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(bin.join("git"), fs::Permissions::from_mode(0o755)).unwrap();
+        }
+        fs::write(dir.join("notes.md"), "").unwrap();
+
+        let diagnosis = diagnose_with("gti status", "bash: gti: command not found\n", 127, dir, &[bin]).unwrap();
+        assert_eq!(diagnosis.message, "Command not found: gti");
+        assert_eq!(diagnosis.suggestions[0].command.as_deref(), Some("git status"));
+
+        let output = "cat: notes.mb: No such file or directory\n";
+        let diagnosis = diagnose_with("cat notes.mb", output, 1, dir, &[]).unwrap();
+        assert_eq!(diagnosis.suggestions[0].command.as_deref(), Some("cat notes.md"));
+
+        assert_eq!(diagnose_with("cat notes.mb", output, 0, dir, &[]), None);
+    }
+
+    #[test]
+    fn test_install_hints_prefer_the_shells_own() {
+        let output = "Command 'htop' not found, but can be installed with:\nsudo apt install htop\n";
+        let suggestions = install_suggestions("htop", output, &[]);
+        assert_eq!(suggestions.len(), 1);
+        assert_eq!(suggestions[0].command.as_deref(), Some("sudo apt install htop"));
+    }
+
+    #[test]
+    fn test_transpositions_are_one_edit() {
+        assert_eq!(edit_distance("gti", "git"), 1);
+        assert_eq!(edit_distance("carg", "cargo"), 1);
+        assert_eq!(closest("ls", vec!["vim".to_string()]), None);
+    }
+}
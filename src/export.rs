@@ -0,0 +1,340 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::cell::RefCell;
+use std::path::{Path, PathBuf};
+
+use crate::block::{Block, BlockContent};
+use crate::config::ColorScheme;
+use crate::terminal::{self, Terminal};
+
+pub use crate::config::{CopyTarget, ExportFormat};
+
+/// The parts of a command block that get exported
+struct Entry<'a> {
+    block: &'a Block,
+    input: &'a str,
+    working_dir: &'a Path,
+    terminal: &'a Terminal,
+}
+
+/// Command blocks among `blocks`; other kinds of blocks have nothing to replay
+fn entries<'a>(blocks: &'a [&'a Block]) -> impl Iterator<Item = Entry<'a>> {
+    blocks.iter().filter_map(|block| match &block.content {
+        BlockContent::Command { input, working_dir, terminal, .. } => Some(Entry {
+            block,
+            input,
+            working_dir,
+            terminal,
+        }),
+        _ => None,
+    })
+}
+
+/// Render the command blocks among `blocks` in the given format
+pub fn export(blocks: &[&Block], format: ExportFormat, colors: &ColorScheme) -> String {
+    match format {
+        ExportFormat::Markdown => markdown(blocks),
+        ExportFormat::Html => html(blocks, colors),
+        ExportFormat::Json => json(blocks),
+        ExportFormat::Asciicast => asciicast(blocks),
+    }
+}
+
+fn markdown(blocks: &[&Block]) -> String {
+    entries(blocks)
+        .map(|entry| {
+            let output = entry.terminal.text();
+            let fence = fence(&format!("{}\n{}", entry.input, output));
+            let mut markdown = format!("{fence}sh\n{}\n{fence}\n", entry.input);
+            if !output.is_empty() {
+                markdown.push_str(&format!("\n{fence}\n{}\n{fence}\n", output));
+            }
+            markdown
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// A code fence longer than any run of backticks in `text`
+fn fence(text: &str) -> String {
+    let longest = text
+        .split(|ch| ch != '`')
+        .map(str::len)
+        .max()
+        .unwrap_or(0);
+    "`".repeat(longest.max(2) + 1)
+}
+
+fn html(blocks: &[&Block], colors: &ColorScheme) -> String {
+    let foreground: iced::Color = colors.terminal_foreground.clone().into();
+    let background: iced::Color = colors.terminal_background.clone().into();
+
+    let mut body = String::new();
+    for entry in entries(blocks) {
+        body.push_str(&format!(
+            "<section>\n<div class=\"command\">$ {}</div>\n<pre>",
+            escape_html(entry.input)
+        ));
+        for row in entry.terminal.lines() {
+            for run in terminal::view::runs(row, colors) {
+                let mut style = Vec::new();
+                if run.fg != foreground {
+                    style.push(format!("color: {}", css_color(run.fg)));
+                }
+                if run.bg != background {
+                    style.push(format!("background: {}", css_color(run.bg)));
+                }
+                if run.attrs.bold {
+                    style.push("font-weight: bold".to_string());
+                }
+                if run.attrs.italic {
+                    style.push("font-style: italic".to_string());
+                }
+                match (run.attrs.underline, run.attrs.strikethrough) {
+                    (true, true) => style.push("text-decoration: underline line-through".to_string()),
+                    (true, false) => style.push("text-decoration: underline".to_string()),
+                    (false, true) => style.push("text-decoration: line-through".to_string()),
+                    (false, false) => {}
+                }
+
+                if style.is_empty() {
+                    body.push_str(&escape_html(&run.text));
+                } else {
+                    body.push_str(&format!(
+                        "<span style=\"{}\">{}</span>",
+                        style.join("; "),
+                        escape_html(&run.text)
+                    ));
+                }
+            }
+            if !row.wrapped {
+                body.push('\n');
+            }
+        }
+        body.push_str("</pre>\n</section>\n");
+    }
+
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>NeoTerm export</title>\n<style>\n\
+         body {{ background: {}; color: {}; font-family: monospace; }}\n\
+         .command {{ font-weight: bold; }}\n\
+         pre {{ white-space: pre-wrap; margin: 0.5em 0 1.5em; }}\n\
+         </style>\n</head>\n<body>\n{}</body>\n</html>\n",
+        css_color(background),
+        css_color(foreground),
+        body
+    )
+}
+
+fn css_color(color: iced::Color) -> String {
+    let [r, g, b, a] = color.into_rgba8();
+    if a == u8::MAX {
+        format!("#{:02x}{:02x}{:02x}", r, g, b)
+    } else {
+        format!("rgba({}, {}, {}, {:.2})", r, g, b, color.a)
+    }
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[derive(Serialize)]
+struct JsonBlock<'a> {
+    command: &'a str,
+    working_dir: String,
+    exit_code: Option<i32>,
+    started_at: DateTime<Utc>,
+    finished_at: Option<DateTime<Utc>>,
+    duration_ms: Option<i64>,
+    cpu_time_ms: Option<u128>,
+    peak_rss_kb: Option<u64>,
+    output: String,
+}
+
+fn json(blocks: &[&Block]) -> String {
+    let blocks: Vec<_> = entries(blocks)
+        .map(|entry| JsonBlock {
+            command: entry.input,
+            working_dir: entry.working_dir.display().to_string(),
+            exit_code: entry.block.exit_code,
+            started_at: entry.block.timestamp,
+            finished_at: entry.block.finished_at,
+            duration_ms: entry.block.duration().map(|duration| duration.num_milliseconds()),
+            cpu_time_ms: entry.block.usage.map(|usage| usage.cpu_time.as_millis()),
+            peak_rss_kb: entry.block.usage.and_then(|usage| usage.peak_rss_kb),
+            output: entry.terminal.text(),
+        })
+        .collect();
+
+    serde_json::to_string_pretty(&blocks).expect("exported blocks serialize to JSON")
+}
+
+/// An asciicast v2 recording that types each command when it started and prints
+/// its output when it finished, as output is not kept with its timing
+fn asciicast(blocks: &[&Block]) -> String {
+    let entries: Vec<_> = entries(blocks).collect();
+    let start = entries
+        .iter()
+        .map(|entry| entry.block.timestamp)
+        .min()
+        .unwrap_or_else(Utc::now);
+    let (width, height) = entries
+        .first()
+        .map_or((80, 24), |entry| (entry.terminal.grid().width(), entry.terminal.grid().height()));
+    let seconds = |time: DateTime<Utc>| (time - start).num_milliseconds() as f64 / 1000.0;
+
+    let mut events = Vec::new();
+    for entry in &entries {
+        let started = seconds(entry.block.timestamp);
+        events.push((started, format!("$ {}\r\n", entry.input)));
+
+        let output = entry.terminal.ansi_text();
+        if !output.is_empty() {
+            let finished = entry.block.finished_at.map_or(started, seconds);
+            events.push((finished, format!("{}\r\n", output.replace('\n', "\r\n"))));
+        }
+    }
+    // Commands that ran at the same time interleave
+    events.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+    let header = serde_json::json!({
+        "version": 2,
+        "width": width,
+        "height": height,
+        "timestamp": start.timestamp(),
+        "env": { "TERM": "xterm-256color" },
+    });
+    let mut cast = format!("{}\n", header);
+    for (time, data) in events {
+        cast.push_str(&format!("{}\n", serde_json::json!([time, "o", data])));
+    }
+    cast
+}
+
+/// The text of `blocks` to put on the clipboard, one block after another
+pub fn copy_text(blocks: &[&Block], target: CopyTarget) -> String {
+    blocks
+        .iter()
+        .filter_map(|block| {
+            let command = match &block.content {
+                BlockContent::Command { input, .. } => Some(input.as_str()),
+                _ => None,
+            };
+            match (target, command) {
+                (CopyTarget::Command, command) => command.map(str::to_string),
+                (CopyTarget::Output, _) | (CopyTarget::Both, None) => Some(output_text(block)),
+                (CopyTarget::Both, Some(command)) => {
+                    Some(format!("$ {}\n{}", command, output_text(block)).trim_end().to_string())
+                }
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn output_text(block: &Block) -> String {
+    match &block.content {
+        BlockContent::Command { terminal, .. } => terminal.text(),
+        BlockContent::Markdown(content) => content.clone(),
+        BlockContent::FilePreview { content, .. } => content.clone(),
+        BlockContent::Error { message, details: Some(details), .. } => format!("{}\n{}", message, details),
+        BlockContent::Error { message, details: None, .. } => message.clone(),
+    }
+}
+
+thread_local! {
+    /// Kept open because on X11 copied text is only available while its owner is
+    static CLIPBOARD: RefCell<Option<arboard::Clipboard>> = const { RefCell::new(None) };
+}
+
+pub fn copy_to_clipboard(text: String) -> Result<(), arboard::Error> {
+    CLIPBOARD.with(|clipboard| {
+        let mut clipboard = clipboard.borrow_mut();
+        let clipboard = match &mut *clipboard {
+            Some(clipboard) => clipboard,
+            None => clipboard.insert(arboard::Clipboard::new()?),
+        };
+        clipboard.set_text(text)
+    })
+}
+
+/// Ask where to save an export and write it there; `None` if the user cancelled
+pub async fn save(content: String, format: ExportFormat) -> Result<Option<PathBuf>, String> {
+    let Some(file) = rfd::AsyncFileDialog::new()
+        .set_file_name(format!("neoterm-export.{}", format.extension()))
+        .save_file()
+        .await
+    else {
+        return Ok(None);
+    };
+
+    let path = file.path().to_path_buf();
+    tokio::fs::write(&path, content)
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(Some(path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ThemeConfig;
+
+    fn block(input: &str, output: &str) -> Block {
+        let mut block = Block::new_command(input.to_string(), PathBuf::from("/tmp"), 40, 10, 100);
+        block.append_output(output.as_bytes());
+        block.finish(0, None);
+        block
+    }
+
+    #[test]
+    fn test_markdown_fences_outlast_backticks_in_output() {
+        let block = block("cat README.md", "```rust\r\nfn main() {}\r\n```");
+        let markdown = markdown(&[&block]);
+        assert!(markdown.starts_with("````sh\ncat README.md\n````\n"));
+        assert!(markdown.ends_with("````\n```rust\nfn main() {}\n```\n````\n"));
+    }
+
+    #[test]
+    fn test_html_keeps_colors_and_escapes_text() {
+        let block = block("echo", "\x1b[1;31m<error>\x1b[0m & done");
+        let html = html(&[&block], &ThemeConfig::default().colors);
+        assert!(html.contains("font-weight: bold\">&lt;error&gt;</span>"));
+        assert!(html.contains("<span style=\"color: #"));
+        assert!(html.contains(" &amp; done\n</pre>"));
+    }
+
+    #[test]
+    fn test_json_reports_duration() {
+        let mut block = block("true", "");
+        block.finished_at = Some(block.timestamp + chrono::Duration::milliseconds(1500));
+        let exported: serde_json::Value = serde_json::from_str(&json(&[&block])).unwrap();
+        assert_eq!(exported[0]["command"], "true");
+        assert_eq!(exported[0]["exit_code"], 0);
+        assert_eq!(exported[0]["duration_ms"], 1500);
+    }
+
+    #[test]
+    fn test_asciicast_header_and_events() {
+        let block = block("ls", "a\r\nb");
+        let cast = asciicast(&[&block]);
+        let lines: Vec<serde_json::Value> = cast.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        assert_eq!(lines[0]["version"], 2);
+        assert_eq!(lines[0]["width"], 40);
+        assert_eq!(lines[1][1], "o");
+        assert_eq!(lines[1][2], "$ ls\r\n");
+        assert_eq!(lines[2][2], "a\r\nb\r\n");
+    }
+
+    #[test]
+    fn test_copy_text() {
+        let block = block("echo hi", "hi");
+        assert_eq!(copy_text(&[&block], CopyTarget::Command), "echo hi");
+        assert_eq!(copy_text(&[&block], CopyTarget::Output), "hi");
+        assert_eq!(copy_text(&[&block], CopyTarget::Both), "$ echo hi\nhi");
+    }
+}
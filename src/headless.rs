@@ -0,0 +1,308 @@
+use iced::advanced::graphics::Viewport;
+use iced::advanced::widget::Tree;
+use iced::advanced::{layout, renderer};
+use iced::widget::{column, container};
+use iced::{mouse, Element, Font, Pixels, Rectangle, Size, Theme};
+use iced_runtime::user_interface::{self, UserInterface};
+use image::RgbaImage;
+use std::path::Path;
+
+use crate::block::Block;
+use crate::config::{BlockState, TerminalPreferences, ThemeConfig, YamlTheme};
+use crate::Message;
+
+/// Width of the images `--render` draws
+const RENDER_WIDTH: u32 = 800;
+/// Space around the blocks, and between them
+const RENDER_PADDING: f32 = 8.0;
+
+/// The widget palette for a theme, so buttons and borders follow it like the blocks' output does
+pub fn iced_theme(theme: &ThemeConfig) -> Theme {
+    let colors = &theme.colors;
+    Theme::custom(
+        theme.name.clone(),
+        iced::theme::Palette {
+            background: colors.background.clone().into(),
+            text: colors.text.clone().into(),
+            primary: colors.primary.clone().into(),
+            success: colors.success.clone().into(),
+            danger: colors.error.clone().into(),
+        },
+    )
+}
+
+/// Draw `blocks` stacked the way a pane shows them into an image `size` pixels large, with the
+/// software renderer, so no GPU or window is needed; `save` the image to get a PNG
+pub fn render_blocks(blocks: &[Block], theme: &ThemeConfig, preferences: &TerminalPreferences, size: Size<u32>) -> RgbaImage {
+    render(blocks_view(blocks, theme, preferences), &iced_theme(theme), size)
+}
+
+/// How tall an image `width` pixels wide must be to show all of `blocks`
+pub fn blocks_height(blocks: &[Block], theme: &ThemeConfig, preferences: &TerminalPreferences, width: u32) -> u32 {
+    let content = blocks_view(blocks, theme, preferences);
+    let limits = layout::Limits::new(Size::ZERO, Size::new(width as f32, f32::INFINITY));
+    let node = content
+        .as_widget()
+        .layout(&mut Tree::new(&content), &software_renderer(), &limits);
+    node.size().height.ceil() as u32
+}
+
+/// Draw saved blocks, as a JSON list like the session file keeps them, in the theme of a YAML
+/// file, and save the picture as a PNG; this is what `neoterm --render` does
+pub fn render_file(blocks: &Path, theme: &Path, output: &Path) -> Result<(), String> {
+    let content = std::fs::read_to_string(blocks).map_err(|e| format!("{}: {}", blocks.display(), e))?;
+    let states: Vec<BlockState> = serde_json::from_str(&content).map_err(|e| format!("{}: {}", blocks.display(), e))?;
+    let theme = YamlTheme::from_file(theme)
+        .and_then(|yaml| yaml.to_theme_config())
+        .map_err(|e| format!("{}: {}", theme.display(), e))?;
+
+    let preferences = TerminalPreferences::default();
+    let blocks: Vec<Block> = states
+        .into_iter()
+        .map(|state| Block::restore(state, 80, 24, preferences.scrollback_lines))
+        .collect();
+    let height = blocks_height(&blocks, &theme, &preferences, RENDER_WIDTH).max(1);
+    let image = render_blocks(&blocks, &theme, &preferences, Size::new(RENDER_WIDTH, height));
+    image.save(output).map_err(|e| format!("{}: {}", output.display(), e))
+}
+
+fn blocks_view<'a>(blocks: &'a [Block], theme: &'a ThemeConfig, preferences: &'a TerminalPreferences) -> Element<'a, Message> {
+    container(
+        column(
+            blocks
+                .iter()
+                .map(|block| block.view(theme, preferences, false, false, &[], 0.0..f32::INFINITY))
+                .collect::<Vec<_>>(),
+        )
+        .spacing(RENDER_PADDING),
+    )
+    .padding(RENDER_PADDING)
+    .into()
+}
+
+fn software_renderer() -> iced::Renderer {
+    iced::Renderer::TinySkia(iced_tiny_skia::Renderer::new(
+        iced_tiny_skia::Backend::new(),
+        Font::default(),
+        Pixels(16.0),
+    ))
+}
+
+/// Lay out and draw an element with tiny-skia
+fn render(content: Element<Message>, theme: &Theme, size: Size<u32>) -> RgbaImage {
+    let mut renderer = software_renderer();
+    let bounds = Size::new(size.width as f32, size.height as f32);
+    let mut interface = UserInterface::build(content, bounds, user_interface::Cache::default(), &mut renderer);
+    let palette = theme.palette();
+    interface.draw(
+        &mut renderer,
+        theme,
+        &renderer::Style { text_color: palette.text },
+        mouse::Cursor::Unavailable,
+    );
+
+    let mut pixmap = tiny_skia::Pixmap::new(size.width, size.height).expect("an image to render into");
+    let mut mask = tiny_skia::Mask::new(size.width, size.height).expect("a clip mask for the image");
+    let iced::Renderer::TinySkia(renderer) = &mut renderer else {
+        unreachable!("the renderer was made with tiny-skia");
+    };
+    renderer.with_primitives(|backend, primitives| {
+        backend.draw(
+            &mut pixmap.as_mut(),
+            &mut mask,
+            primitives,
+            &Viewport::with_physical_size(size, 1.0),
+            &[Rectangle::with_size(bounds)],
+            palette.background,
+            &[] as &[String],
+        );
+    });
+
+    // The backend draws BGRA for the window surface; the background is opaque, so nothing is premultiplied
+    let mut pixels = pixmap.take();
+    for pixel in pixels.chunks_exact_mut(4) {
+        pixel.swap(0, 2);
+    }
+    RgbaImage::from_raw(size.width, size.height, pixels).expect("one RGBA pixel for every point of the image")
+}
+
+/// Comparing rendered images with the goldens in `tests/golden`
+#[cfg(test)]
+mod golden {
+    use super::*;
+    use std::path::PathBuf;
+
+    /// How far a channel may stray from the golden before its pixel counts as different; text is
+    /// anti-aliased a little differently from one font rasterizer version to the next
+    const CHANNEL_TOLERANCE: u8 = 24;
+    /// Share of the pixels that may differ before an image no longer matches its golden
+    const MAX_DIFFERING_SHARE: f64 = 0.002;
+    /// Set to write the images tests render as their new goldens instead of comparing against them
+    const UPDATE_GOLDENS: &str = "NEOTERM_UPDATE_GOLDENS";
+
+    /// How an image differs from the one it should look like
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub struct ImageDiff {
+        /// Pixels with a channel off by more than [`CHANNEL_TOLERANCE`]
+        pub differing: usize,
+        pub total: usize,
+        /// Largest difference in any channel of any pixel
+        pub max_delta: u8,
+    }
+
+    impl ImageDiff {
+        pub fn matches(&self) -> bool {
+            self.differing as f64 <= self.total as f64 * MAX_DIFFERING_SHARE
+        }
+    }
+
+    /// Compare two images pixel by pixel, or `None` if their sizes differ
+    pub fn diff_images(actual: &RgbaImage, expected: &RgbaImage) -> Option<ImageDiff> {
+        if actual.dimensions() != expected.dimensions() {
+            return None;
+        }
+        let mut diff = ImageDiff {
+            differing: 0,
+            total: (actual.width() * actual.height()) as usize,
+            max_delta: 0,
+        };
+        for (a, e) in actual.pixels().zip(expected.pixels()) {
+            let delta = a.0.iter().zip(e.0).map(|(a, e)| a.abs_diff(e)).max().unwrap_or(0);
+            diff.max_delta = diff.max_delta.max(delta);
+            if delta > CHANNEL_TOLERANCE {
+                diff.differing += 1;
+            }
+        }
+        Some(diff)
+    }
+
+    /// Check an image against the golden of the same name, or write it as the golden while
+    /// `NEOTERM_UPDATE_GOLDENS` is set. On a mismatch the image is saved to the temporary directory so
+    /// it can be compared with the golden.
+    pub fn check_golden(name: &str, image: &RgbaImage) -> Result<(), String> {
+        let path = golden_path(name);
+        if std::env::var_os(UPDATE_GOLDENS).is_some() {
+            if let Some(dir) = path.parent() {
+                std::fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+            }
+            return image.save(&path).map_err(|e| e.to_string());
+        }
+        if !path.exists() {
+            return Err(format!(
+                "{}: golden missing at {}; render it with {} set",
+                name,
+                path.display(),
+                UPDATE_GOLDENS
+            ));
+        }
+
+        let expected = image::open(&path).map_err(|e| format!("{}: {}", path.display(), e))?.to_rgba8();
+        let problem = match diff_images(image, &expected) {
+            None => format!(
+                "rendered {}x{} but the golden is {}x{}",
+                image.width(),
+                image.height(),
+                expected.width(),
+                expected.height()
+            ),
+            Some(diff) if diff.matches() => return Ok(()),
+            Some(diff) => format!(
+                "{} of {} pixels differ, by up to {}",
+                diff.differing, diff.total, diff.max_delta
+            ),
+        };
+
+        let actual_path = std::env::temp_dir().join(format!("neoterm-{}.actual.png", name));
+        let _ = image.save(&actual_path);
+        Err(format!(
+            "{} doesn't match {}: {}; the rendered image is at {}",
+            name,
+            path.display(),
+            problem,
+            actual_path.display()
+        ))
+    }
+
+    fn golden_path(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests")
+            .join("golden")
+            .join(format!("{}.png", name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::golden::*;
+    use super::*;
+    use chrono::TimeZone;
+    use std::path::PathBuf;
+
+    /// A finished command, a failed one, a note and an error, at fixed times so headers don't change
+    fn sample_blocks() -> Vec<Block> {
+        let started = chrono::Utc.with_ymd_and_hms(2024, 1, 1, 12, 0, 0).unwrap();
+        let mut listing = Block::new_command("ls --color".to_string(), PathBuf::from("/work"), 80, 24, 1000);
+        listing.append_output(b"\x1b[1;34msrc\x1b[0m  \x1b[1;34mthemes\x1b[0m  Cargo.toml  \x1b[32mbuild.sh\x1b[0m  README.md\r\n");
+        listing.finish(0, None);
+
+        let mut build = Block::new_command("cargo build".to_string(), PathBuf::from("/work"), 80, 24, 1000);
+        build.append_output(b"   \x1b[1;32mCompiling\x1b[0m neoterm v0.1.0\r\n");
+        build.append_output(b"\x1b[1;31merror[E0425]\x1b[0m: cannot find value `x` in this scope\r\n");
+        build.append_output(b"  \x1b[1;34m-->\x1b[0m src/main.rs:3:5\r\n");
+        build.finish(101, None);
+
+        let note = Block::new_markdown("## Notes\n\nRun **`cargo test`** before pushing:\n\n```rust\nfn main() {\n    println!(\"hi\");\n}\n```".to_string());
+        let error = Block::new_error("Command not found: gti".to_string(), Some("Did you mean git?".to_string()));
+
+        let mut blocks = vec![listing, build, note, error];
+        for (i, block) in blocks.iter_mut().enumerate() {
+            block.timestamp = started + chrono::Duration::seconds(10 * i as i64);
+            if block.finished_at.is_some() {
+                block.finished_at = Some(block.timestamp + chrono::Duration::milliseconds(1500));
+            }
+        }
+        blocks
+    }
+
+    #[test]
+    fn test_bundled_themes_match_their_goldens() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("themes");
+        let mut paths: Vec<PathBuf> = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.extension().is_some_and(|extension| extension == "yaml"))
+            .collect();
+        paths.sort();
+        assert!(!paths.is_empty());
+
+        let blocks = sample_blocks();
+        let preferences = TerminalPreferences::default();
+        let failures: Vec<String> = paths
+            .iter()
+            .filter_map(|path| {
+                let theme = YamlTheme::from_file(path).unwrap().to_theme_config().unwrap();
+                let image = render_blocks(&blocks, &theme, &preferences, Size::new(800, 720));
+                let name = path.file_stem().unwrap().to_string_lossy();
+                check_golden(&name, &image).err()
+            })
+            .collect();
+        assert!(failures.is_empty(), "{}", failures.join("\n"));
+    }
+
+    #[test]
+    fn test_diffs_tolerate_small_changes() {
+        let expected = RgbaImage::from_pixel(10, 10, image::Rgba([10, 20, 30, 255]));
+        let mut actual = expected.clone();
+        actual.put_pixel(0, 0, image::Rgba([20, 20, 30, 255]));
+        assert_eq!(
+            diff_images(&actual, &expected),
+            Some(ImageDiff { differing: 0, total: 100, max_delta: 10 })
+        );
+
+        actual.put_pixel(1, 0, image::Rgba([200, 20, 30, 255]));
+        let diff = diff_images(&actual, &expected).unwrap();
+        assert_eq!(diff.differing, 1);
+        assert!(!diff.matches());
+        assert_eq!(diff_images(&RgbaImage::new(5, 5), &expected), None);
+    }
+}
@@ -1,4 +1,4 @@
-use iced::{Element, widget::{text_input, column, row, container}};
+use iced::{Element, widget::{text_input, column, row, container, pane_grid}};
 use std::collections::VecDeque;
 
 use crate::Message;
@@ -270,10 +270,10 @@ impl EnhancedTextInput {
         }
     }
 
-    pub fn view(&self) -> Element<Message> {
+    pub fn view(&self, pane: pane_grid::Pane) -> Element<Message> {
         let input = text_input("Enter command...", &self.value)
-            .on_input(Message::InputChanged)
-            .on_submit(Message::ExecuteCommand)
+            .on_input(move |input| Message::InputChanged(pane, input))
+            .on_submit(Message::ExecuteCommand(pane))
             .padding(12)
             .size(16);
 
@@ -0,0 +1,276 @@
+use regex::Regex;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::OnceLock;
+
+use crate::preview;
+use crate::terminal::{Row, Terminal};
+
+/// What clicking a link opens
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LinkTarget {
+    Url(String),
+    File {
+        path: PathBuf,
+        line: Option<u32>,
+        column: Option<u32>,
+    },
+}
+
+/// A link in a row of output
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Link {
+    /// Cells of the row the link covers
+    pub columns: Range<usize>,
+    pub target: LinkTarget,
+}
+
+/// The links in a row: its OSC 8 hyperlinks and, with `detect` set, the URLs and paths in its text,
+/// with relative paths taken from `working_dir`
+pub fn row_links(row: &Row, terminal: &Terminal, working_dir: &Path, detect: bool) -> Vec<Link> {
+    let mut links: Vec<Link> = Vec::new();
+    for (col, cell) in row.cells.iter().enumerate() {
+        let Some(uri) = cell.link.and_then(|id| terminal.hyperlink(id)) else {
+            continue;
+        };
+        let target = hyperlink_target(uri, working_dir);
+        match links.last_mut() {
+            Some(link) if link.columns.end == col && link.target == target => link.columns.end = col + 1,
+            _ => links.push(Link {
+                columns: col..col + 1,
+                target,
+            }),
+        }
+    }
+
+    if detect {
+        // Text the program already linked keeps its own target
+        let detected = detect_links(&row.text(), working_dir)
+            .into_iter()
+            .filter(|found| {
+                !links
+                    .iter()
+                    .any(|link| link.columns.start < found.columns.end && found.columns.start < link.columns.end)
+            })
+            .collect::<Vec<_>>();
+        links.extend(detected);
+        links.sort_by_key(|link| link.columns.start);
+    }
+    links
+}
+
+/// URLs, paths and compiler-style `file:line:col` references in a line of text
+pub fn detect_links(text: &str, working_dir: &Path) -> Vec<Link> {
+    static URL: OnceLock<Regex> = OnceLock::new();
+    static WORD: OnceLock<Regex> = OnceLock::new();
+    static POSITION: OnceLock<Regex> = OnceLock::new();
+    let url = URL.get_or_init(|| Regex::new(r#"\b(?:https?|ftp|file)://[^\s<>"'`]+"#).unwrap());
+    let word = WORD.get_or_init(|| Regex::new(r"\S+").unwrap());
+    // A path, then an optional `:line` or `:line:col`, then any punctuation ending the sentence
+    let position = POSITION.get_or_init(|| {
+        Regex::new(r#"^(?P<path>[~\w./@+-]+?)(?::(?P<line>\d+)(?::(?P<column>\d+))?)?[.,;:!?)\]>'"`]*$"#).unwrap()
+    });
+    let column = |byte: usize| text[..byte].chars().count();
+
+    let mut links: Vec<Link> = Vec::new();
+    for found in url.find_iter(text) {
+        let url = trim_url(found.as_str());
+        links.push(Link {
+            columns: column(found.start())..column(found.start() + url.len()),
+            target: LinkTarget::Url(url.to_string()),
+        });
+    }
+
+    for found in word.find_iter(text) {
+        // Quotes and brackets around a path are not part of it
+        let word = found.as_str().trim_start_matches(['"', '\'', '`', '(', '[', '<']);
+        let offset = found.end() - word.len();
+        let Some(captures) = position.captures(word) else {
+            continue;
+        };
+        let path = &captures["path"];
+        let line = captures.name("line").and_then(|line| line.as_str().parse().ok());
+        if !looks_like_path(path, line.is_some()) {
+            continue;
+        }
+
+        let end = captures.name("column").or(captures.name("line")).map_or(path.len(), |m| m.end());
+        let columns = column(offset)..column(offset + end);
+        if links.iter().any(|link| link.columns.start < columns.end && columns.start < link.columns.end) {
+            continue;
+        }
+        links.push(Link {
+            columns,
+            target: LinkTarget::File {
+                path: preview::resolve_path(path, working_dir),
+                line,
+                column: captures.name("column").and_then(|column| column.as_str().parse().ok()),
+            },
+        });
+    }
+
+    links.sort_by_key(|link| link.columns.start);
+    links
+}
+
+/// Whether a word is specific enough to be a path rather than ordinary text
+fn looks_like_path(word: &str, has_line: bool) -> bool {
+    if !word.chars().any(char::is_alphanumeric) {
+        return false;
+    }
+    let has_extension = word
+        .rsplit('/')
+        .next()
+        .and_then(|name| name.rsplit_once('.'))
+        .map_or(false, |(stem, extension)| {
+            !stem.is_empty() && !extension.is_empty() && extension.chars().any(char::is_alphabetic)
+        });
+
+    // `main.rs:12`, `src/lib.rs:3:4`, `Makefile:10`
+    if has_line {
+        return has_extension || word.contains('/') || word.starts_with(char::is_uppercase);
+    }
+    let rooted = ["/", "./", "../", "~/"].iter().any(|prefix| word.starts_with(prefix)) && word.len() > 2;
+    rooted || (word.contains('/') && has_extension)
+}
+
+/// A URL without the punctuation that usually follows one in prose
+fn trim_url(url: &str) -> &str {
+    let mut url = url.trim_end_matches(['.', ',', ';', ':', '!', '?', '\'', '"']);
+    // Keep the parenthesis of `https://en.wikipedia.org/wiki/Rust_(language)` but not of `(see https://x.org)`
+    while url.ends_with(')') && url.matches('(').count() < url.matches(')').count() {
+        url = url[..url.len() - 1].trim_end_matches(['.', ',', ';', ':', '!', '?']);
+    }
+    url
+}
+
+/// Where an OSC 8 URI points: local `file://` URIs are files, anything else is opened as a URL
+fn hyperlink_target(uri: &str, working_dir: &Path) -> LinkTarget {
+    match url::Url::parse(uri) {
+        Ok(url) if url.scheme() == "file" => match url.to_file_path() {
+            Ok(path) => LinkTarget::File {
+                path: working_dir.join(path),
+                line: None,
+                column: None,
+            },
+            Err(()) => LinkTarget::Url(uri.to_string()),
+        },
+        _ => LinkTarget::Url(uri.to_string()),
+    }
+}
+
+/// Open a URL, or a file or directory, with the desktop's default application
+pub fn open_externally(target: &str) -> std::io::Result<()> {
+    let mut command = if cfg!(target_os = "macos") {
+        std::process::Command::new("open")
+    } else if cfg!(windows) {
+        let mut command = std::process::Command::new("cmd");
+        command.args(["/C", "start", ""]);
+        command
+    } else {
+        std::process::Command::new("xdg-open")
+    };
+    command
+        .arg(target)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .map(|_| ())
+}
+
+/// The command line that opens a file at a line and column in the user's `$VISUAL` or `$EDITOR`
+pub fn editor_command(path: &Path, line: u32, column: Option<u32>) -> Option<String> {
+    let editor = std::env::var("VISUAL")
+        .ok()
+        .filter(|editor| !editor.trim().is_empty())
+        .or_else(|| std::env::var("EDITOR").ok().filter(|editor| !editor.trim().is_empty()))?;
+    Some(editor_command_for(&editor, path, line, column))
+}
+
+fn editor_command_for(editor: &str, path: &Path, line: u32, column: Option<u32>) -> String {
+    let program = editor
+        .split_whitespace()
+        .next()
+        .and_then(|program| Path::new(program).file_name())
+        .and_then(|name| name.to_str())
+        .unwrap_or_default();
+    let path = shell_quote(&path.to_string_lossy());
+    let position = match column {
+        Some(column) => format!("{}:{}", line, column),
+        None => line.to_string(),
+    };
+
+    match program {
+        "code" | "code-insiders" | "codium" | "cursor" => format!("{} -g {}:{}", editor, path, position),
+        "subl" | "zed" | "hx" | "helix" | "micro" => format!("{} {}:{}", editor, path, position),
+        // vi, vim, nvim, nano, emacs and most others take `+line`
+        _ => format!("{} +{} {}", editor, line, path),
+    }
+}
+
+/// Quote a word for the shell if it needs it
+fn shell_quote(word: &str) -> String {
+    if word.chars().all(|c| c.is_alphanumeric() || "/._-+@~:".contains(c)) {
+        word.to_string()
+    } else {
+        format!("'{}'", word.replace('\'', r"'\''"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn targets(text: &str) -> Vec<(String, LinkTarget)> {
+        let chars: Vec<char> = text.chars().collect();
+        detect_links(text, Path::new("/work"))
+            .into_iter()
+            .map(|link| (chars[link.columns].iter().collect(), link.target))
+            .collect()
+    }
+
+    fn file(path: &str, line: Option<u32>, column: Option<u32>) -> LinkTarget {
+        LinkTarget::File {
+            path: PathBuf::from(path),
+            line,
+            column,
+        }
+    }
+
+    #[test]
+    fn test_detects_urls_paths_and_positions() {
+        assert_eq!(
+            targets("see https://example.com/a_(b) or (https://x.org)."),
+            vec![
+                ("https://example.com/a_(b)".to_string(), LinkTarget::Url("https://example.com/a_(b)".to_string())),
+                ("https://x.org".to_string(), LinkTarget::Url("https://x.org".to_string())),
+            ]
+        );
+        assert_eq!(
+            targets("  --> src/main.rs:12:5"),
+            vec![("src/main.rs:12:5".to_string(), file("/work/src/main.rs", Some(12), Some(5)))]
+        );
+        assert_eq!(
+            targets("lib.rs:3: error, see /etc/hosts and ../notes.md."),
+            vec![
+                ("lib.rs:3".to_string(), file("/work/lib.rs", Some(3), None)),
+                ("/etc/hosts".to_string(), file("/etc/hosts", None, None)),
+                ("../notes.md".to_string(), file("/work/../notes.md", None, None)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_ignores_ordinary_words() {
+        assert!(targets("e.g. this and/or that, version 1.2.3 at 12:30").is_empty());
+    }
+
+    #[test]
+    fn test_editor_commands() {
+        let path = Path::new("/src/my file.rs");
+        assert_eq!(editor_command_for("nvim", path, 4, Some(2)), "nvim +4 '/src/my file.rs'");
+        assert_eq!(editor_command_for("code --wait", path, 4, Some(2)), "code --wait -g '/src/my file.rs':4:2");
+    }
+}
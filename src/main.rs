@@ -1,49 +1,176 @@
-use iced::{executor, Application, Command, Element, Settings, Theme};
-use iced::widget::{column, container, scrollable, text_input, button, row};
+use iced::{executor, Alignment, Application, Command, Element, Settings, Subscription, Theme};
+use iced::widget::{button, column, pane_grid, pick_list, row, scrollable, text, text_input};
+use std::collections::{HashMap, HashSet};
 use std::path::PathBuf;
-use tokio::sync::mpsc;
+use std::sync::Arc;
+use tokio::sync::{mpsc, Mutex};
 use uuid::Uuid;
 
 mod block;
+mod config;
+mod diagnostics;
+mod export;
+mod headless;
 mod shell;
 mod input;
+mod links;
+mod performance;
 mod renderer;
+mod pane;
+mod preview;
+mod pty;
+mod search;
+mod shell_integration;
+mod terminal;
+mod wheel;
 
 use block::{Block, BlockContent};
-use shell::ShellManager;
+use config::{Action, AppConfig, HyperlinkBehavior, SessionState, StartupBehavior, TabBarVisibility, ThemeConfig, YamlThemeManager};
+use shell::{CommandEvent, ShellManager};
 use input::EnhancedTextInput;
+use export::{CopyTarget, ExportFormat};
+use links::LinkTarget;
+use pane::{profile_theme, Pane, SelectionMode, Tab, PANE_PADDING};
+use pty::Signal;
+use renderer::PerformanceMonitor;
+use search::{SearchMode, SearchScope};
+use terminal::input::{MouseAction, MouseButton};
+
+/// Font metrics used for command output, needed to map pixels to terminal rows/cols
+pub const OUTPUT_FONT_SIZE: f32 = 12.0;
+pub const OUTPUT_LINE_HEIGHT: f32 = 1.3;
+/// Padding around everything in the window
+const WINDOW_PADDING: f32 = 16.0;
+/// Height of the tab bar, or of the close confirmation shown in its place
+const TAB_BAR_HEIGHT: f32 = 36.0;
+/// Gap between the tab bar and the panes, and between split panes
+const SPACING: f32 = 8.0;
+/// Horizontal padding blocks add around their output
+const BLOCK_INSET: f32 = 24.0;
 
 #[derive(Debug, Clone)]
 pub struct NeoTerm {
-    blocks: Vec<Block>,
-    current_input: String,
+    tabs: Vec<Tab>,
+    active_tab: usize,
+    config: AppConfig,
+    /// YAML themes, loaded once for the profiles' themes
+    themes: Option<YamlThemeManager>,
     input_history: Vec<String>,
-    history_index: Option<usize>,
     shell_manager: ShellManager,
+    /// Commands still streaming output, keyed by block id
+    running: HashMap<Uuid, RunningCommand>,
+    /// A close that would end running commands, waiting for the user to confirm it
+    pending_close: Option<CloseTarget>,
+    window_size: iced::Size,
     input_state: text_input::State,
-    suggestions: Vec<String>,
-    active_suggestion: Option<usize>,
+    /// Last cell the pointer was over in full-screen mode, as (column, row)
+    mouse_cell: (usize, usize),
+    mouse_button: Option<MouseButton>,
+    keyboard_modifiers: iced::keyboard::Modifiers,
+    /// The performance overlay is shown, and the CPU is being profiled for flamegraphs
+    show_performance: bool,
+    performance: PerformanceMonitor,
+    last_frame: Option<std::time::Instant>,
+    /// Time spent in `view` since the last frame, while the performance overlay is shown
+    view_time: std::cell::Cell<std::time::Duration>,
+    /// What became of the last flamegraph saved, shown in the overlay
+    performance_status: Option<String>,
+}
+
+/// A command whose output is still streaming into its block
+#[derive(Debug, Clone)]
+struct RunningCommand {
+    pane_id: Uuid,
+    session_id: Uuid,
+    events: Arc<Mutex<mpsc::Receiver<CommandEvent>>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum CloseTarget {
+    Window,
+    Tab(Uuid),
+    /// A pane of the tab with the given id
+    Pane(Uuid, pane_grid::Pane),
 }
 
 #[derive(Debug, Clone)]
 pub enum Message {
-    InputChanged(String),
-    ExecuteCommand,
-    CommandOutput(String, i32), // output, exit_code
-    KeyPressed(iced::keyboard::Key),
+    InputChanged(pane_grid::Pane, String),
+    ExecuteCommand(pane_grid::Pane),
+    CommandProgress(Uuid, CommandEvent), // block, event
+    /// Why a finished command failed, worked out off the UI thread
+    Diagnosed(Uuid, Option<diagnostics::Diagnosis>),
+    KeyPressed(iced::keyboard::Key, iced::keyboard::Modifiers),
     HistoryUp,
     HistoryDown,
     SuggestionSelected(usize),
     BlockAction(Uuid, BlockMessage),
+    WindowResized(u32, u32),
+    WindowCloseRequested,
+    /// Keyboard or mouse input for a full-screen program
+    TerminalEvent(iced::Event),
+    /// Ctrl-C / Ctrl-Z outside a text field, aimed at the most recent running command of the focused pane
+    SignalRunning(Signal),
+    Action(Action),
+    SelectTab(Uuid),
+    CloseTab(Uuid),
+    PaneClicked(pane_grid::Pane),
+    PaneResized(pane_grid::ResizeEvent),
+    ConfirmClose,
+    CancelClose,
+    /// Where an export was saved, or `None` if saving it was cancelled
+    ExportFinished(Result<Option<PathBuf>, String>),
+    ModifiersChanged(iced::keyboard::Modifiers),
+    Find(pane_grid::Pane, FindMessage),
+    /// A pane's block list moved or was resized
+    Scrolled(pane_grid::Pane, scrollable::Viewport),
+    /// A frame was drawn, while the performance overlay is shown
+    Frame(std::time::Instant),
     Tick,
 }
 
 #[derive(Debug, Clone)]
 pub enum BlockMessage {
-    Copy,
+    Copy(CopyTarget),
     Rerun,
     Delete,
-    Export,
+    Export(ExportFormat),
+    Signal(Signal),
+    InputChanged(String),
+    /// Send the typed line to the running command's stdin
+    SubmitInput,
+    ToggleCollapsed,
+    /// Show long output in full, or go back to its head and tail
+    ToggleFullOutput,
+    /// A click on the block; Shift extends the selection to it and Ctrl adds or removes it
+    Select,
+    /// Fold or unfold a list or map of a JSON or YAML preview, given by its JSON pointer
+    ToggleFold(String),
+    /// Put an error's suggested command in the pane's input, to be checked before it runs
+    UseSuggestion(String),
+    TogglePinned,
+    ToggleBookmark,
+    /// Start writing or changing the block's note
+    EditAnnotation,
+    AnnotationChanged(String),
+    SaveAnnotation,
+    /// Move the block cursor to the block and scroll it into view, e.g. from the bookmarks list
+    JumpTo,
+    /// A click on a URL, path or hyperlink in the output
+    OpenLink(LinkTarget),
+}
+
+/// Input from a pane's find bar
+#[derive(Debug, Clone)]
+pub enum FindMessage {
+    QueryChanged(String),
+    ModeSelected(SearchMode),
+    ScopeSelected(SearchScope),
+    /// Show only the blocks with matches, or all blocks again
+    FilterBlocks(bool),
+    Next,
+    Previous,
+    Close,
 }
 
 impl Application for NeoTerm {
@@ -53,21 +180,21 @@ impl Application for NeoTerm {
     type Flags = ();
 
     fn new(_flags: ()) -> (Self, Command<Message>) {
-        let shell_manager = ShellManager::new();
-        
-        (
-            Self {
-                blocks: Vec::new(),
-                current_input: String::new(),
-                input_history: Vec::new(),
-                history_index: None,
-                shell_manager,
-                input_state: text_input::State::new(),
-                suggestions: Vec::new(),
-                active_suggestion: None,
-            },
-            Command::none(),
-        )
+        let config = AppConfig::load().unwrap_or_else(|e| {
+            eprintln!("Failed to load config, using defaults: {}", e);
+            AppConfig::default()
+        });
+        // Incognito windows neither restore nor leave behind any history
+        let saved = if config.preferences.privacy.incognito_mode {
+            None
+        } else {
+            SessionState::load().unwrap_or_else(|e| {
+                eprintln!("Failed to load the last session: {}", e);
+                None
+            })
+        };
+
+        (Self::with_config(config, saved), Command::none())
     }
 
     fn title(&self) -> String {
@@ -76,95 +203,991 @@ impl Application for NeoTerm {
 
     fn update(&mut self, message: Message) -> Command<Message> {
         match message {
-            Message::InputChanged(input) => {
-                self.current_input = input.clone();
-                self.suggestions = self.generate_suggestions(&input);
+            Message::InputChanged(handle, input) => {
+                let suggestions = self.generate_suggestions(&input);
+                if let Some(pane) = self.pane_mut(handle) {
+                    pane.current_input = input;
+                    pane.suggestions = suggestions;
+                }
                 Command::none()
             }
-            Message::ExecuteCommand => {
-                if !self.current_input.trim().is_empty() {
-                    let command = self.current_input.clone();
+            Message::ExecuteCommand(handle) => {
+                let Some(pane) = self.pane_mut(handle) else {
+                    return Command::none();
+                };
+                if !pane.current_input.trim().is_empty() {
+                    let command = std::mem::take(&mut pane.current_input);
+                    pane.history_index = None;
+                    let pane_id = pane.id;
                     self.input_history.push(command.clone());
-                    self.history_index = None;
-                    
-                    // Create new command block
-                    let block = Block::new_command(command.clone());
-                    self.blocks.push(block);
-                    self.current_input.clear();
-                    
-                    // Execute command asynchronously
-                    Command::perform(
-                        self.shell_manager.execute_command(command),
-                        |(output, exit_code)| Message::CommandOutput(output, exit_code)
-                    )
-                } else {
-                    Command::none()
+
+                    match preview::builtin_path(&command) {
+                        Some(path) => self.preview_file(pane_id, path),
+                        None => self.run_command(pane_id, command, None),
+                    }
                 }
+                Command::none()
             }
-            Message::CommandOutput(output, exit_code) => {
-                if let Some(last_block) = self.blocks.last_mut() {
-                    last_block.set_output(output, exit_code);
+            Message::CommandProgress(block_id, event) => {
+                match event {
+                    CommandEvent::Output(bytes) => {
+                        self.performance.record_pty_output(bytes.len());
+                        if let Some(block) = self.block_mut(block_id) {
+                            block.append_output(&bytes);
+
+                            // Answer status queries such as cursor position reports
+                            let responses = block
+                                .terminal_mut()
+                                .map(|terminal| terminal.take_responses())
+                                .unwrap_or_default();
+                            if !responses.is_empty() {
+                                block.send_input(&responses);
+                            }
+                        }
+                        if let Some(pane) = self.pane_of_block_mut(block_id) {
+                            pane.mark_search_stale(block_id);
+                        }
+                    }
+                    CommandEvent::Failed(diagnosis) => {
+                        if let Some(pane) = self.pane_of_block_mut(block_id) {
+                            pane.insert_after(block_id, Block::from_diagnosis(diagnosis));
+                        }
+                    }
+                    CommandEvent::Finished(result) => {
+                        let Some(running) = self.running.remove(&block_id) else {
+                            return Command::none();
+                        };
+                        if let Some(working_dir) = result.working_dir {
+                            self.shell_manager.set_working_dir(&running.session_id, working_dir);
+                        }
+                        if let Some(environment) = result.environment {
+                            self.shell_manager.set_environment(&running.session_id, environment);
+                        }
+                        let mut diagnose = Command::none();
+                        if let Some(pane) = self.pane_of_block_mut(block_id) {
+                            if let Some(diagnosis) = pane.block_mut(block_id).and_then(|block| {
+                                block.finish(result.exit_code, result.usage);
+                                block.diagnose()
+                            }) {
+                                diagnose = Command::perform(diagnosis, move |diagnosis| Message::Diagnosed(block_id, diagnosis));
+                            }
+                            pane.update_block_search(block_id);
+                        }
+                        // The block may have been deleted while it ran; its pane's queue still moves on
+                        self.run_next_queued(running.pane_id);
+                        return diagnose;
+                    }
+                }
+                Command::none()
+            }
+            Message::Diagnosed(block_id, diagnosis) => {
+                if let (Some(diagnosis), Some(pane)) = (diagnosis, self.pane_of_block_mut(block_id)) {
+                    pane.insert_after(block_id, Block::from_diagnosis(diagnosis));
                 }
                 Command::none()
             }
             Message::HistoryUp => {
-                if !self.input_history.is_empty() {
-                    let new_index = match self.history_index {
-                        None => Some(self.input_history.len() - 1),
+                let history = &self.input_history;
+                let focused = self.tabs.get_mut(self.active_tab).and_then(Tab::focused_mut);
+                if let (Some(pane), false) = (focused, history.is_empty()) {
+                    let new_index = match pane.history_index {
+                        None => Some(history.len() - 1),
                         Some(i) if i > 0 => Some(i - 1),
                         Some(i) => Some(i),
                     };
                     
                     if let Some(index) = new_index {
-                        self.current_input = self.input_history[index].clone();
-                        self.history_index = new_index;
+                        pane.current_input = history[index].clone();
+                        pane.history_index = new_index;
                     }
                 }
                 Command::none()
             }
             Message::HistoryDown => {
-                match self.history_index {
-                    Some(i) if i < self.input_history.len() - 1 => {
-                        self.history_index = Some(i + 1);
-                        self.current_input = self.input_history[i + 1].clone();
-                    }
-                    Some(_) => {
-                        self.history_index = None;
-                        self.current_input.clear();
+                let history = &self.input_history;
+                if let Some(pane) = self.tabs.get_mut(self.active_tab).and_then(Tab::focused_mut) {
+                    match pane.history_index {
+                        Some(i) if i + 1 < history.len() => {
+                            pane.history_index = Some(i + 1);
+                            pane.current_input = history[i + 1].clone();
+                        }
+                        Some(_) => {
+                            pane.history_index = None;
+                            pane.current_input.clear();
+                        }
+                        None => {}
                     }
-                    None => {}
                 }
                 Command::none()
             }
             Message::BlockAction(block_id, action) => {
                 self.handle_block_action(block_id, action)
             }
+            Message::WindowResized(width, height) => {
+                self.window_size = iced::Size::new(width as f32, height as f32);
+                self.resize_panes();
+                Command::none()
+            }
+            Message::WindowCloseRequested => self.request_close(CloseTarget::Window),
+            Message::TerminalEvent(event) => {
+                self.forward_terminal_event(event);
+                Command::none()
+            }
+            Message::SignalRunning(signal) => {
+                // Full-screen programs already got the key itself as a control character
+                if self.fullscreen_block().is_some() {
+                    return Command::none();
+                }
+                if let Some(pane) = self.tabs.get_mut(self.active_tab).and_then(Tab::focused_mut) {
+                    if let Some(block) = pane.blocks.iter_mut().rev().find(|b| b.is_running()) {
+                        block.send_signal(signal);
+                    }
+                }
+                Command::none()
+            }
+            Message::KeyPressed(key, modifiers) => {
+                if self.fullscreen_block().is_some() {
+                    return Command::none();
+                }
+                match self.config.keybindings.action_for(&key, modifiers).cloned() {
+                    Some(action) => self.perform_action(action),
+                    None => Command::none(),
+                }
+            }
+            Message::Action(action) => self.perform_action(action),
+            Message::SelectTab(id) => {
+                if let Some(index) = self.tabs.iter().position(|tab| tab.id == id) {
+                    self.active_tab = index;
+                }
+                Command::none()
+            }
+            Message::CloseTab(id) => self.request_close(CloseTarget::Tab(id)),
+            Message::PaneClicked(handle) => {
+                if let Some(tab) = self.tabs.get_mut(self.active_tab) {
+                    tab.focus = handle;
+                }
+                Command::none()
+            }
+            Message::PaneResized(pane_grid::ResizeEvent { split, ratio }) => {
+                if let Some(tab) = self.tabs.get_mut(self.active_tab) {
+                    tab.panes.resize(split, ratio);
+                }
+                self.resize_panes();
+                Command::none()
+            }
+            Message::ConfirmClose => match self.pending_close.take() {
+                Some(target) => self.close(target),
+                None => Command::none(),
+            },
+            Message::CancelClose => {
+                self.pending_close = None;
+                self.resize_panes();
+                Command::none()
+            }
+            Message::ExportFinished(result) => {
+                if let Err(e) = result {
+                    eprintln!("Failed to export blocks: {}", e);
+                }
+                Command::none()
+            }
+            Message::ModifiersChanged(modifiers) => {
+                self.keyboard_modifiers = modifiers;
+                Command::none()
+            }
+            Message::Find(handle, message) => {
+                let preferences = self.config.preferences.terminal.clone();
+                match self.pane_mut(handle) {
+                    Some(pane) => pane.update_find(message, &preferences),
+                    None => Command::none(),
+                }
+            }
+            Message::Scrolled(handle, viewport) => {
+                if let Some(pane) = self.pane_mut(handle) {
+                    pane.scrolled(viewport);
+                }
+                Command::none()
+            }
+            Message::Frame(now) => {
+                for pane in self.tabs.iter_mut().flat_map(|tab| tab.panes_mut()) {
+                    pane.refresh_search();
+                }
+                if self.show_performance {
+                    let view_time = self.view_time.take();
+                    if let Some(last_frame) = self.last_frame.replace(now) {
+                        self.performance.record_frame_interval(now.duration_since(last_frame));
+                        self.performance.record_frame_time(view_time);
+                    }
+                }
+                Command::none()
+            }
+            Message::Tick => {
+                if self.show_performance {
+                    if let Some(bytes) = performance::resident_memory() {
+                        self.performance.record_memory_usage(bytes);
+                    }
+                    self.performance.sample_pty_throughput();
+                    performance::roll_profile();
+                }
+                Command::none()
+            }
             _ => Command::none(),
         }
     }
 
-    fn view(&self) -> Element<Message> {
-        let blocks_view = scrollable(
-            column(
-                self.blocks
-                    .iter()
-                    .map(|block| block.view())
-                    .collect::<Vec<_>>()
-            )
-            .spacing(8)
+    fn subscription(&self) -> Subscription<Message> {
+        let window_events = iced::event::listen_with(|event, status| match event {
+            iced::Event::Window(_, iced::window::Event::Resized { width, height }) => {
+                Some(Message::WindowResized(width, height))
+            }
+            iced::Event::Window(_, iced::window::Event::CloseRequested) => Some(Message::WindowCloseRequested),
+            iced::Event::Keyboard(iced::keyboard::Event::ModifiersChanged(modifiers)) => {
+                Some(Message::ModifiersChanged(modifiers))
+            }
+            iced::Event::Keyboard(iced::keyboard::Event::KeyPressed { key, modifiers, .. }) => {
+                // Text fields keep Ctrl-C for copying; elsewhere it behaves like a terminal
+                if status == iced::event::Status::Ignored && modifiers.control() && !modifiers.shift() {
+                    match key.as_ref() {
+                        iced::keyboard::Key::Character("c") => return Some(Message::SignalRunning(Signal::Interrupt)),
+                        iced::keyboard::Key::Character("z") => return Some(Message::SignalRunning(Signal::Stop)),
+                        _ => {}
+                    }
+                }
+                Some(Message::KeyPressed(key, modifiers))
+            }
+            _ => None,
+        });
+
+        let command_output = self
+            .running
+            .iter()
+            .map(|(block_id, running)| Self::command_output(*block_id, running.events.clone()));
+
+        // Full-screen programs receive all keyboard and mouse input
+        let terminal_events = self
+            .fullscreen_block()
+            .map(|_| iced::event::listen_with(terminal_event));
+
+        // Redraw running blocks every second so their elapsed time stays current, and sample the overlay's stats
+        let elapsed_timer = (!self.running.is_empty() || self.show_performance)
+            .then(|| iced::time::every(std::time::Duration::from_secs(1)).map(|_| Message::Tick));
+
+        // Streamed output is searched once per frame rather than once per chunk
+        let search_is_stale = self
+            .tabs
+            .iter()
+            .flat_map(|tab| tab.panes())
+            .any(Pane::search_is_stale);
+        let frames = (self.show_performance || search_is_stale).then(|| iced::window::frames().map(Message::Frame));
+
+        Subscription::batch(
+            std::iter::once(window_events)
+                .chain(terminal_events)
+                .chain(elapsed_timer)
+                .chain(frames)
+                .chain(command_output)
         )
-        .height(iced::Length::Fill);
+    }
 
-        let input_view = self.create_input_view();
+    fn view(&self) -> Element<Message> {
+        let started = std::time::Instant::now();
+        let mut content = column![].spacing(SPACING).padding(WINDOW_PADDING);
 
-        column![blocks_view, input_view]
-            .spacing(8)
-            .padding(16)
-            .into()
+        if let Some(target) = self.pending_close {
+            content = content.push(Self::confirm_close_view(target));
+        } else if self.tab_bar_visible() {
+            content = content.push(self.tab_bar_view());
+        }
+
+        if self.show_performance {
+            let blocks = self.tabs.iter().flat_map(Tab::panes).map(|pane| pane.blocks.len()).sum();
+            content = content.push(performance::overlay_view(
+                &self.performance,
+                blocks,
+                self.performance_status.as_deref(),
+            ));
+        }
+
+        if let Some(tab) = self.tabs.get(self.active_tab) {
+            let theme = &self.config.theme;
+            let preferences = &self.config.preferences.terminal;
+            let focus = tab.focus;
+            let panes = pane_grid::PaneGrid::new(&tab.panes, move |handle, pane, _maximized| {
+                pane_grid::Content::new(pane.view(handle, theme, preferences, handle == focus))
+            })
+            .on_click(Message::PaneClicked)
+            .on_resize(10, Message::PaneResized)
+            .spacing(SPACING);
+
+            content = content.push(panes);
+        }
+
+        if self.show_performance {
+            self.view_time.set(self.view_time.get() + started.elapsed());
+        }
+        content.into()
     }
 }
 
 impl NeoTerm {
+    /// The app for a loaded configuration, reopening the saved session if there is one and the
+    /// preferences ask for it
+    fn with_config(config: AppConfig, saved: Option<SessionState>) -> Self {
+        renderer::block_renderer()
+            .lock()
+            .unwrap()
+            .apply_preferences(&config.preferences.performance);
+
+        let mut shell_manager = ShellManager::new(&config.preferences.general);
+        if let Some(dir) = saved.as_ref().and_then(|state| state.last_working_dir.clone()) {
+            shell_manager.set_last_used_dir(dir);
+        }
+
+        let mut app = Self {
+            tabs: Vec::new(),
+            active_tab: 0,
+            config,
+            themes: YamlThemeManager::new()
+                .map_err(|e| eprintln!("Failed to load themes: {}", e))
+                .ok(),
+            input_history: Vec::new(),
+            shell_manager,
+            running: HashMap::new(),
+            pending_close: None,
+            window_size: iced::window::Settings::default().size,
+            input_state: text_input::State::new(),
+            mouse_cell: (0, 0),
+            mouse_button: None,
+            keyboard_modifiers: iced::keyboard::Modifiers::default(),
+            show_performance: false,
+            performance: PerformanceMonitor::new(),
+            last_frame: None,
+            view_time: std::cell::Cell::default(),
+            performance_status: None,
+        };
+        if let Some(state) = saved {
+            app.restore_session(state);
+        }
+        if app.tabs.is_empty() {
+            app.open_tab(None);
+        }
+
+        app
+    }
+
+    /// Open a tab with a single pane on a fresh shell session and switch to it
+    fn open_tab(&mut self, profile: Option<&str>) {
+        let profile = profile.and_then(|name| self.config.profile(name));
+        let theme = profile_theme(&mut self.themes, profile);
+        let pane = Pane::open(&mut self.shell_manager, profile, theme);
+        let pane_id = pane.id;
+
+        self.tabs.push(Tab::new(pane));
+        self.active_tab = self.tabs.len() - 1;
+        self.resize_panes();
+        self.run_startup_script(pane_id);
+    }
+
+    /// Split the focused pane of the active tab; the new pane gets its own session with the same profile
+    fn split(&mut self, axis: pane_grid::Axis) {
+        let Some(tab) = self.tabs.get_mut(self.active_tab) else {
+            return;
+        };
+        let profile = tab
+            .focused()
+            .and_then(|pane| pane.profile.as_deref())
+            .and_then(|name| self.config.profile(name));
+        let theme = profile_theme(&mut self.themes, profile);
+        let pane = Pane::open(&mut self.shell_manager, profile, theme);
+        let (pane_id, session_id) = (pane.id, pane.session_id);
+
+        match tab.panes.split(axis, tab.focus, pane) {
+            Some((handle, _)) => tab.focus = handle,
+            None => {
+                self.shell_manager.close_session(&session_id);
+                return;
+            }
+        }
+        self.resize_panes();
+        self.run_startup_script(pane_id);
+    }
+
+    /// Run the startup script of the profile a new pane was opened with
+    fn run_startup_script(&mut self, pane_id: Uuid) {
+        let script = self
+            .tabs
+            .iter()
+            .flat_map(|tab| tab.panes())
+            .find(|pane| pane.id == pane_id)
+            .and_then(|pane| pane.profile.as_deref())
+            .and_then(|name| self.config.profile(name))
+            .and_then(|profile| profile.startup_script.clone())
+            .filter(|script| !script.trim().is_empty());
+
+        if let Some(script) = script {
+            self.run_command(pane_id, script, None);
+        }
+    }
+
+    fn perform_action(&mut self, action: Action) -> Command<Message> {
+        match action {
+            Action::NewTab => self.open_tab(None),
+            Action::NewTabWithProfile(name) => {
+                if self.config.profile(&name).is_some() {
+                    self.open_tab(Some(&name));
+                } else {
+                    eprintln!("No shell profile named {}", name);
+                }
+            }
+            Action::CloseTab => {
+                if let Some(tab) = self.tabs.get(self.active_tab) {
+                    return self.request_close(CloseTarget::Tab(tab.id));
+                }
+            }
+            Action::NextTab if !self.tabs.is_empty() => {
+                self.active_tab = (self.active_tab + 1) % self.tabs.len();
+            }
+            Action::PreviousTab if !self.tabs.is_empty() => {
+                self.active_tab = (self.active_tab + self.tabs.len() - 1) % self.tabs.len();
+            }
+            Action::SplitHorizontal => self.split(pane_grid::Axis::Horizontal),
+            Action::SplitVertical => self.split(pane_grid::Axis::Vertical),
+            Action::CloseSplit => {
+                if let Some(tab) = self.tabs.get(self.active_tab) {
+                    return self.request_close(CloseTarget::Pane(tab.id, tab.focus));
+                }
+            }
+            Action::ToggleBlockCollapsed => {
+                let block = self
+                    .tabs
+                    .get_mut(self.active_tab)
+                    .and_then(Tab::focused_mut)
+                    .and_then(Pane::current_block_mut);
+                if let Some(block) = block {
+                    block.collapsed = !block.collapsed;
+                }
+            }
+            Action::TogglePinBlock | Action::ToggleBookmarkBlock | Action::AnnotateBlock => {
+                let block_id = self
+                    .tabs
+                    .get_mut(self.active_tab)
+                    .and_then(Tab::focused_mut)
+                    .and_then(Pane::current_block_mut)
+                    .map(|block| block.id);
+                let message = match action {
+                    Action::TogglePinBlock => BlockMessage::TogglePinned,
+                    Action::ToggleBookmarkBlock => BlockMessage::ToggleBookmark,
+                    _ => BlockMessage::EditAnnotation,
+                };
+                if let Some(block_id) = block_id {
+                    return self.handle_block_action(block_id, message);
+                }
+            }
+            Action::ToggleBookmarks | Action::NextBookmark | Action::PreviousBookmark => {
+                let Some(pane) = self.tabs.get_mut(self.active_tab).and_then(Tab::focused_mut) else {
+                    return Command::none();
+                };
+                return match action {
+                    Action::ToggleBookmarks => {
+                        pane.show_bookmarks = !pane.show_bookmarks;
+                        Command::none()
+                    }
+                    Action::NextBookmark => pane.step_bookmark(1, &self.config.preferences.terminal),
+                    _ => pane.step_bookmark(-1, &self.config.preferences.terminal),
+                };
+            }
+            Action::Find | Action::FindNext | Action::FindPrevious | Action::CloseFind => {
+                let Some(pane) = self.tabs.get_mut(self.active_tab).and_then(Tab::focused_mut) else {
+                    return Command::none();
+                };
+                return match action {
+                    Action::Find => pane.open_search(),
+                    Action::FindNext => pane.update_find(FindMessage::Next, &self.config.preferences.terminal),
+                    Action::FindPrevious => pane.update_find(FindMessage::Previous, &self.config.preferences.terminal),
+                    _ => pane.update_find(FindMessage::Close, &self.config.preferences.terminal),
+                };
+            }
+            Action::CollapseAllBlocks | Action::ExpandAllBlocks => {
+                let collapsed = matches!(action, Action::CollapseAllBlocks);
+                if let Some(pane) = self.tabs.get_mut(self.active_tab).and_then(Tab::focused_mut) {
+                    for block in &mut pane.blocks {
+                        block.collapsed = collapsed;
+                    }
+                }
+            }
+            Action::FocusPreviousBlock
+            | Action::FocusNextBlock
+            | Action::SelectPreviousBlock
+            | Action::SelectNextBlock => {
+                let offset = match action {
+                    Action::FocusPreviousBlock | Action::SelectPreviousBlock => -1,
+                    _ => 1,
+                };
+                let mode = match action {
+                    Action::SelectPreviousBlock | Action::SelectNextBlock => SelectionMode::Range,
+                    _ => SelectionMode::Replace,
+                };
+                if let Some(pane) = self.tabs.get_mut(self.active_tab).and_then(Tab::focused_mut) {
+                    pane.move_block_focus(offset, mode);
+                }
+            }
+            Action::ToggleBlockSelection => {
+                if let Some(pane) = self.tabs.get_mut(self.active_tab).and_then(Tab::focused_mut) {
+                    pane.move_block_focus(0, SelectionMode::Toggle);
+                }
+            }
+            Action::ClearBlockSelection => {
+                if let Some(pane) = self.tabs.get_mut(self.active_tab).and_then(Tab::focused_mut) {
+                    pane.clear_selection();
+                }
+            }
+            Action::CopySelectedBlocks(target) => {
+                if let Some((pane_id, ids)) = self.selection_targets() {
+                    self.copy_blocks(pane_id, &ids, target);
+                }
+            }
+            Action::ExportSelectedBlocks(format) => {
+                if let Some((pane_id, ids)) = self.selection_targets() {
+                    return self.export_blocks(pane_id, &ids, format);
+                }
+            }
+            Action::ShareSelectedBlocks => {
+                if let Some((pane_id, ids)) = self.selection_targets() {
+                    self.share_blocks(pane_id, &ids);
+                }
+            }
+            Action::RerunSelectedBlocks => {
+                if let Some((pane_id, ids)) = self.selection_targets() {
+                    self.rerun_blocks(pane_id, &ids);
+                }
+            }
+            Action::DeleteSelectedBlocks => {
+                if let Some((pane_id, ids)) = self.selection_targets() {
+                    self.delete_blocks(pane_id, &ids);
+                }
+            }
+            Action::TogglePerformanceOverlay => {
+                self.show_performance = !self.show_performance;
+                self.performance = PerformanceMonitor::new();
+                self.last_frame = None;
+                self.view_time.take();
+                self.performance_status = None;
+                if self.show_performance {
+                    let window = std::time::Duration::from_secs(self.config.preferences.performance.profile_window);
+                    if let Err(e) = performance::start_profiling(window) {
+                        self.performance_status = Some(format!("Profiling unavailable: {}", e));
+                    }
+                } else {
+                    performance::stop_profiling();
+                }
+                self.resize_panes();
+            }
+            Action::SaveFlamegraph if self.show_performance => {
+                let saved = performance::flamegraph_path()
+                    .ok_or_else(|| "No data directory to save it in".to_string())
+                    .and_then(|path| performance::dump_flamegraph(&path).map(|()| path));
+                self.performance_status = Some(match saved {
+                    Ok(path) => format!("Flamegraph saved to {}", path.display()),
+                    Err(e) => format!("Couldn't save flamegraph: {}", e),
+                });
+            }
+            _ => {}
+        }
+        Command::none()
+    }
+
+    /// Close a tab or pane, first asking for confirmation if that would end running commands
+    fn request_close(&mut self, target: CloseTarget) -> Command<Message> {
+        let has_running_commands = match target {
+            CloseTarget::Window => self.tabs.iter().any(Tab::has_running_commands),
+            CloseTarget::Tab(id) => self
+                .tabs
+                .iter()
+                .find(|tab| tab.id == id)
+                .map_or(false, Tab::has_running_commands),
+            CloseTarget::Pane(tab_id, handle) => self
+                .tabs
+                .iter()
+                .find(|tab| tab.id == tab_id)
+                .and_then(|tab| tab.panes.get(handle))
+                .map_or(false, Pane::has_running_commands),
+        };
+
+        if has_running_commands && self.config.preferences.terminal.confirm_before_closing {
+            self.pending_close = Some(target);
+            self.resize_panes();
+            return Command::none();
+        }
+        self.close(target)
+    }
+
+    /// Close a tab or pane and end its shell sessions; closing the last tab closes the window
+    fn close(&mut self, target: CloseTarget) -> Command<Message> {
+        match target {
+            CloseTarget::Window => return self.exit(),
+            CloseTarget::Tab(id) => {
+                let Some(index) = self.tabs.iter().position(|tab| tab.id == id) else {
+                    return Command::none();
+                };
+                let mut tab = self.tabs.remove(index);
+                for pane in tab.panes_mut() {
+                    Self::end_session(&mut self.shell_manager, &mut self.running, pane);
+                }
+
+                if self.tabs.is_empty() {
+                    return self.exit();
+                }
+                if self.active_tab > index || self.active_tab == self.tabs.len() {
+                    self.active_tab -= 1;
+                }
+            }
+            CloseTarget::Pane(tab_id, handle) => {
+                let Some(tab) = self.tabs.iter_mut().find(|tab| tab.id == tab_id) else {
+                    return Command::none();
+                };
+                if tab.panes.len() == 1 {
+                    return self.close(CloseTarget::Tab(tab_id));
+                }
+
+                if let Some((mut pane, sibling)) = tab.panes.close(handle) {
+                    if tab.focus == handle {
+                        tab.focus = sibling;
+                    }
+                    Self::end_session(&mut self.shell_manager, &mut self.running, &mut pane);
+                }
+            }
+        }
+
+        self.resize_panes();
+        Command::none()
+    }
+
+    /// Save the session for the next launch and close the window
+    fn exit(&mut self) -> Command<Message> {
+        self.save_session();
+        iced::window::close(iced::window::Id::MAIN)
+    }
+
+    /// Reopen the tabs saved when NeoTerm last exited, if the preferences ask for it
+    fn restore_session(&mut self, state: SessionState) {
+        if !matches!(self.config.preferences.general.startup_behavior, StartupBehavior::RestoreLastSession) {
+            return;
+        }
+
+        self.tabs = state
+            .tabs
+            .into_iter()
+            .map(|tab| Tab::restore(tab, &mut self.shell_manager, &self.config, &mut self.themes))
+            .collect();
+        self.active_tab = state.active_tab.min(self.tabs.len().saturating_sub(1));
+        self.resize_panes();
+    }
+
+    /// Remember the layout, sessions and block history for the next launch; incognito windows leave no trace
+    fn save_session(&self) {
+        if self.config.preferences.privacy.incognito_mode {
+            return;
+        }
+
+        let scrollback_lines = self.config.preferences.terminal.scrollback_lines;
+        let state = SessionState {
+            tabs: self
+                .tabs
+                .iter()
+                .map(|tab| tab.save(&self.shell_manager, scrollback_lines))
+                .collect(),
+            active_tab: self.active_tab,
+            last_working_dir: self.shell_manager.last_used_dir().cloned(),
+        };
+        if let Err(e) = state.save() {
+            eprintln!("Failed to save the session: {}", e);
+        }
+    }
+
+    /// Stop a closed pane's commands and its shell, since nothing will show their output any more
+    fn end_session(shell_manager: &mut ShellManager, running: &mut HashMap<Uuid, RunningCommand>, pane: &mut Pane) {
+        for block in pane.blocks.iter_mut().filter(|block| block.is_running()) {
+            block.send_signal(Signal::Terminate);
+            running.remove(&block.id);
+        }
+        shell_manager.close_session(&pane.session_id);
+    }
+
+    fn tab_bar_visible(&self) -> bool {
+        match self.config.preferences.ui.show_tab_bar {
+            TabBarVisibility::Always => true,
+            TabBarVisibility::WhenMultiple => self.tabs.len() > 1,
+            TabBarVisibility::Never => false,
+        }
+    }
+
+    /// Top-left corner and size of the area the active tab's panes are laid out in
+    fn pane_area(&self) -> (iced::Point, iced::Size) {
+        let mut header = if self.pending_close.is_some() || self.tab_bar_visible() {
+            TAB_BAR_HEIGHT + SPACING
+        } else {
+            0.0
+        };
+        if self.show_performance {
+            header += performance::OVERLAY_HEIGHT + SPACING;
+        }
+
+        (
+            iced::Point::new(WINDOW_PADDING, WINDOW_PADDING + header),
+            iced::Size::new(
+                (self.window_size.width - 2.0 * WINDOW_PADDING).max(0.0),
+                (self.window_size.height - 2.0 * WINDOW_PADDING - header).max(0.0),
+            ),
+        )
+    }
+
+    /// Give every pane's session a terminal size matching the space the pane has on screen
+    fn resize_panes(&mut self) {
+        let (_, area) = self.pane_area();
+
+        for tab in &mut self.tabs {
+            let regions = tab.panes.layout().pane_regions(SPACING, area);
+            for (handle, region) in regions {
+                let Some(pane) = tab.panes.get_mut(handle) else {
+                    continue;
+                };
+                let size = pty::size_for_viewport(
+                    region.width - 2.0 * PANE_PADDING - BLOCK_INSET,
+                    region.height - 2.0 * PANE_PADDING,
+                    OUTPUT_FONT_SIZE,
+                    OUTPUT_LINE_HEIGHT,
+                );
+                self.shell_manager.resize_session(&pane.session_id, size);
+
+                // Running programs redraw for the new size, so their screens must match it
+                for block in pane.blocks.iter_mut().filter(|block| block.is_running()) {
+                    if let Some(terminal) = block.terminal_mut() {
+                        terminal.resize(size.cols as usize, size.rows as usize);
+                    }
+                    if let Some(process) = &block.process {
+                        process.resize(size);
+                    }
+                }
+            }
+        }
+    }
+
+    fn pane_mut(&mut self, handle: pane_grid::Pane) -> Option<&mut Pane> {
+        self.tabs.get_mut(self.active_tab)?.panes.get_mut(handle)
+    }
+
+    fn pane_of_block_mut(&mut self, block_id: Uuid) -> Option<&mut Pane> {
+        self.tabs
+            .iter_mut()
+            .flat_map(|tab| tab.panes_mut())
+            .find(|pane| pane.block(block_id).is_some())
+    }
+
+    fn block_mut(&mut self, id: Uuid) -> Option<&mut Block> {
+        self.tabs
+            .iter_mut()
+            .flat_map(|tab| tab.panes_mut())
+            .find_map(|pane| pane.block_mut(id))
+    }
+
+    /// Start a command in a new block of a pane's session, streaming its output into it.
+    ///
+    /// Output is routed by block id, so several commands can run at once.
+    fn run_command(&mut self, pane_id: Uuid, command: String, rerun_of: Option<Uuid>) {
+        let Some(pane) = self
+            .tabs
+            .iter_mut()
+            .flat_map(|tab| tab.panes_mut())
+            .find(|pane| pane.id == pane_id)
+        else {
+            return;
+        };
+
+        let session_id = pane.session_id;
+        let size = self.shell_manager.pty_size(&session_id);
+        // The session's directory is kept current from what the shell reports after each command
+        let working_dir = self
+            .shell_manager
+            .get_session(&session_id)
+            .map(|session| session.get_working_dir().clone())
+            .unwrap_or_default();
+        let mut block = Block::new_command(
+            command.clone(),
+            working_dir,
+            size.cols as usize,
+            size.rows as usize,
+            self.config.preferences.terminal.scrollback_lines,
+        );
+        block.rerun_of = rerun_of;
+
+        let handle = self.shell_manager.execute_command(session_id, command);
+        block.process = handle.process;
+        self.running.insert(block.id, RunningCommand {
+            pane_id,
+            session_id,
+            events: Arc::new(Mutex::new(handle.events)),
+        });
+        pane.blocks.push(block);
+    }
+
+    /// Add a preview of a file, given relative to the pane's working directory, to the pane
+    fn preview_file(&mut self, pane_id: Uuid, path: &str) {
+        let Some(pane) = self.pane_by_id(pane_id) else {
+            return;
+        };
+        let working_dir = self
+            .shell_manager
+            .get_session(&pane.session_id)
+            .map(|session| session.get_working_dir().clone())
+            .unwrap_or_default();
+
+        self.push_preview(pane_id, preview::resolve_path(path, &working_dir));
+    }
+
+    fn push_preview(&mut self, pane_id: Uuid, path: PathBuf) {
+        let Some(pane) = self.pane_by_id(pane_id) else {
+            return;
+        };
+        let block = Block::preview_file(path, pane.theme.as_ref().unwrap_or(&self.config.theme));
+        if let Some(pane) = self.pane_by_id_mut(pane_id) {
+            pane.blocks.push(block);
+            pane.update_search();
+        }
+    }
+
+    /// Open a link from a block's output: URLs and directories with the desktop's default application,
+    /// `file:line` references in the editor, and other files in a preview block
+    fn open_link(&mut self, block_id: Uuid, target: LinkTarget) {
+        let Some(pane_id) = self.pane_of_block_mut(block_id).map(|pane| pane.id) else {
+            return;
+        };
+        match target {
+            LinkTarget::Url(url) => {
+                if let Err(e) = links::open_externally(&url) {
+                    eprintln!("Failed to open {}: {}", url, e);
+                }
+            }
+            LinkTarget::File { path, .. } if !path.exists() => {
+                if let Some(pane) = self.pane_by_id_mut(pane_id) {
+                    let message = format!("No such file or directory: {}", path.display());
+                    pane.insert_after(block_id, Block::new_error(message, None));
+                    pane.update_search();
+                }
+            }
+            LinkTarget::File { path, .. } if path.is_dir() => {
+                if let Err(e) = links::open_externally(&path.to_string_lossy()) {
+                    eprintln!("Failed to open {}: {}", path.display(), e);
+                }
+            }
+            LinkTarget::File { path, line: Some(line), column } => match links::editor_command(&path, line, column) {
+                Some(command) => self.run_command(pane_id, command, None),
+                None => self.push_preview(pane_id, path),
+            },
+            LinkTarget::File { path, .. } => self.push_preview(pane_id, path),
+        }
+    }
+
+    /// The focused pane's full-screen program, if it has one
+    fn fullscreen_block(&self) -> Option<&Block> {
+        self.tabs.get(self.active_tab)?.focused()?.fullscreen_block()
+    }
+
+    /// Translate keyboard and mouse input into the bytes a terminal would send the full-screen program
+    fn forward_terminal_event(&mut self, event: iced::Event) {
+        let Some(block) = self.fullscreen_block() else {
+            return;
+        };
+        let Some(terminal) = block.terminal() else {
+            return;
+        };
+        let modes = terminal.modes.clone();
+        let (cols, rows) = (terminal.grid().width(), terminal.grid().height());
+
+        let bytes = match event {
+            iced::Event::Keyboard(iced::keyboard::Event::ModifiersChanged(modifiers)) => {
+                self.keyboard_modifiers = modifiers;
+                None
+            }
+            iced::Event::Keyboard(iced::keyboard::Event::KeyPressed { key, modifiers, text, .. }) => {
+                terminal::input::encode_key(&key, modifiers, text.as_deref(), &modes)
+            }
+            iced::Event::Mouse(event) if self.config.preferences.terminal.mouse_reporting => {
+                let action = match event {
+                    iced::mouse::Event::CursorMoved { position } => {
+                        let origin = self.focused_grid_origin();
+                        let (cell_width, cell_height) = pty::cell_size(OUTPUT_FONT_SIZE, OUTPUT_LINE_HEIGHT);
+                        let col = ((position.x - origin.x).max(0.0) / cell_width) as usize;
+                        let row = ((position.y - origin.y).max(0.0) / cell_height) as usize;
+                        let cell = (col.min(cols - 1), row.min(rows - 1));
+                        if cell == self.mouse_cell {
+                            return;
+                        }
+                        self.mouse_cell = cell;
+                        Some(MouseAction::Motion(self.mouse_button))
+                    }
+                    iced::mouse::Event::ButtonPressed(button) => mouse_button(button).map(|button| {
+                        self.mouse_button = Some(button);
+                        MouseAction::Press(button)
+                    }),
+                    iced::mouse::Event::ButtonReleased(button) => mouse_button(button).map(|button| {
+                        self.mouse_button = None;
+                        MouseAction::Release(button)
+                    }),
+                    iced::mouse::Event::WheelScrolled { delta } => {
+                        let y = match delta {
+                            iced::mouse::ScrollDelta::Lines { y, .. } | iced::mouse::ScrollDelta::Pixels { y, .. } => y,
+                        };
+                        if y > 0.0 {
+                            Some(MouseAction::ScrollUp)
+                        } else if y < 0.0 {
+                            Some(MouseAction::ScrollDown)
+                        } else {
+                            None
+                        }
+                    }
+                    _ => None,
+                };
+
+                let (col, row) = self.mouse_cell;
+                action.and_then(|action| {
+                    terminal::input::encode_mouse(action, col, row, self.keyboard_modifiers, &modes)
+                })
+            }
+            _ => None,
+        };
+
+        if let (Some(bytes), Some(block)) = (bytes, self.fullscreen_block()) {
+            block.send_input(&bytes);
+        }
+    }
+
+    /// Window position of the top-left cell of the focused pane's full-screen grid
+    fn focused_grid_origin(&self) -> iced::Point {
+        let (origin, area) = self.pane_area();
+        let region = self.tabs.get(self.active_tab).and_then(|tab| {
+            tab.panes.layout().pane_regions(SPACING, area).remove(&tab.focus)
+        });
+
+        match region {
+            Some(region) => iced::Point::new(
+                origin.x + region.x + PANE_PADDING,
+                origin.y + region.y + PANE_PADDING,
+            ),
+            None => origin,
+        }
+    }
+
+    /// Deliver a running command's output events as messages for its block
+    fn command_output(block_id: Uuid, events: Arc<Mutex<mpsc::Receiver<CommandEvent>>>) -> Subscription<Message> {
+        iced::subscription::unfold(block_id, events, move |events| async move {
+            let event = events.lock().await.recv().await;
+            match event {
+                Some(event) => (Message::CommandProgress(block_id, event), events),
+                // Senders close only after `Finished`, which already ended this subscription
+                None => std::future::pending().await,
+            }
+        })
+    }
+
     fn generate_suggestions(&self, input: &str) -> Vec<String> {
         // Implement fuzzy matching for commands, files, etc.
         let mut suggestions = Vec::new();
@@ -188,61 +1211,459 @@ impl NeoTerm {
         suggestions
     }
 
-    fn create_input_view(&self) -> Element<Message> {
-        let input = text_input("Enter command...", &self.current_input)
-            .on_input(Message::InputChanged)
-            .on_submit(Message::ExecuteCommand)
-            .padding(12)
-            .size(16);
-
-        let suggestions_view = if !self.suggestions.is_empty() {
-            column(
-                self.suggestions
-                    .iter()
-                    .enumerate()
-                    .map(|(i, suggestion)| {
-                        button(text(suggestion))
-                            .on_press(Message::SuggestionSelected(i))
-                            .width(iced::Length::Fill)
-                            .into()
-                    })
-                    .collect::<Vec<_>>()
-            )
-            .spacing(2)
+    /// Name of the focused pane's working directory
+    fn tab_title(&self, tab: &Tab) -> String {
+        tab.focused()
+            .and_then(|pane| self.shell_manager.get_session(&pane.session_id))
+            .map(|session| {
+                let dir = session.get_working_dir();
+                dir.file_name()
+                    .map(|name| name.to_string_lossy().into_owned())
+                    .unwrap_or_else(|| dir.display().to_string())
+            })
+            .unwrap_or_else(|| "shell".to_string())
+    }
+
+    fn tab_bar_view(&self) -> Element<Message> {
+        let tabs = self.tabs.iter().enumerate().map(|(index, tab)| {
+            let title = button(text(self.tab_title(tab)).size(13))
+                .on_press(Message::SelectTab(tab.id))
+                .style(if index == self.active_tab {
+                    button::primary
+                } else {
+                    button::secondary
+                });
+            let close = button(text("×").size(13))
+                .on_press(Message::CloseTab(tab.id))
+                .style(button::secondary);
+
+            row![title, close].spacing(2).into()
+        });
+
+        let mut tab_bar = row(tabs.collect::<Vec<_>>())
+            .push(button(text("+").size(13)).on_press(Message::Action(Action::NewTab)));
+
+        if !self.config.profiles.is_empty() {
+            let names: Vec<String> = self.config.profiles.iter().map(|profile| profile.name.clone()).collect();
+            tab_bar = tab_bar.push(
+                pick_list(names, None::<String>, |name| Message::Action(Action::NewTabWithProfile(name)))
+                    .placeholder("New tab with profile…")
+                    .text_size(13),
+            );
+        }
+
+        tab_bar
+            .spacing(SPACING)
+            .height(TAB_BAR_HEIGHT)
+            .align_items(Alignment::Center)
             .into()
-        } else {
-            column![].into()
+    }
+
+    fn confirm_close_view(target: CloseTarget) -> Element<'static, Message> {
+        let prompt = match target {
+            CloseTarget::Window => "Commands are still running. Quit anyway?",
+            CloseTarget::Tab(_) => "Commands are still running in this tab. Close it anyway?",
+            CloseTarget::Pane(..) => "Commands are still running in this pane. Close it anyway?",
         };
 
-        column![input, suggestions_view].spacing(4).into()
+        row![
+            text(prompt).size(13),
+            button(text("Close").size(13))
+                .on_press(Message::ConfirmClose)
+                .style(button::danger),
+            button(text("Cancel").size(13))
+                .on_press(Message::CancelClose)
+                .style(button::secondary),
+        ]
+        .spacing(SPACING)
+        .height(TAB_BAR_HEIGHT)
+        .align_items(Alignment::Center)
+        .into()
     }
 
     fn handle_block_action(&mut self, block_id: Uuid, action: BlockMessage) -> Command<Message> {
         match action {
             BlockMessage::Rerun => {
-                if let Some(block) = self.blocks.iter().find(|b| b.id == block_id) {
-                    if let BlockContent::Command { input, .. } = &block.content {
-                        let command = input.clone();
-                        Command::perform(
-                            self.shell_manager.execute_command(command),
-                            |(output, exit_code)| Message::CommandOutput(output, exit_code)
-                        )
-                    } else {
-                        Command::none()
-                    }
-                } else {
-                    Command::none()
+                if let Some((pane_id, ids)) = self.action_targets(block_id) {
+                    self.rerun_blocks(pane_id, &ids);
                 }
+                Command::none()
             }
             BlockMessage::Delete => {
-                self.blocks.retain(|b| b.id != block_id);
+                if let Some((pane_id, ids)) = self.action_targets(block_id) {
+                    self.delete_blocks(pane_id, &ids);
+                }
+                Command::none()
+            }
+            BlockMessage::Copy(target) => {
+                if let Some((pane_id, ids)) = self.action_targets(block_id) {
+                    self.copy_blocks(pane_id, &ids, target);
+                }
+                Command::none()
+            }
+            BlockMessage::Export(format) => match self.action_targets(block_id) {
+                Some((pane_id, ids)) => self.export_blocks(pane_id, &ids, format),
+                None => Command::none(),
+            },
+            BlockMessage::Signal(signal) => {
+                if let Some(block) = self.block_mut(block_id) {
+                    block.send_signal(signal);
+                }
+                Command::none()
+            }
+            BlockMessage::InputChanged(input) => {
+                if let Some(block) = self.block_mut(block_id) {
+                    block.stdin = input;
+                }
+                Command::none()
+            }
+            BlockMessage::SubmitInput => {
+                if let Some(block) = self.block_mut(block_id) {
+                    let line = std::mem::take(&mut block.stdin);
+                    // Enter on a terminal sends a carriage return, which the tty turns into a newline
+                    block.send_input(format!("{}\r", line).as_bytes());
+                }
+                Command::none()
+            }
+            BlockMessage::ToggleCollapsed => {
+                if let Some(block) = self.block_mut(block_id) {
+                    block.collapsed = !block.collapsed;
+                }
+                Command::none()
+            }
+            BlockMessage::ToggleFullOutput => {
+                if let Some(block) = self.block_mut(block_id) {
+                    block.show_full_output = !block.show_full_output;
+                }
+                Command::none()
+            }
+            BlockMessage::ToggleFold(pointer) => {
+                if let Some(block) = self.block_mut(block_id) {
+                    if let BlockContent::FilePreview { preview: preview::Preview::Tree(tree), .. } = &mut block.content {
+                        tree.toggle(&pointer);
+                    }
+                }
+                Command::none()
+            }
+            BlockMessage::UseSuggestion(command) => match self.pane_of_block_mut(block_id) {
+                Some(pane) => pane.set_input(command),
+                None => Command::none(),
+            },
+            BlockMessage::TogglePinned | BlockMessage::ToggleBookmark => {
+                if let Some(pane) = self.pane_of_block_mut(block_id) {
+                    if let Some(block) = pane.block_mut(block_id) {
+                        match action {
+                            BlockMessage::TogglePinned => block.pinned = !block.pinned,
+                            _ => block.bookmarked = !block.bookmarked,
+                        }
+                    }
+                    pane.update_search();
+                }
+                Command::none()
+            }
+            BlockMessage::EditAnnotation => match self.block_mut(block_id) {
+                Some(block) => {
+                    block.edit_annotation();
+                    text_input::focus(block.annotation_input_id())
+                }
+                None => Command::none(),
+            },
+            BlockMessage::AnnotationChanged(annotation) => {
+                if let Some(block) = self.block_mut(block_id) {
+                    block.annotation = Some(annotation);
+                }
+                Command::none()
+            }
+            BlockMessage::SaveAnnotation => {
+                if let Some(pane) = self.pane_of_block_mut(block_id) {
+                    if let Some(block) = pane.block_mut(block_id) {
+                        block.save_annotation();
+                    }
+                    pane.update_search();
+                }
+                Command::none()
+            }
+            BlockMessage::JumpTo => {
+                let preferences = self.config.preferences.terminal.clone();
+                match self.pane_of_block_mut(block_id) {
+                    Some(pane) => pane.jump_to_block(block_id, &preferences),
+                    None => Command::none(),
+                }
+            }
+            BlockMessage::OpenLink(target) => {
+                let behavior = &self.config.preferences.terminal.hyperlink_behavior;
+                // Without Ctrl, a click on a link picks the block like a click anywhere else in it
+                if matches!(behavior, HyperlinkBehavior::CtrlClick) && !self.keyboard_modifiers.command() {
+                    return self.handle_block_action(block_id, BlockMessage::Select);
+                }
+                self.open_link(block_id, target);
+                Command::none()
+            }
+            BlockMessage::Select => {
+                let modifiers = self.keyboard_modifiers;
+                let mode = if modifiers.shift() {
+                    SelectionMode::Range
+                } else if modifiers.command() {
+                    SelectionMode::Toggle
+                } else {
+                    SelectionMode::Replace
+                };
+                if let Some(pane) = self.pane_of_block_mut(block_id) {
+                    pane.select_block(block_id, mode);
+                }
                 Command::none()
             }
-            _ => Command::none(),
         }
     }
+
+    fn pane_by_id(&self, id: Uuid) -> Option<&Pane> {
+        self.tabs.iter().flat_map(|tab| tab.panes()).find(|pane| pane.id == id)
+    }
+
+    fn pane_by_id_mut(&mut self, id: Uuid) -> Option<&mut Pane> {
+        self.tabs.iter_mut().flat_map(|tab| tab.panes_mut()).find(|pane| pane.id == id)
+    }
+
+    /// The pane holding a block and the blocks an action on it applies to:
+    /// the whole selection when the block is part of it, otherwise just the block
+    fn action_targets(&self, block_id: Uuid) -> Option<(Uuid, HashSet<Uuid>)> {
+        let pane = self
+            .tabs
+            .iter()
+            .flat_map(|tab| tab.panes())
+            .find(|pane| pane.block(block_id).is_some())?;
+        let ids = if pane.selected_blocks.contains(&block_id) {
+            pane.selected_blocks.clone()
+        } else {
+            HashSet::from([block_id])
+        };
+        Some((pane.id, ids))
+    }
+
+    /// The focused pane and the blocks the selection actions apply to
+    fn selection_targets(&self) -> Option<(Uuid, HashSet<Uuid>)> {
+        let pane = self.tabs.get(self.active_tab)?.focused()?;
+        let ids: HashSet<Uuid> = pane.selection().iter().map(|block| block.id).collect();
+        (!ids.is_empty()).then_some((pane.id, ids))
+    }
+
+    /// A pane's blocks among `ids` in display order, with the theme they are shown in
+    fn blocks_in(&self, pane_id: Uuid, ids: &HashSet<Uuid>) -> Option<(Vec<&Block>, &ThemeConfig)> {
+        let pane = self.pane_by_id(pane_id)?;
+        let blocks = pane.blocks.iter().filter(|block| ids.contains(&block.id)).collect();
+        Some((blocks, pane.theme.as_ref().unwrap_or(&self.config.theme)))
+    }
+
+    fn copy_blocks(&self, pane_id: Uuid, ids: &HashSet<Uuid>, target: CopyTarget) {
+        if let Some((blocks, _)) = self.blocks_in(pane_id, ids) {
+            if let Err(e) = export::copy_to_clipboard(export::copy_text(&blocks, target)) {
+                eprintln!("Failed to copy to the clipboard: {}", e);
+            }
+        }
+    }
+
+    fn export_blocks(&self, pane_id: Uuid, ids: &HashSet<Uuid>, format: ExportFormat) -> Command<Message> {
+        match self.blocks_in(pane_id, ids) {
+            // HTML keeps the colors the blocks are shown with
+            Some((blocks, theme)) => {
+                let content = export::export(&blocks, format, &theme.colors);
+                Command::perform(export::save(content, format), Message::ExportFinished)
+            }
+            None => Command::none(),
+        }
+    }
+
+    /// Put the blocks on the clipboard as Markdown, ready to paste into a chat or an issue
+    fn share_blocks(&self, pane_id: Uuid, ids: &HashSet<Uuid>) {
+        if let Some((blocks, theme)) = self.blocks_in(pane_id, ids) {
+            let content = export::export(&blocks, ExportFormat::Markdown, &theme.colors);
+            if let Err(e) = export::copy_to_clipboard(content) {
+                eprintln!("Failed to copy to the clipboard: {}", e);
+            }
+        }
+    }
+
+    /// Delete blocks of a pane, interrupting any that are still running. Their commands stay in
+    /// `running` until they finish, so the session's state and the pane's queue still follow them.
+    fn delete_blocks(&mut self, pane_id: Uuid, ids: &HashSet<Uuid>) {
+        if let Some(pane) = self.pane_by_id_mut(pane_id) {
+            for block in pane.blocks.iter_mut().filter(|block| ids.contains(&block.id) && block.is_running()) {
+                block.send_signal(Signal::Interrupt);
+            }
+            pane.remove_blocks(ids);
+            pane.queued_commands.retain(|(_, rerun_of)| !ids.contains(rerun_of));
+        }
+    }
+
+    /// Run the commands of the blocks again; several are run one after another, in display order
+    fn rerun_blocks(&mut self, pane_id: Uuid, ids: &HashSet<Uuid>) {
+        let Some((blocks, _)) = self.blocks_in(pane_id, ids) else {
+            return;
+        };
+        let commands: Vec<(String, Uuid)> = blocks
+            .iter()
+            .filter_map(|block| match &block.content {
+                BlockContent::Command { input, .. } => Some((input.clone(), block.id)),
+                _ => None,
+            })
+            .collect();
+
+        // The original blocks keep their output; each rerun gets a new block linked to its original
+        if let [(command, rerun_of)] = &commands[..] {
+            self.run_command(pane_id, command.clone(), Some(*rerun_of));
+        } else if let Some(pane) = self.pane_by_id_mut(pane_id) {
+            pane.queued_commands.extend(commands);
+            self.run_next_queued(pane_id);
+        }
+    }
+
+    /// Start the pane's next queued command once nothing else is running in it
+    fn run_next_queued(&mut self, pane_id: Uuid) {
+        let Some(pane) = self.pane_by_id_mut(pane_id) else {
+            return;
+        };
+        if pane.has_running_commands() {
+            return;
+        }
+        if let Some((command, rerun_of)) = pane.queued_commands.pop_front() {
+            self.run_command(pane_id, command, Some(rerun_of));
+        }
+    }
+}
+
+fn terminal_event(event: iced::Event, _status: iced::event::Status) -> Option<Message> {
+    match event {
+        iced::Event::Keyboard(_) | iced::Event::Mouse(_) => Some(Message::TerminalEvent(event)),
+        _ => None,
+    }
+}
+
+fn mouse_button(button: iced::mouse::Button) -> Option<MouseButton> {
+    match button {
+        iced::mouse::Button::Left => Some(MouseButton::Left),
+        iced::mouse::Button::Middle => Some(MouseButton::Middle),
+        iced::mouse::Button::Right => Some(MouseButton::Right),
+        _ => None,
+    }
 }
 
 fn main() -> iced::Result {
-    NeoTerm::run(Settings::default())
-}
\ No newline at end of file
+    // `neoterm --render BLOCKS.json THEME.yaml OUTPUT.png` draws saved blocks without a window or GPU
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let [flag, blocks, theme, output] = &args[..] {
+        if flag == "--render" {
+            if let Err(e) = headless::render_file(blocks.as_ref(), theme.as_ref(), output.as_ref()) {
+                eprintln!("Failed to render blocks: {}", e);
+                std::process::exit(1);
+            }
+            return Ok(());
+        }
+    }
+
+    NeoTerm::run(Settings {
+        window: iced::window::Settings {
+            // Closing goes through `NeoTerm::exit` so the session is saved first
+            exit_on_close_request: false,
+            ..Default::default()
+        },
+        ..Settings::default()
+    })
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::BlockContent;
+    use crate::config::ShellProfile;
+
+    /// An app on `/bin/sh` that leaves no session behind when it closes
+    fn app() -> NeoTerm {
+        let mut config = AppConfig::default();
+        config.preferences.general.default_shell = Some("/bin/sh".to_string());
+        config.preferences.privacy.incognito_mode = true;
+        NeoTerm::with_config(config, None)
+    }
+
+    fn session_ids(tab: &Tab) -> Vec<Uuid> {
+        tab.panes().map(|pane| pane.session_id).collect()
+    }
+
+    #[test]
+    fn test_split_panes_get_their_own_sessions_and_closing_one_ends_it() {
+        let mut app = app();
+        let first = app.tabs[0].focus;
+
+        app.perform_action(Action::SplitVertical);
+        let tab = &app.tabs[0];
+        assert_eq!(tab.panes.len(), 2);
+        assert_ne!(tab.focus, first);
+        let sessions = session_ids(tab);
+        assert_ne!(sessions[0], sessions[1]);
+        assert!(sessions.iter().all(|id| app.shell_manager.get_session(id).is_some()));
+
+        let split = tab.focus;
+        let split_session = tab.panes.get(split).unwrap().session_id;
+        app.perform_action(Action::CloseSplit);
+        assert_eq!(app.tabs[0].panes.len(), 1);
+        assert_eq!(app.tabs[0].focus, first);
+        assert!(app.shell_manager.get_session(&split_session).is_none());
+    }
+
+    #[test]
+    fn test_closing_a_tab_keeps_the_active_one() {
+        let mut app = app();
+        app.perform_action(Action::NewTab);
+        app.perform_action(Action::NewTab);
+        assert_eq!(app.active_tab, 2);
+        let (first, last) = (app.tabs[0].id, app.tabs[2].id);
+        let first_session = session_ids(&app.tabs[0])[0];
+
+        app.close(CloseTarget::Tab(first));
+        assert_eq!(app.tabs.len(), 2);
+        assert_eq!(app.tabs[app.active_tab].id, last);
+        assert!(app.shell_manager.get_session(&first_session).is_none());
+
+        // The only pane of a tab closes the tab with it
+        app.perform_action(Action::CloseSplit);
+        assert_eq!(app.tabs.len(), 1);
+        assert_eq!(app.active_tab, 0);
+    }
+
+    #[test]
+    fn test_profile_tabs_run_the_startup_script() {
+        let mut app = app();
+        app.config.profiles.push(ShellProfile {
+            name: "rust-dev".to_string(),
+            shell: None,
+            args: Vec::new(),
+            login: None,
+            environment: HashMap::new(),
+            working_directory: None,
+            theme: None,
+            startup_script: Some("echo ready".to_string()),
+        });
+
+        app.perform_action(Action::NewTabWithProfile("missing".to_string()));
+        assert_eq!(app.tabs.len(), 1);
+
+        app.perform_action(Action::NewTabWithProfile("rust-dev".to_string()));
+        assert_eq!(app.tabs.len(), 2);
+        let pane = app.tabs[1].focused().unwrap();
+        assert_eq!(pane.profile.as_deref(), Some("rust-dev"));
+        assert!(matches!(
+            &pane.blocks[..],
+            [Block { content: BlockContent::Command { input, .. }, .. }] if input == "echo ready"
+        ));
+        assert!(app.tabs[0].focused().unwrap().blocks.is_empty());
+    }
+
+    #[test]
+    fn test_closing_running_commands_asks_first() {
+        let mut app = app();
+        let pane = app.tabs[0].focused_mut().unwrap();
+        pane.blocks.push(Block::new_command("sleep 30".to_string(), PathBuf::from("/"), 80, 24, 100));
+
+        app.perform_action(Action::CloseTab);
+        assert!(matches!(app.pending_close, Some(CloseTarget::Tab(_))));
+        assert_eq!(app.tabs.len(), 1);
+    }
+}
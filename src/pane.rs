@@ -0,0 +1,893 @@
+use iced::widget::{button, checkbox, column, container, pane_grid, pick_list, row, scrollable, text, text_input, Space};
+use iced::{Command, Element};
+use std::collections::{HashSet, VecDeque};
+use uuid::Uuid;
+
+use crate::block::{Block, ROW_HEIGHT};
+use crate::config::{self, Action, AppConfig, PaneLayout, PaneState, ShellProfile, SplitAxis, TabState, TerminalPreferences, ThemeConfig, YamlThemeManager};
+use crate::export::{CopyTarget, ExportFormat};
+use crate::renderer::VirtualScroller;
+use crate::search::{MatchLocation, Search, SearchMode, SearchScope};
+use crate::shell::ShellManager;
+use crate::terminal;
+use crate::wheel::scaled_wheel;
+use crate::{BlockMessage, FindMessage, Message, OUTPUT_FONT_SIZE};
+
+/// Padding between a pane's border and its blocks or full-screen grid
+pub const PANE_PADDING: f32 = 8.0;
+
+/// Most of a pane's height the pinned blocks, or the bookmarks list, take before they scroll
+const PINNED_MAX_HEIGHT: f32 = 240.0;
+/// Gap between blocks in the block list
+const BLOCK_SPACING: f32 = 8.0;
+/// Height the block list is assumed to have until it reports its size
+const INITIAL_VIEWPORT_HEIGHT: f32 = 1080.0;
+
+/// One terminal view inside a tab: a shell session with its own blocks and input line
+#[derive(Debug, Clone)]
+pub struct Pane {
+    pub id: Uuid,
+    pub session_id: Uuid,
+    /// Name of the shell profile the session was started with
+    pub profile: Option<String>,
+    /// The profile's theme, used instead of the application theme
+    pub theme: Option<ThemeConfig>,
+    pub blocks: Vec<Block>,
+    pub current_input: String,
+    pub history_index: Option<usize>,
+    pub suggestions: Vec<String>,
+    pub active_suggestion: Option<usize>,
+    /// The block cursor that keyboard navigation moves
+    pub focused_block: Option<Uuid>,
+    /// Where a shift-selected range starts
+    selection_anchor: Option<Uuid>,
+    /// Blocks that actions on the selection apply to
+    pub selected_blocks: HashSet<Uuid>,
+    /// Commands waiting for the running one to finish, with the block each re-runs
+    pub queued_commands: VecDeque<(String, Uuid)>,
+    /// The find bar, while it is open
+    pub search: Option<Search>,
+    /// The list of bookmarked blocks is shown above the blocks
+    pub show_bookmarks: bool,
+    /// Where the block list is scrolled to, so that only the blocks near it are drawn
+    scroller: VirtualScroller,
+}
+
+/// How picking a block changes the selection
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SelectionMode {
+    /// Select only the picked block
+    Replace,
+    /// Add or remove the picked block
+    Toggle,
+    /// Select every block from the anchor to the picked one
+    Range,
+}
+
+impl Pane {
+    pub fn new(session_id: Uuid) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            session_id,
+            profile: None,
+            theme: None,
+            blocks: Vec::new(),
+            current_input: String::new(),
+            history_index: None,
+            suggestions: Vec::new(),
+            active_suggestion: None,
+            focused_block: None,
+            selection_anchor: None,
+            selected_blocks: HashSet::new(),
+            queued_commands: VecDeque::new(),
+            search: None,
+            show_bookmarks: false,
+            scroller: VirtualScroller::new(INITIAL_VIEWPORT_HEIGHT),
+        }
+    }
+
+    /// Open a pane on a new session started with `profile`, or with the defaults; `theme` is the
+    /// profile's theme, resolved with [`profile_theme`]
+    pub fn open(shell_manager: &mut ShellManager, profile: Option<&ShellProfile>, theme: Option<ThemeConfig>) -> Self {
+        let mut pane = Self::new(shell_manager.create_session(profile));
+        pane.profile = profile.map(|profile| profile.name.clone());
+        pane.theme = theme;
+        pane
+    }
+
+    /// Reopen a saved pane on a new session in its old directory and environment
+    pub fn restore(
+        state: PaneState,
+        shell_manager: &mut ShellManager,
+        config: &AppConfig,
+        themes: &mut Option<YamlThemeManager>,
+    ) -> Self {
+        let profile = state.profile.as_deref().and_then(|name| config.profile(name));
+        let environment = config::apply_environment_delta(std::env::vars().collect(), &state.environment);
+        let session_id = shell_manager.create_session_with(profile, state.working_dir, environment);
+        let size = shell_manager.pty_size(&session_id);
+        let scrollback_lines = config.preferences.terminal.scrollback_lines;
+
+        let mut pane = Self::new(session_id);
+        pane.profile = profile.map(|profile| profile.name.clone());
+        pane.theme = profile_theme(themes, profile);
+        pane.blocks = state
+            .blocks
+            .into_iter()
+            .map(|block| Block::restore(block, size.cols as usize, size.rows as usize, scrollback_lines))
+            .collect();
+        pane
+    }
+
+    pub fn save(&self, shell_manager: &ShellManager, scrollback_lines: usize) -> PaneState {
+        let session = shell_manager.get_session(&self.session_id);
+        let environment = session
+            .map(|session| config::environment_delta(&std::env::vars().collect(), session.environment()))
+            .unwrap_or_default();
+
+        PaneState {
+            profile: self.profile.clone(),
+            working_dir: session
+                .map(|session| session.get_working_dir().clone())
+                .unwrap_or_else(|| std::env::current_dir().unwrap_or_default()),
+            environment,
+            blocks: self.blocks.iter().filter_map(|block| block.save(scrollback_lines)).collect(),
+        }
+    }
+
+    pub fn block(&self, id: Uuid) -> Option<&Block> {
+        self.blocks.iter().find(|block| block.id == id)
+    }
+
+    pub fn block_mut(&mut self, id: Uuid) -> Option<&mut Block> {
+        self.blocks.iter_mut().find(|block| block.id == id)
+    }
+
+    pub fn has_running_commands(&self) -> bool {
+        self.blocks.iter().any(Block::is_running)
+    }
+
+    /// Move the block cursor to `id` and update the selection
+    pub fn select_block(&mut self, id: Uuid, mode: SelectionMode) {
+        match mode {
+            SelectionMode::Replace => {
+                self.selected_blocks = HashSet::from([id]);
+                self.selection_anchor = Some(id);
+            }
+            SelectionMode::Toggle => {
+                if !self.selected_blocks.remove(&id) {
+                    self.selected_blocks.insert(id);
+                }
+                self.selection_anchor = Some(id);
+            }
+            SelectionMode::Range => {
+                let index = |id| self.blocks.iter().position(|block| block.id == id);
+                let anchor = self.selection_anchor.and_then(index);
+                self.selected_blocks = match (anchor, index(id)) {
+                    (Some(anchor), Some(end)) => self.blocks[anchor.min(end)..=anchor.max(end)]
+                        .iter()
+                        .map(|block| block.id)
+                        .collect(),
+                    _ => HashSet::from([id]),
+                };
+                self.selection_anchor.get_or_insert(id);
+            }
+        }
+        self.focused_block = Some(id);
+    }
+
+    /// Move the block cursor by `offset` blocks, starting from the newest block when there is none
+    pub fn move_block_focus(&mut self, offset: isize, mode: SelectionMode) {
+        if self.blocks.is_empty() {
+            return;
+        }
+        let current = self
+            .focused_block
+            .and_then(|id| self.blocks.iter().position(|block| block.id == id));
+        let index = match current {
+            Some(index) => index.saturating_add_signed(offset).min(self.blocks.len() - 1),
+            None => self.blocks.len() - 1,
+        };
+        self.select_block(self.blocks[index].id, mode);
+    }
+
+    pub fn clear_selection(&mut self) {
+        self.selected_blocks.clear();
+        self.selection_anchor = None;
+    }
+
+    /// The selected blocks in display order, or the focused block when nothing is selected
+    pub fn selection(&self) -> Vec<&Block> {
+        if self.selected_blocks.is_empty() {
+            return self.focused_block.and_then(|id| self.block(id)).into_iter().collect();
+        }
+        self.blocks
+            .iter()
+            .filter(|block| self.selected_blocks.contains(&block.id))
+            .collect()
+    }
+
+    /// The focused block, or the newest one when the cursor has not been moved
+    pub fn current_block_mut(&mut self) -> Option<&mut Block> {
+        match self.focused_block {
+            Some(id) => self.blocks.iter_mut().find(|block| block.id == id),
+            None => self.blocks.last_mut(),
+        }
+    }
+
+    pub fn remove_blocks(&mut self, ids: &HashSet<Uuid>) {
+        self.blocks.retain(|block| !ids.contains(&block.id));
+        self.selected_blocks.retain(|id| !ids.contains(id));
+        if self.focused_block.map_or(false, |id| ids.contains(&id)) {
+            self.focused_block = None;
+        }
+        if self.selection_anchor.map_or(false, |id| ids.contains(&id)) {
+            self.selection_anchor = None;
+        }
+        self.update_search();
+    }
+
+    /// Add a block right after another one, or at the end if that one is gone
+    pub fn insert_after(&mut self, block_id: Uuid, block: Block) {
+        let index = self
+            .blocks
+            .iter()
+            .position(|b| b.id == block_id)
+            .map_or(self.blocks.len(), |index| index + 1);
+        let id = block.id;
+        self.blocks.insert(index, block);
+        self.update_block_search(id);
+    }
+
+    /// Replace what is typed in the command input and focus it, cursor at the end
+    pub fn set_input(&mut self, input: String) -> Command<Message> {
+        self.current_input = input;
+        self.history_index = None;
+        self.suggestions.clear();
+        let id = self.input_id();
+        Command::batch([text_input::focus(id.clone()), text_input::move_cursor_to_end(id)])
+    }
+
+    fn input_id(&self) -> text_input::Id {
+        text_input::Id::new(format!("input-{}", self.id))
+    }
+
+    fn search_input_id(&self) -> text_input::Id {
+        text_input::Id::new(format!("find-{}", self.id))
+    }
+
+    fn scrollable_id(&self) -> scrollable::Id {
+        scrollable::Id::new(format!("blocks-{}", self.id))
+    }
+
+    /// Open the find bar, or go back to it, with its query selected
+    pub fn open_search(&mut self) -> Command<Message> {
+        self.search.get_or_insert_with(Search::default);
+        let id = self.search_input_id();
+        Command::batch([text_input::focus(id.clone()), text_input::select_all(id)])
+    }
+
+    /// Match the find bar's query again after the blocks changed
+    pub fn update_search(&mut self) {
+        if let Some(search) = &mut self.search {
+            search.update(&self.blocks);
+        }
+    }
+
+    /// Match the find bar's query again in one block, after only that block changed
+    pub fn update_block_search(&mut self, block_id: Uuid) {
+        if let Some(search) = &mut self.search {
+            search.update_block(&self.blocks, block_id);
+        }
+    }
+
+    /// Note that a block's output changed, so the find bar matches it again on the next frame
+    pub fn mark_search_stale(&mut self, block_id: Uuid) {
+        if let Some(search) = &mut self.search {
+            search.mark_stale(block_id);
+        }
+    }
+
+    pub fn search_is_stale(&self) -> bool {
+        self.search.as_ref().is_some_and(Search::is_stale)
+    }
+
+    /// Match the find bar's query again in the blocks whose output changed since the last frame
+    pub fn refresh_search(&mut self) {
+        if let Some(search) = &mut self.search {
+            search.refresh(&self.blocks);
+        }
+    }
+
+    pub fn update_find(&mut self, message: FindMessage, preferences: &TerminalPreferences) -> Command<Message> {
+        let Some(search) = &mut self.search else {
+            return Command::none();
+        };
+        match message {
+            FindMessage::QueryChanged(query) => {
+                search.query = query;
+                search.current = None;
+                self.update_search();
+                self.jump_to_match(0, preferences)
+            }
+            FindMessage::ModeSelected(mode) => {
+                search.mode = mode;
+                self.update_search();
+                self.jump_to_match(0, preferences)
+            }
+            FindMessage::ScopeSelected(scope) => {
+                search.scope = scope;
+                search.current = None;
+                self.update_search();
+                self.jump_to_match(0, preferences)
+            }
+            FindMessage::FilterBlocks(filter_blocks) => {
+                search.filter_blocks = filter_blocks;
+                Command::none()
+            }
+            FindMessage::Next => self.jump_to_match(1, preferences),
+            FindMessage::Previous => self.jump_to_match(-1, preferences),
+            FindMessage::Close => {
+                self.search = None;
+                Command::none()
+            }
+        }
+    }
+
+    /// Move `step` matches on and scroll the match into view, expanding its block if it is hidden
+    fn jump_to_match(&mut self, step: isize, preferences: &TerminalPreferences) -> Command<Message> {
+        let Some(search) = &mut self.search else {
+            return Command::none();
+        };
+        let found = if step == 0 { search.current_match() } else { search.step(step) };
+        let Some(found) = found.cloned() else {
+            return Command::none();
+        };
+
+        if let Some(block) = self.block_mut(found.block) {
+            block.collapsed = false;
+            if matches!(found.location, MatchLocation::Line(_)) {
+                block.show_full_output = true;
+            }
+        }
+        self.focused_block = Some(found.block);
+
+        let row = match found.location {
+            MatchLocation::Annotation(_) | MatchLocation::Input => None,
+            MatchLocation::Line(row) => Some(row),
+        };
+        self.scroll_to(found.block, row, preferences)
+    }
+
+    /// Move the block cursor to a block and scroll it into view
+    pub fn jump_to_block(&mut self, id: Uuid, preferences: &TerminalPreferences) -> Command<Message> {
+        if self.block(id).is_none() {
+            return Command::none();
+        }
+        self.focused_block = Some(id);
+        self.scroll_to(id, None, preferences)
+    }
+
+    /// Jump `step` bookmarks on from the block cursor, wrapping around at either end
+    pub fn step_bookmark(&mut self, step: isize, preferences: &TerminalPreferences) -> Command<Message> {
+        let bookmarks: Vec<Uuid> = self.bookmarks().map(|block| block.id).collect();
+        if bookmarks.is_empty() {
+            return Command::none();
+        }
+        let current = self.focused_block.and_then(|id| self.blocks.iter().position(|block| block.id == id));
+        let position = |id: &Uuid| self.blocks.iter().position(|block| block.id == *id);
+        let next = match current {
+            Some(current) if step < 0 => bookmarks
+                .iter()
+                .rev()
+                .find(|id| position(id) < Some(current))
+                .or(bookmarks.last()),
+            Some(current) => bookmarks
+                .iter()
+                .find(|id| position(id) > Some(current))
+                .or(bookmarks.first()),
+            None if step < 0 => bookmarks.last(),
+            None => bookmarks.first(),
+        };
+        match next.copied() {
+            Some(id) => self.jump_to_block(id, preferences),
+            None => Command::none(),
+        }
+    }
+
+    /// The bookmarked blocks, oldest first
+    pub fn bookmarks(&self) -> impl Iterator<Item = &Block> {
+        self.blocks.iter().filter(|block| block.bookmarked)
+    }
+
+    /// Scroll the block list to a block, or to a row of its output; pinned blocks are always in view
+    fn scroll_to(&mut self, block_id: Uuid, row: Option<usize>, preferences: &TerminalPreferences) -> Command<Message> {
+        let (blocks, layout) = self.layout(preferences);
+        let Some(index) = blocks.iter().position(|block| block.id == block_id) else {
+            return Command::none();
+        };
+        let mut y = layout.offset_of(index);
+        if let Some(row) = row {
+            // A few rows above the match stay in view
+            y += blocks[index].output_offset() + row.saturating_sub(2) as f32 * ROW_HEIGHT;
+        }
+
+        self.scroll_to_offset(y)
+    }
+
+    fn scroll_to_offset(&mut self, y: f32) -> Command<Message> {
+        scrollable::scroll_to(self.scrollable_id(), scrollable::AbsoluteOffset { x: 0.0, y: y.max(0.0) })
+    }
+
+    /// Follow the block list as it scrolls or is resized
+    pub fn scrolled(&mut self, viewport: scrollable::Viewport) {
+        self.scroller.scroll(viewport.absolute_offset().y, viewport.bounds().height);
+    }
+
+    /// The blocks of the scrolling list, and where each of them goes in it
+    fn layout(&self, preferences: &TerminalPreferences) -> (Vec<&Block>, VirtualScroller) {
+        let blocks: Vec<&Block> = self
+            .blocks
+            .iter()
+            .filter(|block| !block.pinned && self.shows_block(block))
+            .collect();
+        let mut layout = self.scroller.clone();
+        layout.update(blocks.iter().map(|block| block.estimated_height(preferences)), BLOCK_SPACING);
+        (blocks, layout)
+    }
+
+    fn shows_block(&self, block: &Block) -> bool {
+        self.search.as_ref().map_or(true, |search| search.shows_block(block))
+    }
+
+    /// The running block whose program switched to the alternate screen, if any
+    pub fn fullscreen_block(&self) -> Option<&Block> {
+        self.blocks.iter().rev().find(|block| {
+            block.is_running() && block.terminal().map_or(false, |terminal| terminal.is_alternate_screen())
+        })
+    }
+
+    pub fn view<'a>(
+        &'a self,
+        handle: pane_grid::Pane,
+        theme: &'a ThemeConfig,
+        preferences: &'a TerminalPreferences,
+        focused: bool,
+    ) -> Element<'a, Message> {
+        let theme = self.theme.as_ref().unwrap_or(theme);
+        let content: Element<Message> = if let Some(terminal) = self.fullscreen_block().and_then(Block::terminal) {
+            // A full-screen program gets the whole pane, as it would in any other terminal
+            let background: iced::Color = theme.colors.terminal_background.clone().into();
+
+            container(terminal::view::screen(
+                terminal.grid(),
+                &theme.colors,
+                OUTPUT_FONT_SIZE,
+                terminal.modes.cursor_visible,
+            ))
+            .width(iced::Length::Fill)
+            .height(iced::Length::Fill)
+            .style(move |_theme| container::Appearance {
+                background: Some(background.into()),
+                ..Default::default()
+            })
+            .into()
+        } else {
+            let block_view = |block: &'a Block, visible: std::ops::Range<f32>| {
+                let matches = self
+                    .search
+                    .as_ref()
+                    .map(|search| search.block_matches(block.id))
+                    .unwrap_or_default();
+                block.view(
+                    theme,
+                    preferences,
+                    self.focused_block == Some(block.id),
+                    self.selected_blocks.contains(&block.id),
+                    &matches,
+                    visible,
+                )
+            };
+            let pinned: Vec<&Block> = self
+                .blocks
+                .iter()
+                .filter(|block| block.pinned && self.shows_block(block))
+                .collect();
+
+            // Only blocks near the viewport are drawn, with space standing in for the others
+            let (blocks, layout) = self.layout(preferences);
+            let visible = layout.visible_range();
+            let viewport = layout.viewport();
+            let mut list = column![].spacing(BLOCK_SPACING);
+            if visible.start > 0 {
+                list = list.push(Space::with_height(layout.offset_of(visible.start) - BLOCK_SPACING));
+            }
+            for index in visible.clone() {
+                let top = layout.offset_of(index);
+                list = list.push(block_view(blocks[index], viewport.start - top..viewport.end - top));
+            }
+            if visible.end < blocks.len() {
+                list = list.push(Space::with_height(layout.total_height() - layout.offset_of(visible.end)));
+            }
+
+            let blocks_view = scaled_wheel(
+                scrollable(list)
+                    .id(self.scrollable_id())
+                    .on_scroll(move |viewport| Message::Scrolled(handle, viewport))
+                    .height(iced::Length::Fill),
+                preferences.scroll_sensitivity,
+            );
+
+            let mut content = column![].spacing(8);
+            if let Some(search) = &self.search {
+                content = content.push(self.create_find_bar(handle, search));
+            }
+            if self.show_bookmarks {
+                content = content.push(self.create_bookmarks());
+            }
+            if !pinned.is_empty() {
+                // Pinned blocks stay in view, but can only take part of the pane from the others
+                content = content.push(
+                    container(scrollable(
+                        column(pinned.into_iter().map(|block| block_view(block, 0.0..f32::INFINITY)).collect::<Vec<_>>())
+                            .spacing(BLOCK_SPACING)
+                    ))
+                        .max_height(PINNED_MAX_HEIGHT)
+                );
+            }
+            content = content.push(blocks_view);
+            if self.selected_blocks.len() > 1 {
+                content = content.push(self.create_selection_bar());
+            }
+            content.push(self.create_input_view(handle)).into()
+        };
+
+        container(content)
+            .width(iced::Length::Fill)
+            .height(iced::Length::Fill)
+            .padding(PANE_PADDING)
+            .style(move |theme| {
+                let palette = theme.palette();
+                container::Appearance {
+                    border: iced::Border {
+                        color: if focused { palette.primary } else { palette.background },
+                        width: 1.0,
+                        radius: 4.0.into(),
+                    },
+                    ..Default::default()
+                }
+            })
+            .into()
+    }
+
+    fn create_find_bar(&self, handle: pane_grid::Pane, search: &Search) -> Element<Message> {
+        let find = move |message| Message::Find(handle, message);
+        let status = match search.current {
+            _ if search.error.is_some() => "Invalid regex".to_string(),
+            _ if search.query.is_empty() => String::new(),
+            Some(current) => format!("{} of {}", current + 1, search.matches.len()),
+            None => "No matches".to_string(),
+        };
+
+        row![
+            text_input("Find in blocks...", &search.query)
+                .id(self.search_input_id())
+                .on_input(move |query| find(FindMessage::QueryChanged(query)))
+                .on_submit(find(FindMessage::Next))
+                .size(13),
+            pick_list(&SearchMode::ALL[..], Some(search.mode), move |mode| find(FindMessage::ModeSelected(mode)))
+                .text_size(12),
+            pick_list(&SearchScope::ALL[..], Some(search.scope), move |scope| find(FindMessage::ScopeSelected(scope)))
+                .text_size(12),
+            checkbox("Only matching blocks", search.filter_blocks)
+                .on_toggle(move |filter_blocks| find(FindMessage::FilterBlocks(filter_blocks)))
+                .text_size(12),
+            text(status).size(12),
+            button(text("↑").size(12)).on_press(find(FindMessage::Previous)),
+            button(text("↓").size(12)).on_press(find(FindMessage::Next)),
+            button(text("×").size(12))
+                .on_press(find(FindMessage::Close))
+                .style(button::secondary),
+        ]
+        .spacing(8)
+        .align_items(iced::Alignment::Center)
+        .into()
+    }
+
+    /// The bookmarked blocks, each jumping to its block when clicked
+    fn create_bookmarks(&self) -> Element<Message> {
+        let mut bookmarks = column![
+            row![
+                text("🔖 Bookmarks").size(12),
+                Space::with_width(iced::Length::Fill),
+                button(text("×").size(12))
+                    .on_press(Message::Action(Action::ToggleBookmarks))
+                    .style(button::secondary),
+            ]
+            .align_items(iced::Alignment::Center),
+        ]
+        .spacing(2);
+
+        let mut empty = true;
+        for block in self.bookmarks() {
+            empty = false;
+            bookmarks = bookmarks.push(
+                button(text(format!("{}  {}", block.timestamp.format("%H:%M:%S"), block.label())).size(12))
+                    .on_press(Message::BlockAction(block.id, BlockMessage::JumpTo))
+                    .style(button::text)
+                    .width(iced::Length::Fill),
+            );
+        }
+        if empty {
+            bookmarks = bookmarks.push(text("No bookmarks yet; 🔖 on a block adds it here").size(12));
+        }
+
+        container(scrollable(bookmarks)).max_height(PINNED_MAX_HEIGHT).into()
+    }
+
+    /// Actions for all selected blocks at once
+    fn create_selection_bar(&self) -> Element<Message> {
+        let action = |label, action| button(text(label).size(12)).on_press(Message::Action(action));
+
+        row![
+            text(format!("{} blocks selected", self.selected_blocks.len())).size(12),
+            pick_list(&CopyTarget::ALL[..], None::<CopyTarget>, |target| {
+                Message::Action(Action::CopySelectedBlocks(target))
+            })
+            .placeholder("Copy")
+            .text_size(12),
+            pick_list(&ExportFormat::ALL[..], None::<ExportFormat>, |format| {
+                Message::Action(Action::ExportSelectedBlocks(format))
+            })
+            .placeholder("Export")
+            .text_size(12),
+            action("Rerun all", Action::RerunSelectedBlocks),
+            action("Share", Action::ShareSelectedBlocks),
+            action("Delete", Action::DeleteSelectedBlocks),
+            action("Clear", Action::ClearBlockSelection),
+        ]
+        .spacing(8)
+        .align_items(iced::Alignment::Center)
+        .into()
+    }
+
+    fn create_input_view(&self, handle: pane_grid::Pane) -> Element<Message> {
+        let input = text_input("Enter command...", &self.current_input)
+            .id(self.input_id())
+            .on_input(move |input| Message::InputChanged(handle, input))
+            .on_submit(Message::ExecuteCommand(handle))
+            .padding(12)
+            .size(16);
+
+        let suggestions_view = if !self.suggestions.is_empty() {
+            column(
+                self.suggestions
+                    .iter()
+                    .enumerate()
+                    .map(|(i, suggestion)| {
+                        button(text(suggestion))
+                            .on_press(Message::SuggestionSelected(i))
+                            .width(iced::Length::Fill)
+                            .into()
+                    })
+                    .collect::<Vec<_>>()
+            )
+            .spacing(2)
+            .into()
+        } else {
+            column![].into()
+        };
+
+        column![input, suggestions_view].spacing(4).into()
+    }
+}
+
+/// A tab holding one or more panes split side by side or stacked
+#[derive(Debug, Clone)]
+pub struct Tab {
+    pub id: Uuid,
+    pub panes: pane_grid::State<Pane>,
+    pub focus: pane_grid::Pane,
+}
+
+impl Tab {
+    pub fn new(pane: Pane) -> Self {
+        let (panes, focus) = pane_grid::State::new(pane);
+        Self {
+            id: Uuid::new_v4(),
+            panes,
+            focus,
+        }
+    }
+
+    /// Rebuild a saved tab, starting a session for each of its panes
+    pub fn restore(
+        state: TabState,
+        shell_manager: &mut ShellManager,
+        config: &AppConfig,
+        themes: &mut Option<YamlThemeManager>,
+    ) -> Self {
+        fn configuration(
+            layout: PaneLayout,
+            shell_manager: &mut ShellManager,
+            config: &AppConfig,
+            themes: &mut Option<YamlThemeManager>,
+        ) -> pane_grid::Configuration<Pane> {
+            match layout {
+                PaneLayout::Split { axis, ratio, first, second } => pane_grid::Configuration::Split {
+                    axis: match axis {
+                        SplitAxis::Horizontal => pane_grid::Axis::Horizontal,
+                        SplitAxis::Vertical => pane_grid::Axis::Vertical,
+                    },
+                    ratio,
+                    a: Box::new(configuration(*first, shell_manager, config, themes)),
+                    b: Box::new(configuration(*second, shell_manager, config, themes)),
+                },
+                PaneLayout::Pane(pane) => pane_grid::Configuration::Pane(Pane::restore(pane, shell_manager, config, themes)),
+            }
+        }
+
+        let panes = pane_grid::State::with_configuration(configuration(state.layout, shell_manager, config, themes));
+        let order = pane_order(panes.layout());
+        let focus = order.get(state.focus).or(order.first()).copied();
+
+        Self {
+            id: Uuid::new_v4(),
+            focus: focus.expect("a layout always has a pane"),
+            panes,
+        }
+    }
+
+    pub fn save(&self, shell_manager: &ShellManager, scrollback_lines: usize) -> TabState {
+        fn layout(
+            node: &pane_grid::Node,
+            panes: &pane_grid::State<Pane>,
+            shell_manager: &ShellManager,
+            scrollback_lines: usize,
+        ) -> PaneLayout {
+            match node {
+                pane_grid::Node::Split { axis, ratio, a, b, .. } => PaneLayout::Split {
+                    axis: match axis {
+                        pane_grid::Axis::Horizontal => SplitAxis::Horizontal,
+                        pane_grid::Axis::Vertical => SplitAxis::Vertical,
+                    },
+                    ratio: *ratio,
+                    first: Box::new(layout(a, panes, shell_manager, scrollback_lines)),
+                    second: Box::new(layout(b, panes, shell_manager, scrollback_lines)),
+                },
+                pane_grid::Node::Pane(handle) => PaneLayout::Pane(
+                    panes
+                        .get(*handle)
+                        .expect("every pane in the layout has state")
+                        .save(shell_manager, scrollback_lines),
+                ),
+            }
+        }
+
+        TabState {
+            layout: layout(self.panes.layout(), &self.panes, shell_manager, scrollback_lines),
+            focus: pane_order(self.panes.layout())
+                .iter()
+                .position(|pane| *pane == self.focus)
+                .unwrap_or(0),
+        }
+    }
+
+    pub fn focused(&self) -> Option<&Pane> {
+        self.panes.get(self.focus)
+    }
+
+    pub fn focused_mut(&mut self) -> Option<&mut Pane> {
+        self.panes.get_mut(self.focus)
+    }
+
+    pub fn panes(&self) -> impl Iterator<Item = &Pane> {
+        self.panes.iter().map(|(_, pane)| pane)
+    }
+
+    pub fn panes_mut(&mut self) -> impl Iterator<Item = &mut Pane> {
+        self.panes.iter_mut().map(|(_, pane)| pane)
+    }
+
+    pub fn has_running_commands(&self) -> bool {
+        self.panes().any(Pane::has_running_commands)
+    }
+}
+
+/// The theme `profile` asks for, looked up in the themes loaded when the app started
+pub fn profile_theme(themes: &mut Option<YamlThemeManager>, profile: Option<&ShellProfile>) -> Option<ThemeConfig> {
+    let name = profile?.theme.as_deref()?;
+    themes.as_mut()?.get_theme(name)
+}
+
+/// Panes of a layout from left to right and top to bottom, the order they are saved in
+fn pane_order(node: &pane_grid::Node) -> Vec<pane_grid::Pane> {
+    match node {
+        pane_grid::Node::Split { a, b, .. } => {
+            let mut panes = pane_order(a);
+            panes.extend(pane_order(b));
+            panes
+        }
+        pane_grid::Node::Pane(pane) => vec![*pane],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::BlockContent;
+    use std::path::PathBuf;
+
+    fn pane_with_blocks(count: usize) -> (Pane, Vec<Uuid>) {
+        let mut pane = Pane::new(Uuid::new_v4());
+        pane.blocks = (0..count).map(|i| Block::new_markdown(i.to_string())).collect();
+        let ids = pane.blocks.iter().map(|block| block.id).collect();
+        (pane, ids)
+    }
+
+    #[test]
+    fn test_range_selection_extends_from_anchor() {
+        let (mut pane, ids) = pane_with_blocks(5);
+        pane.select_block(ids[3], SelectionMode::Replace);
+        pane.select_block(ids[1], SelectionMode::Range);
+        assert_eq!(pane.selected_blocks, HashSet::from([ids[1], ids[2], ids[3]]));
+
+        // Shrinking the range keeps the original anchor
+        pane.select_block(ids[2], SelectionMode::Range);
+        assert_eq!(pane.selected_blocks, HashSet::from([ids[2], ids[3]]));
+        assert_eq!(pane.focused_block, Some(ids[2]));
+    }
+
+    #[test]
+    fn test_toggle_adds_and_removes() {
+        let (mut pane, ids) = pane_with_blocks(3);
+        pane.select_block(ids[0], SelectionMode::Toggle);
+        pane.select_block(ids[2], SelectionMode::Toggle);
+        assert_eq!(pane.selection().len(), 2);
+
+        pane.select_block(ids[0], SelectionMode::Toggle);
+        assert_eq!(pane.selected_blocks, HashSet::from([ids[2]]));
+    }
+
+    #[test]
+    fn test_focus_starts_at_newest_block_and_stays_in_bounds() {
+        let (mut pane, ids) = pane_with_blocks(3);
+        pane.move_block_focus(-1, SelectionMode::Replace);
+        assert_eq!(pane.focused_block, Some(ids[2]));
+
+        pane.move_block_focus(-5, SelectionMode::Replace);
+        assert_eq!(pane.focused_block, Some(ids[0]));
+        pane.move_block_focus(1, SelectionMode::Range);
+        assert_eq!(pane.selected_blocks, HashSet::from([ids[0], ids[1]]));
+
+        pane.remove_blocks(&HashSet::from([ids[1]]));
+        assert_eq!(pane.focused_block, None);
+        assert_eq!(pane.selected_blocks, HashSet::from([ids[0]]));
+    }
+
+    #[test]
+    fn test_layout_stacks_unpinned_blocks_by_their_output() {
+        let (mut pane, ids) = pane_with_blocks(3);
+        let mut long = Block::new_command("cat big".to_string(), PathBuf::from("/"), 80, 24, 100_000);
+        if let BlockContent::Command { terminal, .. } = &mut long.content {
+            terminal.feed("line\r\n".repeat(50_000).as_bytes());
+        }
+        pane.blocks.insert(1, long);
+        pane.blocks[0].pinned = true;
+
+        let mut preferences = TerminalPreferences {
+            collapse_output_after: None,
+            ..TerminalPreferences::default()
+        };
+        let (blocks, layout) = pane.layout(&preferences);
+        assert_eq!(blocks.iter().map(|block| block.id).collect::<Vec<_>>()[1..], ids[1..]);
+        let long_height = layout.offset_of(1) - BLOCK_SPACING;
+        assert!(long_height > 50_000.0 * ROW_HEIGHT);
+
+        // Folding the long block's output to its head and tail shrinks it back down
+        preferences.collapse_output_after = Some(100);
+        let (_, layout) = pane.layout(&preferences);
+        assert!(layout.offset_of(1) - BLOCK_SPACING < 100.0 * ROW_HEIGHT);
+        assert_eq!(layout.visible_range(), 0..3);
+    }
+}
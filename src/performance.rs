@@ -0,0 +1,252 @@
+use iced::widget::{button, column, container, row, text, Space};
+use iced::{Alignment, Element};
+use pprof::{Frames, ProfilerGuard, Report, UnresolvedReport};
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use crate::block::format_bytes;
+use crate::config::Action;
+use crate::renderer::{self, PerformanceMonitor};
+use crate::Message;
+
+/// Height the performance overlay takes above the panes
+pub const OVERLAY_HEIGHT: f32 = 64.0;
+/// Samples a second taken of every thread while profiling; odd so it doesn't beat with timers
+const PROFILE_FREQUENCY: i32 = 99;
+/// The profile window is kept as this many segments, so a flamegraph covers the window give or take one
+const PROFILE_SEGMENTS: u32 = 4;
+/// Upper bounds, in time spent building a frame's view, of the histogram's buckets; a last bucket takes everything slower
+const FRAME_TIME_BUCKETS: [Duration; 4] = [
+    Duration::from_millis(8),
+    Duration::from_millis(16),
+    Duration::from_millis(33),
+    Duration::from_millis(50),
+];
+/// Height of the histogram's tallest bar
+const HISTOGRAM_HEIGHT: f32 = 32.0;
+
+thread_local! {
+    // Profiles hold raw stack addresses and aren't `Send`, so they stay on the thread updating the app
+    static PROFILE: RefCell<Option<Profile>> = const { RefCell::new(None) };
+}
+
+/// A CPU profile of the recent past, restarted every segment so old samples can be dropped
+struct Profile {
+    guard: Option<ProfilerGuard<'static>>,
+    segment_started: Instant,
+    /// Finished segments, oldest first, with when each ended
+    segments: VecDeque<(Instant, UnresolvedReport)>,
+    window: Duration,
+}
+
+impl Profile {
+    fn segment(&self) -> Duration {
+        self.window / PROFILE_SEGMENTS
+    }
+
+    /// Finish the current segment, start the next and forget segments that fell out of the window
+    fn roll(&mut self) {
+        let now = Instant::now();
+        // Only one profiler can run at a time, so the old one stops before the next starts
+        if let Some(report) = self.guard.take().and_then(|guard| guard.report().build_unresolved().ok()) {
+            self.segments.push_back((now, report));
+        }
+        self.guard = ProfilerGuard::new(PROFILE_FREQUENCY).ok();
+        self.segment_started = now;
+
+        while let Some((ended, _)) = self.segments.front() {
+            if now.duration_since(*ended) + self.segment() <= self.window {
+                break;
+            }
+            self.segments.pop_front();
+        }
+    }
+}
+
+/// Start sampling the CPU, keeping about `window` of samples for flamegraphs
+pub fn start_profiling(window: Duration) -> Result<(), String> {
+    let guard = ProfilerGuard::new(PROFILE_FREQUENCY).map_err(|e| e.to_string())?;
+    PROFILE.with(|profile| {
+        *profile.borrow_mut() = Some(Profile {
+            guard: Some(guard),
+            segment_started: Instant::now(),
+            segments: VecDeque::new(),
+            window: window.max(Duration::from_secs(1)),
+        });
+    });
+    Ok(())
+}
+
+pub fn stop_profiling() {
+    PROFILE.with(|profile| profile.borrow_mut().take());
+}
+
+/// Move the profile on to a new segment once the current one is full; call it every second or so
+pub fn roll_profile() {
+    PROFILE.with(|profile| {
+        if let Some(profile) = profile.borrow_mut().as_mut() {
+            if profile.segment_started.elapsed() >= profile.segment() {
+                profile.roll();
+            }
+        }
+    });
+}
+
+/// Write a flamegraph SVG of the profile's window to `path`. Symbols are resolved here, which can take a
+/// moment for a large profile.
+pub fn dump_flamegraph(path: &Path) -> Result<(), String> {
+    let report = PROFILE.with(|profile| {
+        let profile = profile.borrow();
+        let profile = profile.as_ref().ok_or("The profiler isn't running")?;
+        let current = match &profile.guard {
+            Some(guard) => Some(guard.report().build_unresolved().map_err(|e| e.to_string())?),
+            None => None,
+        };
+        let reports: Vec<&UnresolvedReport> = profile
+            .segments
+            .iter()
+            .map(|(_, report)| report)
+            .chain(current.as_ref())
+            .collect();
+        let Some(first) = reports.first() else {
+            return Err("Nothing has been profiled yet".to_string());
+        };
+
+        let mut timing = first.timing.clone();
+        timing.duration = reports
+            .iter()
+            .map(|report| report.timing.duration)
+            .sum();
+        let mut data: HashMap<Frames, isize> = HashMap::new();
+        for report in &reports {
+            for (frames, count) in &report.data {
+                *data.entry(Frames::from(frames.clone())).or_default() += count;
+            }
+        }
+        Ok(Report { data, timing })
+    })?;
+
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+    }
+    let file = std::fs::File::create(path).map_err(|e| e.to_string())?;
+    report.flamegraph(file).map_err(|e| e.to_string())
+}
+
+/// Where a flamegraph saved now goes
+pub fn flamegraph_path() -> Option<PathBuf> {
+    let name = format!("flamegraph-{}.svg", chrono::Local::now().format("%Y%m%d-%H%M%S"));
+    dirs::data_dir().map(|dir| dir.join("neoterm").join("profiles").join(name))
+}
+
+/// The resident set size of this process in bytes, where the platform makes it cheap to read
+#[cfg(target_os = "linux")]
+pub fn resident_memory() -> Option<usize> {
+    // Sizes in pages: total, then resident
+    let statm = std::fs::read_to_string("/proc/self/statm").ok()?;
+    let pages: usize = statm.split_whitespace().nth(1)?.parse().ok()?;
+    let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) };
+    Some(pages * usize::try_from(page_size).ok()?)
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn resident_memory() -> Option<usize> {
+    None
+}
+
+/// The overlay: frame rate, time spent building the view, memory, blocks, the highlight cache and PTY throughput
+pub fn overlay_view<'a>(monitor: &PerformanceMonitor, blocks: usize, status: Option<&str>) -> Element<'a, Message> {
+    let (cache_entries, cache_bytes, hit_rate) = {
+        let renderer = renderer::block_renderer().lock().unwrap();
+        (renderer.cache_size(), renderer.cache_bytes(), renderer.performance().cache_hit_rate())
+    };
+    let dim = |label: String| {
+        text(label).size(11).style(|theme| text::Appearance {
+            color: Some(theme.palette().text.scale_alpha(0.7)),
+        })
+    };
+    let stat = |label: &str, value: String| column![dim(label.to_string()), text(value).size(13)].spacing(2);
+
+    let fps = monitor.fps().map_or("–".to_string(), |fps| format!("{:.0}", fps));
+    let frame_time = monitor
+        .average_frame_time()
+        .map_or("–".to_string(), |average| format!("{:.1} ms", average.as_secs_f64() * 1000.0));
+    let memory = monitor.memory_usage().map_or("–".to_string(), |bytes| format_bytes(bytes as u64));
+    let cache = match hit_rate {
+        Some(rate) => format!("{} · {} · {:.0}% hits", cache_entries, format_bytes(cache_bytes as u64), rate * 100.0),
+        None => format!("{} · {}", cache_entries, format_bytes(cache_bytes as u64)),
+    };
+    let throughput = format!("{}/s", format_bytes(monitor.pty_throughput() as u64));
+
+    let mut content = row![
+        stat("FPS", fps),
+        stat("View", frame_time),
+        histogram_view(&monitor.frame_time_histogram(&FRAME_TIME_BUCKETS)),
+        stat("RSS", memory),
+        stat("Blocks", blocks.to_string()),
+        stat("Highlight cache", cache),
+        stat("PTY", throughput),
+        Space::with_width(iced::Length::Fill),
+    ]
+    .spacing(16)
+    .align_items(Alignment::Center);
+
+    if let Some(status) = status {
+        content = content.push(dim(status.to_string()));
+    }
+    content = content.push(
+        button(text("Save flamegraph").size(12))
+            .on_press(Message::Action(Action::SaveFlamegraph))
+            .style(button::secondary),
+    );
+
+    container(content)
+        .height(OVERLAY_HEIGHT)
+        .width(iced::Length::Fill)
+        .padding(8)
+        .center_y()
+        .style(|theme| container::Appearance {
+            background: Some(theme.palette().text.scale_alpha(0.06).into()),
+            border: iced::Border {
+                radius: 4.0.into(),
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .into()
+}
+
+/// A bar for each bucket of recent frame times, fastest first, labelled with its upper bound
+fn histogram_view<'a>(counts: &[usize]) -> Element<'a, Message> {
+    let most = counts.iter().copied().max().unwrap_or(0).max(1);
+    let labels = FRAME_TIME_BUCKETS
+        .iter()
+        .map(|bound| format!("<{}", bound.as_millis()))
+        .chain(std::iter::once(format!("≥{}", FRAME_TIME_BUCKETS[FRAME_TIME_BUCKETS.len() - 1].as_millis())));
+
+    let bars = counts.iter().zip(labels).enumerate().map(|(bucket, (&count, label))| {
+        // Frames slower than a 60 Hz frame are drawn in the warning color
+        let slow = bucket >= 2;
+        let bar = container(Space::new(iced::Length::Fill, iced::Length::Fill))
+            .width(20)
+            .height(HISTOGRAM_HEIGHT * count as f32 / most as f32)
+            .style(move |theme| {
+                let palette = theme.palette();
+                container::Appearance {
+                    background: Some(if slow { palette.danger } else { palette.primary }.into()),
+                    ..Default::default()
+                }
+            });
+        column![
+            Space::with_height(HISTOGRAM_HEIGHT * (most - count) as f32 / most as f32),
+            bar,
+            text(label).size(9),
+        ]
+        .align_items(Alignment::Center)
+        .into()
+    });
+
+    row(bars.collect::<Vec<_>>()).spacing(2).into()
+}
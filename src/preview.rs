@@ -0,0 +1,910 @@
+use iced::font::Weight;
+use iced::widget::{button, column, container, horizontal_rule, image, row, text, Row, Space};
+use iced::{Color, Element, Font, Length};
+use pulldown_cmark::{CodeBlockKind, Event, Options, Parser, Tag};
+use serde::de::{Deserialize, Deserializer, MapAccess, SeqAccess, Visitor};
+use std::collections::HashSet;
+use std::fmt;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+use crate::block::FileType;
+use crate::config::{ColorScheme, ThemeConfig};
+use crate::renderer::{self, StyledSpan};
+use crate::terminal::Terminal;
+use crate::{BlockMessage, Message, OUTPUT_FONT_SIZE};
+
+/// Files are previewed from at most their first this many bytes
+pub const MAX_PREVIEW_BYTES: u64 = 1024 * 1024;
+/// Text previews stop after this many lines
+const MAX_PREVIEW_LINES: usize = 10_000;
+/// Longer lines wrap, which keeps the grid of a preview small
+const MAX_PREVIEW_COLUMNS: usize = 240;
+/// Binary files are dumped from their first this many bytes
+pub const HEX_DUMP_BYTES: usize = 4096;
+
+/// How a file preview is drawn, prepared once when the block is created
+#[derive(Debug, Clone)]
+pub enum Preview {
+    /// Rendered from the block's content on every draw
+    Markdown,
+    /// Loaded from the file's path
+    Image,
+    /// Text, highlighted code or a hex dump, drawn like command output
+    Lines(Box<Terminal>),
+    /// A JSON or YAML document whose lists and maps can be folded
+    Tree(Tree),
+}
+
+/// The path of a `preview <path>` command, which NeoTerm runs itself instead of the shell
+pub fn builtin_path(command: &str) -> Option<&str> {
+    let path = command.trim().strip_prefix("preview")?;
+    if !path.starts_with(char::is_whitespace) {
+        return None;
+    }
+    let path = path.trim();
+    let path = ['"', '\'']
+        .iter()
+        .find_map(|quote| path.strip_prefix(*quote)?.strip_suffix(*quote))
+        .unwrap_or(path);
+    (!path.is_empty()).then_some(path)
+}
+
+/// A path typed by the user, relative to `working_dir` unless absolute or starting with `~`
+pub fn resolve_path(path: &str, working_dir: &Path) -> PathBuf {
+    match path.strip_prefix('~').and_then(|rest| Some((dirs::home_dir()?, rest))) {
+        Some((home, rest)) if rest.is_empty() || rest.starts_with('/') => home.join(rest.trim_start_matches('/')),
+        _ => working_dir.join(path),
+    }
+}
+
+/// The start of a file, up to [`MAX_PREVIEW_BYTES`], and its full size
+pub fn read(path: &Path) -> std::io::Result<(Vec<u8>, u64)> {
+    // Opening a fifo would wait for a writer, and a device may never end
+    if !std::fs::metadata(path)?.is_file() {
+        return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, "Not a regular file"));
+    }
+    let file = std::fs::File::open(path)?;
+    let size = file.metadata()?.len();
+    let mut bytes = Vec::new();
+    file.take(MAX_PREVIEW_BYTES).read_to_end(&mut bytes)?;
+    Ok((bytes, size))
+}
+
+/// The text a preview holds for copying and searching, and how it is drawn,
+/// with code highlighted in the colors of `theme`
+pub fn prepare(file_type: &FileType, bytes: &[u8], theme: &ThemeConfig) -> (String, Preview) {
+    let text = String::from_utf8_lossy(bytes).into_owned();
+    match file_type {
+        FileType::Image => (String::new(), Preview::Image),
+        FileType::Markdown => (text, Preview::Markdown),
+        FileType::Binary => {
+            let dump = hex_dump(&bytes[..bytes.len().min(HEX_DUMP_BYTES)]);
+            let preview = Preview::Lines(Box::new(text_terminal(&dump, None, theme)));
+            (dump, preview)
+        }
+        FileType::Json | FileType::Yaml => {
+            let (syntax, language) = match file_type {
+                FileType::Json => (TreeSyntax::Json, "json"),
+                _ => (TreeSyntax::Yaml, "yaml"),
+            };
+            // Documents that do not parse, for instance because they were cut short, are still shown highlighted
+            let preview = match Tree::parse(&text, syntax) {
+                Some(tree) => Preview::Tree(tree),
+                None => Preview::Lines(Box::new(text_terminal(&text, Some(language), theme))),
+            };
+            (text, preview)
+        }
+        FileType::Code(language) => {
+            let preview = Preview::Lines(Box::new(text_terminal(&text, Some(language), theme)));
+            (text, preview)
+        }
+        FileType::Text => {
+            let preview = Preview::Lines(Box::new(text_terminal(&text, None, theme)));
+            (text, preview)
+        }
+    }
+}
+
+/// Lay out lines of text in a terminal grid just wide and tall enough for them,
+/// colored by the syntax highlighter when a language is given
+fn text_terminal(text: &str, language: Option<&str>, theme: &ThemeConfig) -> Terminal {
+    let lines: Vec<String> = text.lines().take(MAX_PREVIEW_LINES).map(visible_controls).collect();
+    let cols = lines
+        .iter()
+        .map(|line| display_width(line))
+        .max()
+        .unwrap_or(0)
+        .clamp(1, MAX_PREVIEW_COLUMNS);
+    let rows = lines
+        .iter()
+        .map(|line| display_width(line).max(1).div_ceil(cols))
+        .sum::<usize>()
+        .max(1);
+    // Highlighted as a whole so that block comments and strings spanning lines keep their colors
+    let highlighted = language.map(|language| {
+        renderer::syntax_highlighter().highlight(&lines.join("\n"), language, &renderer::syntax_theme(&theme.colors))
+    });
+
+    let mut terminal = Terminal::new(cols, rows, 0);
+    for (index, line) in lines.iter().enumerate() {
+        if index > 0 {
+            terminal.feed(b"\r\n");
+        }
+        match highlighted.as_ref().and_then(|highlighted| highlighted.lines.get(index)) {
+            Some(spans) => {
+                for span in spans {
+                    terminal.feed(sgr(span).as_bytes());
+                    terminal.feed(span.text.as_bytes());
+                }
+                terminal.feed(b"\x1b[0m");
+            }
+            None => terminal.feed(line.as_bytes()),
+        }
+    }
+    terminal
+}
+
+/// A line with its control characters, other than tabs, swapped for symbols that show them, so
+/// that a file cannot move the cursor or send escape sequences to the preview's terminal
+fn visible_controls(line: &str) -> String {
+    line.chars()
+        .map(|ch| match ch {
+            '\t' => ch,
+            '\0'..='\x1f' => char::from_u32(0x2400 + ch as u32).unwrap_or(char::REPLACEMENT_CHARACTER),
+            '\x7f' => '\u{2421}',
+            ch if ch.is_control() => char::REPLACEMENT_CHARACTER,
+            ch => ch,
+        })
+        .collect()
+}
+
+/// The escape sequence that draws text in a span's style
+fn sgr(span: &StyledSpan) -> String {
+    let channel = |value: f32| (value.clamp(0.0, 1.0) * 255.0).round() as u8;
+    format!(
+        "\x1b[0;{}{}{}38;2;{};{};{}m",
+        if span.bold { "1;" } else { "" },
+        if span.italic { "3;" } else { "" },
+        if span.underline { "4;" } else { "" },
+        channel(span.color.r),
+        channel(span.color.g),
+        channel(span.color.b),
+    )
+}
+
+/// Columns a line takes up with tabs expanded to the next multiple of eight
+fn display_width(line: &str) -> usize {
+    line.chars().fold(0, |col, ch| if ch == '\t' { (col / 8 + 1) * 8 } else { col + 1 })
+}
+
+/// Offsets, bytes and printable characters, sixteen bytes to a line, as `hexdump -C` shows them
+pub fn hex_dump(bytes: &[u8]) -> String {
+    bytes
+        .chunks(16)
+        .enumerate()
+        .map(|(index, chunk)| {
+            let hex: Vec<String> = chunk.iter().map(|byte| format!("{:02x}", byte)).collect();
+            let (first, second) = hex.split_at(hex.len().min(8));
+            let printable: String = chunk
+                .iter()
+                .map(|&byte| if byte.is_ascii_graphic() || byte == b' ' { byte as char } else { '.' })
+                .collect();
+            format!("{:08x}  {:<23}  {:<23}  |{}|", index * 16, first.join(" "), second.join(" "), printable)
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+pub fn image_view<'a>(path: &Path) -> Element<'a, Message> {
+    container(image(image::Handle::from_path(path)))
+        .max_height(400.0)
+        .into()
+}
+
+/// A block-level element of a Markdown document
+#[derive(Debug, Clone, PartialEq)]
+pub enum MarkdownBlock {
+    Heading(u8, String),
+    Paragraph(String),
+    CodeBlock {
+        language: Option<String>,
+        code: String,
+    },
+    List {
+        /// Number of the first item of an ordered list
+        start: Option<u64>,
+        items: Vec<Vec<MarkdownBlock>>,
+    },
+    Quote(Vec<MarkdownBlock>),
+    Table {
+        header: Vec<String>,
+        rows: Vec<Vec<String>>,
+    },
+    Rule,
+}
+
+/// Parse Markdown into the block-level elements NeoTerm draws; inline formatting is reduced to its text
+pub fn parse_markdown(source: &str) -> Vec<MarkdownBlock> {
+    // Open containers of blocks: the document, then any quotes and list items inside it
+    let mut containers: Vec<Vec<MarkdownBlock>> = vec![Vec::new()];
+    let mut lists: Vec<(Option<u64>, Vec<Vec<MarkdownBlock>>)> = Vec::new();
+    let mut table: (Vec<String>, Vec<Vec<String>>) = Default::default();
+    let mut table_row: Vec<String> = Vec::new();
+    let mut code_language = None;
+    let mut inline = String::new();
+
+    fn push(containers: &mut [Vec<MarkdownBlock>], block: MarkdownBlock) {
+        if let Some(container) = containers.last_mut() {
+            container.push(block);
+        }
+    }
+    // Items of tight lists hold their text without a paragraph around it
+    fn flush(containers: &mut [Vec<MarkdownBlock>], inline: &mut String) {
+        let content = std::mem::take(inline);
+        if !content.trim().is_empty() {
+            push(containers, MarkdownBlock::Paragraph(content.trim().to_string()));
+        }
+    }
+
+    let options = Options::ENABLE_TABLES | Options::ENABLE_STRIKETHROUGH | Options::ENABLE_TASKLISTS;
+    for event in Parser::new_ext(source, options) {
+        match event {
+            Event::Start(Tag::Paragraph | Tag::Heading(..) | Tag::Table(_)) => {
+                flush(&mut containers, &mut inline);
+            }
+            Event::End(Tag::Paragraph) => flush(&mut containers, &mut inline),
+            Event::End(Tag::Heading(level, ..)) => {
+                let content = std::mem::take(&mut inline).trim().to_string();
+                push(&mut containers, MarkdownBlock::Heading(level as u8, content));
+            }
+            Event::Start(Tag::CodeBlock(kind)) => {
+                flush(&mut containers, &mut inline);
+                code_language = match kind {
+                    CodeBlockKind::Fenced(info) => info.split_whitespace().next().map(str::to_string),
+                    CodeBlockKind::Indented => None,
+                };
+            }
+            Event::End(Tag::CodeBlock(_)) => {
+                let code = std::mem::take(&mut inline);
+                push(&mut containers, MarkdownBlock::CodeBlock {
+                    language: code_language.take(),
+                    code: code.trim_end_matches('\n').to_string(),
+                });
+            }
+            Event::Start(Tag::BlockQuote | Tag::Item) => {
+                flush(&mut containers, &mut inline);
+                containers.push(Vec::new());
+            }
+            Event::End(Tag::BlockQuote) => {
+                flush(&mut containers, &mut inline);
+                let quote = containers.pop().unwrap_or_default();
+                push(&mut containers, MarkdownBlock::Quote(quote));
+            }
+            Event::End(Tag::Item) => {
+                flush(&mut containers, &mut inline);
+                let item = containers.pop().unwrap_or_default();
+                if let Some((_, items)) = lists.last_mut() {
+                    items.push(item);
+                }
+            }
+            Event::Start(Tag::List(start)) => {
+                flush(&mut containers, &mut inline);
+                lists.push((start, Vec::new()));
+            }
+            Event::End(Tag::List(_)) => {
+                if let Some((start, items)) = lists.pop() {
+                    push(&mut containers, MarkdownBlock::List { start, items });
+                }
+            }
+            Event::Start(Tag::TableCell) => inline.clear(),
+            Event::End(Tag::TableCell) => table_row.push(std::mem::take(&mut inline).trim().to_string()),
+            Event::End(Tag::TableHead) => table.0 = std::mem::take(&mut table_row),
+            Event::End(Tag::TableRow) => table.1.push(std::mem::take(&mut table_row)),
+            Event::End(Tag::Table(_)) => {
+                let (header, rows) = std::mem::take(&mut table);
+                push(&mut containers, MarkdownBlock::Table { header, rows });
+            }
+            Event::Text(content) | Event::Code(content) | Event::Html(content) => inline.push_str(&content),
+            Event::SoftBreak => inline.push(' '),
+            Event::HardBreak => inline.push('\n'),
+            Event::Rule => {
+                flush(&mut containers, &mut inline);
+                push(&mut containers, MarkdownBlock::Rule);
+            }
+            Event::TaskListMarker(done) => inline.push_str(if done { "☑ " } else { "☐ " }),
+            _ => {}
+        }
+    }
+
+    flush(&mut containers, &mut inline);
+    containers.into_iter().next().unwrap_or_default()
+}
+
+pub fn markdown_view<'a>(source: &str, theme: &ThemeConfig) -> Element<'a, Message> {
+    markdown_blocks_view(&parse_markdown(source), theme)
+}
+
+fn markdown_blocks_view<'a>(blocks: &[MarkdownBlock], theme: &ThemeConfig) -> Element<'a, Message> {
+    column(blocks.iter().map(|block| markdown_block_view(block, theme)))
+        .spacing(8)
+        .into()
+}
+
+fn markdown_block_view<'a>(block: &MarkdownBlock, theme: &ThemeConfig) -> Element<'a, Message> {
+    let colors = &theme.colors;
+    let bold = Font {
+        weight: Weight::Bold,
+        ..Font::DEFAULT
+    };
+
+    match block {
+        MarkdownBlock::Heading(level, content) => text(content.clone())
+            .size(match level {
+                1 => 24,
+                2 => 20,
+                3 => 17,
+                _ => 15,
+            })
+            .font(bold)
+            .into(),
+        MarkdownBlock::Paragraph(content) => text(content.clone()).size(14).into(),
+        MarkdownBlock::CodeBlock { language, code } => {
+            let background: Color = colors.surface.clone().into();
+            let highlighted = renderer::block_renderer()
+                .lock()
+                .unwrap()
+                .render_block_content(code, language.as_deref(), theme);
+            container(highlighted.view(OUTPUT_FONT_SIZE))
+                .padding(8)
+                .width(Length::Fill)
+                .style(move |_theme| container::Appearance {
+                    background: Some(background.into()),
+                    border: iced::Border {
+                        radius: 4.0.into(),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                })
+                .into()
+        }
+        MarkdownBlock::List { start, items } => column(items.iter().enumerate().map(|(index, item)| {
+            let marker = match start {
+                Some(start) => format!("{}.", start + index as u64),
+                None => "•".to_string(),
+            };
+            row![text(marker).size(14).width(24), markdown_blocks_view(item, theme)].into()
+        }))
+        .spacing(4)
+        .into(),
+        MarkdownBlock::Quote(blocks) => {
+            let background: Color = colors.surface_variant.clone().into();
+            let color: Color = colors.text_secondary.clone().into();
+            container(markdown_blocks_view(blocks, theme))
+                .padding([4, 12])
+                .width(Length::Fill)
+                .style(move |_theme| container::Appearance {
+                    background: Some(background.into()),
+                    text_color: Some(color),
+                    border: iced::Border {
+                        radius: 4.0.into(),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                })
+                .into()
+        }
+        MarkdownBlock::Table { header, rows } => {
+            let table_row = |cells: &[String], font: Font| -> Element<'a, Message> {
+                Row::with_children(cells.iter().map(|cell| {
+                    container(text(cell.clone()).size(13).font(font))
+                        .width(Length::FillPortion(1))
+                        .padding([2, 6])
+                        .into()
+                }))
+                .into()
+            };
+
+            let mut table = column![table_row(header, bold), horizontal_rule(1)];
+            for cells in rows {
+                table = table.push(table_row(cells, Font::DEFAULT));
+            }
+            table.spacing(2).into()
+        }
+        MarkdownBlock::Rule => horizontal_rule(1).into(),
+    }
+}
+
+/// A value of a JSON or YAML document
+#[derive(Debug, Clone, PartialEq)]
+pub enum Node {
+    Null,
+    Bool(bool),
+    Number(String),
+    String(String),
+    List(Vec<Node>),
+    /// Entries in the order the document has them
+    Map(Vec<(String, Node)>),
+}
+
+impl Node {
+    /// The node's text where it is used as a map key
+    fn key_text(&self) -> String {
+        match self {
+            Node::Null => "null".to_string(),
+            Node::Bool(value) => value.to_string(),
+            Node::Number(value) | Node::String(value) => value.clone(),
+            Node::List(_) | Node::Map(_) => "…".to_string(),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Node {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct NodeVisitor;
+
+        impl<'de> Visitor<'de> for NodeVisitor {
+            type Value = Node;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a JSON or YAML value")
+            }
+
+            fn visit_unit<E>(self) -> Result<Node, E> {
+                Ok(Node::Null)
+            }
+
+            fn visit_none<E>(self) -> Result<Node, E> {
+                Ok(Node::Null)
+            }
+
+            fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<Node, D::Error> {
+                Node::deserialize(deserializer)
+            }
+
+            fn visit_bool<E>(self, value: bool) -> Result<Node, E> {
+                Ok(Node::Bool(value))
+            }
+
+            fn visit_i64<E>(self, value: i64) -> Result<Node, E> {
+                Ok(Node::Number(value.to_string()))
+            }
+
+            fn visit_u64<E>(self, value: u64) -> Result<Node, E> {
+                Ok(Node::Number(value.to_string()))
+            }
+
+            fn visit_f64<E>(self, value: f64) -> Result<Node, E> {
+                Ok(Node::Number(value.to_string()))
+            }
+
+            fn visit_str<E>(self, value: &str) -> Result<Node, E> {
+                Ok(Node::String(value.to_string()))
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Node, A::Error> {
+                let mut items = Vec::new();
+                while let Some(item) = seq.next_element()? {
+                    items.push(item);
+                }
+                Ok(Node::List(items))
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Node, A::Error> {
+                let mut entries = Vec::new();
+                while let Some((key, value)) = map.next_entry::<Node, Node>()? {
+                    entries.push((key.key_text(), value));
+                }
+                Ok(Node::Map(entries))
+            }
+        }
+
+        deserializer.deserialize_any(NodeVisitor)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TreeSyntax {
+    Json,
+    Yaml,
+}
+
+/// A parsed JSON or YAML document, pretty-printed with foldable lists and maps
+#[derive(Debug, Clone)]
+pub struct Tree {
+    pub root: Node,
+    pub syntax: TreeSyntax,
+    /// JSON pointers of the folded lists and maps
+    pub folded: HashSet<String>,
+}
+
+/// What a piece of a pretty-printed line is, for coloring it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Token {
+    Key,
+    String,
+    Number,
+    /// `true`, `false` and `null`
+    Literal,
+    Punctuation,
+    /// The size of a folded list or map
+    Summary,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TreeLine {
+    pub depth: usize,
+    /// The pointer of the list or map starting on this line, and whether it is folded
+    pub fold: Option<(String, bool)>,
+    pub segments: Vec<(String, Token)>,
+}
+
+impl Tree {
+    pub fn parse(text: &str, syntax: TreeSyntax) -> Option<Self> {
+        let root = match syntax {
+            TreeSyntax::Json => serde_json::from_str(text).ok()?,
+            TreeSyntax::Yaml => serde_yaml::from_str(text).ok()?,
+        };
+        Some(Self {
+            root,
+            syntax,
+            folded: HashSet::new(),
+        })
+    }
+
+    pub fn toggle(&mut self, pointer: &str) {
+        if !self.folded.remove(pointer) {
+            self.folded.insert(pointer.to_string());
+        }
+    }
+
+    /// The document pretty-printed, leaving out what is folded
+    pub fn lines(&self) -> Vec<TreeLine> {
+        let mut lines = Vec::new();
+        match self.syntax {
+            TreeSyntax::Json => self.json_lines(&self.root, String::new(), Vec::new(), 0, true, &mut lines),
+            TreeSyntax::Yaml => match &self.root {
+                // The top level of a YAML document needs no header line of its own
+                Node::List(_) | Node::Map(_) if !is_empty(&self.root) => {
+                    self.yaml_children(&self.root, "", 0, &mut lines);
+                }
+                node => lines.push(TreeLine {
+                    depth: 0,
+                    fold: None,
+                    segments: vec![yaml_scalar(node)],
+                }),
+            },
+        }
+        lines
+    }
+
+    fn json_lines(
+        &self,
+        node: &Node,
+        pointer: String,
+        mut segments: Vec<(String, Token)>,
+        depth: usize,
+        last: bool,
+        lines: &mut Vec<TreeLine>,
+    ) {
+        let comma = (!last).then(|| (",".to_string(), Token::Punctuation));
+
+        let (open, close) = match node {
+            Node::List(_) => ("[", "]"),
+            Node::Map(_) => ("{", "}"),
+            _ => ("", ""),
+        };
+        if open.is_empty() || is_empty(node) {
+            segments.push(json_scalar(node));
+            segments.extend(comma);
+            lines.push(TreeLine { depth, fold: None, segments });
+            return;
+        }
+
+        if self.folded.contains(&pointer) {
+            segments.push((format!("{}…{}", open, close), Token::Punctuation));
+            segments.extend(comma);
+            segments.push((format!("  {}", summary(node)), Token::Summary));
+            lines.push(TreeLine { depth, fold: Some((pointer, true)), segments });
+            return;
+        }
+
+        segments.push((open.to_string(), Token::Punctuation));
+        lines.push(TreeLine { depth, fold: Some((pointer.clone(), false)), segments });
+        let children = children(node, &pointer);
+        let count = children.len();
+        for (index, (key, child, child_pointer)) in children.into_iter().enumerate() {
+            let segments = match key {
+                Some(key) => vec![
+                    (serde_json::to_string(key).unwrap_or_default(), Token::Key),
+                    (": ".to_string(), Token::Punctuation),
+                ],
+                None => Vec::new(),
+            };
+            self.json_lines(child, child_pointer, segments, depth + 1, index + 1 == count, lines);
+        }
+        let mut segments = vec![(close.to_string(), Token::Punctuation)];
+        segments.extend(comma);
+        lines.push(TreeLine { depth, fold: None, segments });
+    }
+
+    /// Entries of a YAML list or map, each starting with `- ` or its key
+    fn yaml_children(&self, node: &Node, pointer: &str, depth: usize, lines: &mut Vec<TreeLine>) {
+        for (key, child, child_pointer) in children(node, pointer) {
+            let mut segments = match key {
+                Some(key) => vec![(yaml_string(key), Token::Key), (":".to_string(), Token::Punctuation)],
+                None => vec![("-".to_string(), Token::Punctuation)],
+            };
+
+            if matches!(child, Node::List(_) | Node::Map(_)) && !is_empty(child) {
+                let folded = self.folded.contains(&child_pointer);
+                if folded {
+                    let placeholder = if matches!(child, Node::List(_)) { " […]" } else { " {…}" };
+                    segments.push((placeholder.to_string(), Token::Punctuation));
+                    segments.push((format!("  {}", summary(child)), Token::Summary));
+                }
+                lines.push(TreeLine {
+                    depth,
+                    fold: Some((child_pointer.clone(), folded)),
+                    segments,
+                });
+                if !folded {
+                    self.yaml_children(child, &child_pointer, depth + 1, lines);
+                }
+            } else {
+                segments.push((" ".to_string(), Token::Punctuation));
+                segments.push(yaml_scalar(child));
+                lines.push(TreeLine { depth, fold: None, segments });
+            }
+        }
+    }
+}
+
+fn is_empty(node: &Node) -> bool {
+    match node {
+        Node::List(items) => items.is_empty(),
+        Node::Map(entries) => entries.is_empty(),
+        _ => false,
+    }
+}
+
+fn summary(node: &Node) -> String {
+    match node {
+        Node::List(items) if items.len() == 1 => "1 item".to_string(),
+        Node::List(items) => format!("{} items", items.len()),
+        Node::Map(entries) if entries.len() == 1 => "1 key".to_string(),
+        Node::Map(entries) => format!("{} keys", entries.len()),
+        _ => String::new(),
+    }
+}
+
+/// The entries of a list or map with their keys and JSON pointers
+fn children<'a>(node: &'a Node, pointer: &str) -> Vec<(Option<&'a str>, &'a Node, String)> {
+    match node {
+        Node::List(items) => items
+            .iter()
+            .enumerate()
+            .map(|(index, item)| (None, item, format!("{}/{}", pointer, index)))
+            .collect(),
+        Node::Map(entries) => entries
+            .iter()
+            .map(|(key, value)| {
+                let escaped = key.replace('~', "~0").replace('/', "~1");
+                (Some(key.as_str()), value, format!("{}/{}", pointer, escaped))
+            })
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn json_scalar(node: &Node) -> (String, Token) {
+    match node {
+        Node::Null => ("null".to_string(), Token::Literal),
+        Node::Bool(value) => (value.to_string(), Token::Literal),
+        Node::Number(value) => (value.clone(), Token::Number),
+        Node::String(value) => (serde_json::to_string(value).unwrap_or_default(), Token::String),
+        Node::List(_) => ("[]".to_string(), Token::Punctuation),
+        Node::Map(_) => ("{}".to_string(), Token::Punctuation),
+    }
+}
+
+fn yaml_scalar(node: &Node) -> (String, Token) {
+    match node {
+        Node::String(value) => (yaml_string(value), Token::String),
+        node => json_scalar(node),
+    }
+}
+
+/// A string as a plain YAML scalar, or double-quoted where plain would read as something else
+fn yaml_string(value: &str) -> String {
+    let reads_as_other_type = matches!(
+        value.to_lowercase().as_str(),
+        "true" | "false" | "yes" | "no" | "on" | "off" | "null" | "~"
+    ) || value.parse::<f64>().is_ok();
+    let plain = !value.is_empty()
+        && !reads_as_other_type
+        && value.trim() == value
+        && !value.contains(": ")
+        && !value.contains(" #")
+        && !value.ends_with(':')
+        && !value.chars().any(char::is_control)
+        && !value.starts_with(|c: char| "-?:,[]{}#&*!|>'\"%@`".contains(c));
+
+    if plain {
+        value.to_string()
+    } else {
+        // JSON strings are valid double-quoted YAML scalars
+        serde_json::to_string(value).unwrap_or_default()
+    }
+}
+
+pub fn tree_view<'a>(block_id: Uuid, tree: &Tree, colors: &ColorScheme) -> Element<'a, Message> {
+    let ansi = &colors.ansi_colors;
+    let color = |token| -> Color {
+        match token {
+            Token::Key => ansi.blue.clone().into(),
+            Token::String => ansi.green.clone().into(),
+            Token::Number => ansi.yellow.clone().into(),
+            Token::Literal => ansi.magenta.clone().into(),
+            Token::Punctuation => colors.terminal_foreground.clone().into(),
+            Token::Summary => colors.text_secondary.clone().into(),
+        }
+    };
+
+    column(tree.lines().into_iter().map(|line| {
+        let toggle: Element<'a, Message> = match line.fold {
+            Some((pointer, folded)) => button(text(if folded { "▸" } else { "▾" }).size(OUTPUT_FONT_SIZE))
+                .on_press(Message::BlockAction(block_id, BlockMessage::ToggleFold(pointer)))
+                .style(button::text)
+                .padding(0)
+                .width(OUTPUT_FONT_SIZE)
+                .into(),
+            None => Space::with_width(OUTPUT_FONT_SIZE).into(),
+        };
+
+        let mut spans = row![Space::with_width(line.depth as f32 * OUTPUT_FONT_SIZE * 1.5), toggle];
+        for (content, token) in line.segments {
+            let color = color(token);
+            spans = spans.push(
+                text(content)
+                    .size(OUTPUT_FONT_SIZE)
+                    .font(Font::MONOSPACE)
+                    .style(move |_theme| text::Appearance { color: Some(color) }),
+            );
+        }
+        spans.into()
+    }))
+    .into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line_texts(tree: &Tree) -> Vec<String> {
+        tree.lines()
+            .iter()
+            .map(|line| {
+                let content: String = line.segments.iter().map(|(content, _)| content.as_str()).collect();
+                format!("{}{}", "  ".repeat(line.depth), content)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_builtin_path_is_parsed() {
+        assert_eq!(builtin_path("preview Cargo.toml"), Some("Cargo.toml"));
+        assert_eq!(builtin_path("  preview 'my file.md' "), Some("my file.md"));
+        assert_eq!(builtin_path("preview"), None);
+        assert_eq!(builtin_path("previewer x"), None);
+    }
+
+    #[test]
+    fn test_markdown_blocks() {
+        let blocks = parse_markdown(
+            "# Title\n\nSome *text*\nwith `code`.\n\n- one\n- two\n  1. nested\n\n> quoted\n\n```rust\nfn main() {}\n```\n\n| a | b |\n|---|---|\n| 1 | 2 |\n\n---\n",
+        );
+
+        assert_eq!(blocks, vec![
+            MarkdownBlock::Heading(1, "Title".to_string()),
+            MarkdownBlock::Paragraph("Some text with code.".to_string()),
+            MarkdownBlock::List {
+                start: None,
+                items: vec![
+                    vec![MarkdownBlock::Paragraph("one".to_string())],
+                    vec![
+                        MarkdownBlock::Paragraph("two".to_string()),
+                        MarkdownBlock::List {
+                            start: Some(1),
+                            items: vec![vec![MarkdownBlock::Paragraph("nested".to_string())]],
+                        },
+                    ],
+                ],
+            },
+            MarkdownBlock::Quote(vec![MarkdownBlock::Paragraph("quoted".to_string())]),
+            MarkdownBlock::CodeBlock {
+                language: Some("rust".to_string()),
+                code: "fn main() {}".to_string(),
+            },
+            MarkdownBlock::Table {
+                header: vec!["a".to_string(), "b".to_string()],
+                rows: vec![vec!["1".to_string(), "2".to_string()]],
+            },
+            MarkdownBlock::Rule,
+        ]);
+    }
+
+    #[test]
+    fn test_json_keeps_key_order_and_folds() {
+        let mut tree = Tree::parse(r#"{"b": [1, true], "a": {"c/d": null}, "e": []}"#, TreeSyntax::Json).unwrap();
+        assert_eq!(line_texts(&tree), vec![
+            "{",
+            "  \"b\": [",
+            "    1,",
+            "    true",
+            "  ],",
+            "  \"a\": {",
+            "    \"c/d\": null",
+            "  },",
+            "  \"e\": []",
+            "}",
+        ]);
+
+        tree.toggle("/b");
+        assert_eq!(line_texts(&tree)[1], "  \"b\": […],  2 items");
+        assert_eq!(tree.lines()[1].fold, Some(("/b".to_string(), true)));
+    }
+
+    #[test]
+    fn test_yaml_is_printed_as_yaml() {
+        let mut tree = Tree::parse("name: demo\nversion: '1.0'\nsteps:\n  - run: make\n  - test\n", TreeSyntax::Yaml).unwrap();
+        assert_eq!(line_texts(&tree), vec![
+            "name: demo",
+            "version: \"1.0\"",
+            "steps:",
+            "  -",
+            "    run: make",
+            "  - test",
+        ]);
+
+        tree.toggle("/steps");
+        assert_eq!(line_texts(&tree).last().unwrap(), "steps: […]  2 items");
+    }
+
+    #[test]
+    fn test_hex_dump_matches_hexdump_c() {
+        assert_eq!(
+            hex_dump(b"\x7fELF\x02\x01\x01\x00\x00\x00\x00\x00\x00\x00\x00\x00\x03\x00>"),
+            "00000000  7f 45 4c 46 02 01 01 00  00 00 00 00 00 00 00 00  |.ELF............|\n\
+             00000010  03 00 3e                                          |..>|"
+        );
+    }
+
+    #[test]
+    fn test_control_characters_are_shown_not_run() {
+        let terminal = text_terminal("a\x1b[2Jb\tc\rd\x07\u{9b}1m\nnext", None, &ThemeConfig::default());
+        assert_eq!(terminal.text(), "a\u{241b}[2Jb  c\u{240d}d\u{2407}\u{fffd}1m\nnext");
+    }
+
+    #[test]
+    fn test_only_regular_files_are_read() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("notes.txt");
+        std::fs::write(&path, "hello").unwrap();
+        assert_eq!(read(&path).unwrap(), (b"hello".to_vec(), 5));
+
+        let error = read(dir.path()).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::ffi::OsStrExt;
+
+            let fifo = dir.path().join("fifo");
+            let c_path = std::ffi::CString::new(fifo.as_os_str().as_bytes()).unwrap();
+            assert_eq!(unsafe { libc::mkfifo(c_path.as_ptr(), 0o600) }, 0);
+            assert_eq!(read(&fifo).unwrap_err().kind(), std::io::ErrorKind::InvalidInput);
+        }
+    }
+}
@@ -0,0 +1,376 @@
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use portable_pty::{native_pty_system, Child, CommandBuilder, MasterPty, PtySize};
+
+/// Approximate advance width of a monospace glyph relative to its font size
+const CELL_WIDTH_RATIO: f32 = 0.6;
+
+/// Signals that can be delivered to the program running in a terminal
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Signal {
+    /// SIGINT, as sent by Ctrl-C
+    Interrupt,
+    /// SIGTERM
+    Terminate,
+    /// SIGKILL
+    Kill,
+    /// SIGTSTP, as sent by Ctrl-Z
+    Stop,
+    /// SIGCONT
+    Continue,
+}
+
+impl Signal {
+    #[cfg(unix)]
+    fn number(self) -> libc::c_int {
+        match self {
+            Signal::Interrupt => libc::SIGINT,
+            Signal::Terminate => libc::SIGTERM,
+            Signal::Kill => libc::SIGKILL,
+            Signal::Stop => libc::SIGTSTP,
+            Signal::Continue => libc::SIGCONT,
+        }
+    }
+}
+
+/// CPU time and memory a command used, as reported by the kernel
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ResourceUsage {
+    /// User plus system time of the command and the children it waited for
+    pub cpu_time: Duration,
+    /// Largest resident set size any of its processes reached, in kilobytes
+    pub peak_rss_kb: Option<u64>,
+}
+
+/// A child process attached to the slave side of a pseudo-terminal
+pub struct PtyProcess {
+    master: Mutex<Box<dyn MasterPty + Send>>,
+    child: Mutex<Box<dyn Child + Send + Sync>>,
+    writer: Mutex<Box<dyn Write + Send>>,
+    /// Set once the child has been reaped, after which its pid may belong to another process
+    exited: AtomicBool,
+}
+
+impl std::fmt::Debug for PtyProcess {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PtyProcess")
+            .field("pid", &self.pid())
+            .finish()
+    }
+}
+
+impl PtyProcess {
+    /// Spawn `program` inside a fresh pseudo-terminal, returning the process
+    /// together with a blocking reader for everything it writes to the terminal.
+    pub fn spawn(
+        program: &str,
+        args: &[String],
+        working_dir: &Path,
+        environment: &HashMap<String, String>,
+        size: PtySize,
+    ) -> anyhow::Result<(Self, Box<dyn Read + Send>)> {
+        let pair = native_pty_system().openpty(size)?;
+
+        let mut cmd = CommandBuilder::new(program);
+        cmd.args(args);
+        cmd.cwd(working_dir);
+        for (key, value) in environment {
+            cmd.env(key, value);
+        }
+        // Programs decide how to draw based on TERM, so advertise what we emulate
+        cmd.env("TERM", "xterm-256color");
+        cmd.env("COLORTERM", "truecolor");
+
+        let child = pair.slave.spawn_command(cmd)?;
+        // The slave must be closed on our side, otherwise the reader never sees EOF
+        drop(pair.slave);
+
+        let reader = pair.master.try_clone_reader()?;
+        let writer = pair.master.take_writer()?;
+
+        Ok((
+            Self {
+                master: Mutex::new(pair.master),
+                child: Mutex::new(child),
+                writer: Mutex::new(writer),
+                exited: AtomicBool::new(false),
+            },
+            reader,
+        ))
+    }
+
+    pub fn pid(&self) -> Option<u32> {
+        self.child.lock().ok().and_then(|child| child.process_id())
+    }
+
+    /// Propagate a new window size to the terminal (delivers SIGWINCH to the child)
+    pub fn resize(&self, size: PtySize) -> anyhow::Result<()> {
+        let master = self.master.lock().map_err(|_| anyhow::anyhow!("pty master poisoned"))?;
+        master.resize(size)?;
+        Ok(())
+    }
+
+    /// Write raw bytes to the terminal as if they had been typed
+    pub fn write(&self, bytes: &[u8]) -> std::io::Result<()> {
+        let mut writer = self.writer.lock().map_err(|_| {
+            std::io::Error::new(std::io::ErrorKind::Other, "pty writer poisoned")
+        })?;
+        writer.write_all(bytes)?;
+        writer.flush()
+    }
+
+    /// Terminate the child immediately
+    pub fn kill(&self) {
+        if self.exited.load(Ordering::SeqCst) {
+            return;
+        }
+        if let Ok(mut child) = self.child.lock() {
+            let _ = child.kill();
+        }
+    }
+
+    /// Process group currently in the foreground of the terminal, i.e. the running job
+    #[cfg(unix)]
+    pub fn foreground_process_group(&self) -> Option<i32> {
+        self.master.lock().ok().and_then(|master| master.process_group_leader())
+    }
+
+    /// Deliver a signal to the terminal's foreground process group, the way
+    /// the kernel does for keys like Ctrl-C and Ctrl-Z.
+    #[cfg(unix)]
+    pub fn signal_foreground(&self, signal: Signal) -> std::io::Result<()> {
+        let group = self.foreground_process_group().ok_or_else(|| {
+            std::io::Error::new(std::io::ErrorKind::NotFound, "no foreground process group")
+        })?;
+
+        if unsafe { libc::killpg(group, signal.number()) } == 0 {
+            Ok(())
+        } else {
+            Err(std::io::Error::last_os_error())
+        }
+    }
+
+    #[cfg(not(unix))]
+    pub fn signal_foreground(&self, signal: Signal) -> std::io::Result<()> {
+        match signal {
+            Signal::Interrupt => self.write(&[0x03]),
+            Signal::Kill | Signal::Terminate => {
+                self.kill();
+                Ok(())
+            }
+            _ => Err(std::io::Error::new(
+                std::io::ErrorKind::Unsupported,
+                "job control signals are not supported on this platform",
+            )),
+        }
+    }
+
+    /// Block until the child exits and return its exit code, along with the
+    /// resources it used where the platform reports them.
+    ///
+    /// A child killed by signal N exits with 128 + N, as shells report it.
+    pub fn wait(&self) -> (i32, Option<ResourceUsage>) {
+        #[cfg(target_os = "linux")]
+        if let Some((exit_code, usage)) = self.pid().and_then(|pid| wait4(pid as libc::pid_t)) {
+            self.exited.store(true, Ordering::SeqCst);
+            return (exit_code, Some(usage));
+        }
+
+        let exit_code = match self.child.lock() {
+            Ok(mut child) => child
+                .wait()
+                .map(|status| status.exit_code() as i32)
+                .unwrap_or(1),
+            Err(_) => 1,
+        };
+        self.exited.store(true, Ordering::SeqCst);
+        (exit_code, None)
+    }
+
+    /// CPU time of the children this process has waited for, which for a shell
+    /// covers every command it has finished running
+    #[cfg(target_os = "linux")]
+    pub fn children_cpu_time(&self) -> Option<Duration> {
+        let stat = std::fs::read_to_string(format!("/proc/{}/stat", self.pid()?)).ok()?;
+        // The command name in parentheses may contain spaces; fields after it are
+        // numbered from 3 (state), putting cutime and cstime at 16 and 17
+        let fields: Vec<&str> = stat.rsplit_once(')')?.1.split_whitespace().collect();
+        let ticks = fields.get(13)?.parse::<u64>().ok()? + fields.get(14)?.parse::<u64>().ok()?;
+        let ticks_per_second = unsafe { libc::sysconf(libc::_SC_CLK_TCK) };
+        if ticks_per_second <= 0 {
+            return None;
+        }
+        Some(Duration::from_secs_f64(ticks as f64 / ticks_per_second as f64))
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn children_cpu_time(&self) -> Option<Duration> {
+        None
+    }
+}
+
+/// Reap a child with `wait4`, which unlike `waitpid` also reports its resource usage
+#[cfg(target_os = "linux")]
+fn wait4(pid: libc::pid_t) -> Option<(i32, ResourceUsage)> {
+    let mut status = 0;
+    let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+    loop {
+        match unsafe { libc::wait4(pid, &mut status, 0, &mut usage) } {
+            result if result == pid => break,
+            -1 if std::io::Error::last_os_error().kind() == std::io::ErrorKind::Interrupted => continue,
+            _ => return None,
+        }
+    }
+
+    let exit_code = if libc::WIFEXITED(status) {
+        libc::WEXITSTATUS(status)
+    } else if libc::WIFSIGNALED(status) {
+        128 + libc::WTERMSIG(status)
+    } else {
+        1
+    };
+    let seconds = |time: libc::timeval| Duration::new(time.tv_sec as u64, time.tv_usec as u32 * 1000);
+
+    Some((
+        exit_code,
+        ResourceUsage {
+            cpu_time: seconds(usage.ru_utime) + seconds(usage.ru_stime),
+            // Linux reports the maximum resident set size in kilobytes
+            peak_rss_kb: Some(usage.ru_maxrss as u64),
+        },
+    ))
+}
+
+/// Pass each chunk the terminal produces to `on_chunk` as soon as it arrives,
+/// returning once the child closes it.
+///
+/// Linux reports `EIO` on the master once the slave side is gone, which is
+/// treated as a normal end of stream.
+pub fn read_chunks(mut reader: Box<dyn Read + Send>, mut on_chunk: impl FnMut(&[u8])) {
+    let mut buf = [0u8; 8192];
+
+    loop {
+        match reader.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => on_chunk(&buf[..n]),
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(_) => break,
+        }
+    }
+}
+
+/// Pixel width and height of one terminal cell at the given font metrics
+pub fn cell_size(font_size: f32, line_height: f32) -> (f32, f32) {
+    (
+        (font_size * CELL_WIDTH_RATIO).max(1.0),
+        (font_size * line_height).max(1.0),
+    )
+}
+
+/// Compute the terminal grid size that fits a viewport of the given pixel dimensions
+pub fn size_for_viewport(width: f32, height: f32, font_size: f32, line_height: f32) -> PtySize {
+    let (cell_width, cell_height) = cell_size(font_size, line_height);
+
+    PtySize {
+        rows: ((height / cell_height).floor() as u16).max(1),
+        cols: ((width / cell_width).floor() as u16).max(1),
+        pixel_width: width as u16,
+        pixel_height: height as u16,
+    }
+}
+
+pub fn default_size() -> PtySize {
+    PtySize {
+        rows: 24,
+        cols: 80,
+        pixel_width: 0,
+        pixel_height: 0,
+    }
+}
+
+/// Shared registry of live terminals, keyed by the session or command that owns them
+#[derive(Debug, Clone, Default)]
+pub struct PtyRegistry {
+    processes: Arc<Mutex<HashMap<uuid::Uuid, Arc<PtyProcess>>>>,
+}
+
+impl PtyRegistry {
+    pub fn insert(&self, id: uuid::Uuid, process: Arc<PtyProcess>) {
+        if let Ok(mut processes) = self.processes.lock() {
+            processes.insert(id, process);
+        }
+    }
+
+    pub fn remove(&self, id: &uuid::Uuid) -> Option<Arc<PtyProcess>> {
+        self.processes.lock().ok().and_then(|mut processes| processes.remove(id))
+    }
+
+    pub fn get(&self, id: &uuid::Uuid) -> Option<Arc<PtyProcess>> {
+        self.processes.lock().ok().and_then(|processes| processes.get(id).cloned())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spawn_sh(script: &str) -> (PtyProcess, std::thread::JoinHandle<String>) {
+        let args = ["-c".to_string(), script.to_string()];
+        let (process, reader) =
+            PtyProcess::spawn("/bin/sh", &args, &std::env::temp_dir(), &HashMap::new(), default_size()).unwrap();
+        let output = std::thread::spawn(move || {
+            let mut output = Vec::new();
+            read_chunks(reader, |chunk| output.extend_from_slice(chunk));
+            String::from_utf8_lossy(&output).into_owned()
+        });
+        (process, output)
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_signals_map_to_their_numbers() {
+        assert_eq!(Signal::Interrupt.number(), libc::SIGINT);
+        assert_eq!(Signal::Terminate.number(), libc::SIGTERM);
+        assert_eq!(Signal::Kill.number(), libc::SIGKILL);
+        assert_eq!(Signal::Stop.number(), libc::SIGTSTP);
+        assert_eq!(Signal::Continue.number(), libc::SIGCONT);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_written_input_reaches_the_program() {
+        let (process, output) = spawn_sh("read line; echo \"got $line\"");
+        process.write(b"hello\r").unwrap();
+
+        assert_eq!(process.wait().0, 0);
+        assert!(output.join().unwrap().contains("got hello"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_signals_reach_the_foreground_job() {
+        let (process, output) = spawn_sh("sleep 30");
+        process.signal_foreground(Signal::Interrupt).unwrap();
+        assert_eq!(process.wait().0, 128 + libc::SIGINT);
+        output.join().unwrap();
+
+        let (process, output) = spawn_sh("sleep 30");
+        process.signal_foreground(Signal::Kill).unwrap();
+        assert_eq!(process.wait().0, 128 + libc::SIGKILL);
+        output.join().unwrap();
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_wait_reports_resource_usage() {
+        let (process, output) = spawn_sh("exit 4");
+        let (exit_code, usage) = process.wait();
+        assert_eq!(exit_code, 4);
+        assert!(usage.and_then(|usage| usage.peak_rss_kb).is_some_and(|kb| kb > 0));
+        output.join().unwrap();
+    }
+}
@@ -1,20 +1,39 @@
-use std::collections::HashMap;
-use std::sync::Arc;
-use iced::advanced::graphics::text;
+use std::collections::{BTreeMap, HashMap};
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, OnceLock};
+use iced::widget::{column, row, text};
+use iced::{Color, Element, Font};
+use syntect::easy::HighlightLines;
+use syntect::highlighting::{FontStyle, ScopeSelectors, StyleModifier, Theme, ThemeItem, ThemeSettings};
+use syntect::util::LinesWithEndings;
 use uuid::Uuid;
 
+use crate::config::{ColorScheme, ColorValue, PerformancePreferences, ThemeConfig};
+
+/// Share of [`PerformancePreferences::memory_limit`] the text cache may use
+const TEXT_CACHE_SHARE: usize = 8;
+/// Memory limit in MB when the preferences set none
+const DEFAULT_MEMORY_LIMIT: usize = 1024;
+
 /// GPU-accelerated renderer for terminal blocks
 pub struct BlockRenderer {
-    text_cache: HashMap<String, Arc<text::Paragraph>>,
-    syntax_highlighter: SyntaxHighlighter,
+    text_cache: TextCache,
+    /// Fingerprint of the colors each theme was last rendered in, by theme name
+    theme_fingerprints: HashMap<String, u64>,
+    /// Syntax colors derived from each theme, by fingerprint
+    syntax_themes: HashMap<u64, Theme>,
+    performance: PerformanceMonitor,
     gpu_context: Option<wgpu::Device>,
 }
 
 impl BlockRenderer {
     pub fn new() -> Self {
         Self {
-            text_cache: HashMap::new(),
-            syntax_highlighter: SyntaxHighlighter::new(),
+            text_cache: TextCache::new(DEFAULT_MEMORY_LIMIT * 1024 * 1024 / TEXT_CACHE_SHARE),
+            theme_fingerprints: HashMap::new(),
+            syntax_themes: HashMap::new(),
+            performance: PerformanceMonitor::new(),
             gpu_context: None,
         }
     }
@@ -34,117 +53,484 @@ impl BlockRenderer {
         Ok(())
     }
 
-    pub fn render_block_content(&mut self, content: &str, language: Option<&str>) -> Arc<text::Paragraph> {
-        let cache_key = format!("{}:{}", language.unwrap_or("plain"), content);
-        
-        if let Some(cached) = self.text_cache.get(&cache_key) {
-            return cached.clone();
+    /// Size the text cache to its share of the memory limit, dropping what no longer fits
+    pub fn apply_preferences(&mut self, preferences: &PerformancePreferences) {
+        let limit = preferences.memory_limit.unwrap_or(DEFAULT_MEMORY_LIMIT);
+        self.text_cache.set_capacity(limit * 1024 * 1024 / TEXT_CACHE_SHARE);
+    }
+
+    /// Text highlighted as `language` in the colors of `theme`, or plain when no language is given
+    pub fn render_block_content(
+        &mut self,
+        content: &str,
+        language: Option<&str>,
+        theme: &ThemeConfig,
+    ) -> Arc<HighlightedText> {
+        let fingerprint = theme_fingerprint(theme);
+        // A theme edited in place keeps its name, so what was rendered in its old colors goes
+        if let Some(old) = self.theme_fingerprints.insert(theme.name.clone(), fingerprint) {
+            if old != fingerprint {
+                self.text_cache.remove_theme(old);
+                self.syntax_themes.remove(&old);
+            }
         }
 
-        let highlighted = if let Some(lang) = language {
-            self.syntax_highlighter.highlight(content, lang)
-        } else {
-            content.to_string()
+        let mut hasher = DefaultHasher::new();
+        (fingerprint, language, content).hash(&mut hasher);
+        let cache_key = hasher.finish();
+
+        if let Some(cached) = self.text_cache.get(cache_key) {
+            self.performance.record_cache_hit();
+            return cached;
+        }
+        self.performance.record_cache_miss();
+
+        let highlighted = match language {
+            Some(lang) => {
+                let syntax_theme = self
+                    .syntax_themes
+                    .entry(fingerprint)
+                    .or_insert_with(|| syntax_theme(&theme.colors));
+                syntax_highlighter().highlight(content, lang, syntax_theme)
+            }
+            None => HighlightedText::plain(content, theme.colors.terminal_foreground.clone().into()),
         };
 
-        // Create paragraph with syntax highlighting
-        let paragraph = Arc::new(text::Paragraph::new());
-        self.text_cache.insert(cache_key, paragraph.clone());
-        
-        paragraph
+        let highlighted = Arc::new(highlighted);
+        self.text_cache.insert(cache_key, fingerprint, highlighted.clone());
+
+        highlighted
     }
 
     pub fn clear_cache(&mut self) {
         self.text_cache.clear();
+        self.theme_fingerprints.clear();
+        self.syntax_themes.clear();
     }
 
     pub fn cache_size(&self) -> usize {
         self.text_cache.len()
     }
+
+    /// Estimated memory held by the text cache, in bytes
+    pub fn cache_bytes(&self) -> usize {
+        self.text_cache.bytes
+    }
+
+    pub fn performance(&self) -> &PerformanceMonitor {
+        &self.performance
+    }
+}
+
+/// A hash of everything in a theme that changes how text is highlighted
+fn theme_fingerprint(theme: &ThemeConfig) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    theme.name.hash(&mut hasher);
+    serde_json::to_vec(&theme.colors).unwrap_or_default().hash(&mut hasher);
+    hasher.finish()
+}
+
+struct CacheEntry {
+    text: Arc<HighlightedText>,
+    /// Fingerprint of the theme the text was highlighted in
+    theme: u64,
+    bytes: usize,
+    last_used: u64,
+}
+
+/// Highlighted text by a hash of its content, language and theme, dropping the least recently used
+/// once the estimated memory of the entries goes over capacity
+struct TextCache {
+    entries: HashMap<u64, CacheEntry>,
+    /// Keys by when they were last used, oldest first
+    recency: BTreeMap<u64, u64>,
+    tick: u64,
+    bytes: usize,
+    capacity: usize,
+}
+
+impl TextCache {
+    fn new(capacity: usize) -> Self {
+        Self {
+            entries: HashMap::new(),
+            recency: BTreeMap::new(),
+            tick: 0,
+            bytes: 0,
+            capacity,
+        }
+    }
+
+    fn get(&mut self, key: u64) -> Option<Arc<HighlightedText>> {
+        let entry = self.entries.get_mut(&key)?;
+        self.recency.remove(&entry.last_used);
+        self.tick += 1;
+        entry.last_used = self.tick;
+        self.recency.insert(self.tick, key);
+        Some(entry.text.clone())
+    }
+
+    fn insert(&mut self, key: u64, theme: u64, text: Arc<HighlightedText>) {
+        let bytes = text.estimated_bytes();
+        // Text too large to ever fit would only push everything else out
+        if bytes > self.capacity {
+            return;
+        }
+        self.remove(key);
+
+        self.tick += 1;
+        self.recency.insert(self.tick, key);
+        self.entries.insert(
+            key,
+            CacheEntry {
+                text,
+                theme,
+                bytes,
+                last_used: self.tick,
+            },
+        );
+        self.bytes += bytes;
+        self.evict();
+    }
+
+    fn remove(&mut self, key: u64) {
+        if let Some(entry) = self.entries.remove(&key) {
+            self.recency.remove(&entry.last_used);
+            self.bytes -= entry.bytes;
+        }
+    }
+
+    /// Drop everything highlighted in a theme
+    fn remove_theme(&mut self, theme: u64) {
+        let keys: Vec<u64> = self
+            .entries
+            .iter()
+            .filter(|(_, entry)| entry.theme == theme)
+            .map(|(key, _)| *key)
+            .collect();
+        for key in keys {
+            self.remove(key);
+        }
+    }
+
+    fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        self.evict();
+    }
+
+    fn evict(&mut self) {
+        while self.bytes > self.capacity {
+            let Some((_, key)) = self.recency.pop_first() else {
+                break;
+            };
+            if let Some(entry) = self.entries.remove(&key) {
+                self.bytes -= entry.bytes;
+            }
+        }
+    }
+
+    fn clear(&mut self) {
+        self.entries.clear();
+        self.recency.clear();
+        self.bytes = 0;
+    }
+
+    fn len(&self) -> usize {
+        self.entries.len()
+    }
+}
+
+/// The renderer shared by every block, so each piece of highlighted text is only computed once
+pub fn block_renderer() -> &'static Mutex<BlockRenderer> {
+    static RENDERER: OnceLock<Mutex<BlockRenderer>> = OnceLock::new();
+    RENDERER.get_or_init(|| Mutex::new(BlockRenderer::new()))
+}
+
+/// The highlighter shared by everything that shows code, since loading the syntax definitions is slow
+pub fn syntax_highlighter() -> &'static SyntaxHighlighter {
+    static HIGHLIGHTER: OnceLock<SyntaxHighlighter> = OnceLock::new();
+    HIGHLIGHTER.get_or_init(SyntaxHighlighter::new)
+}
+
+/// A run of text in one style
+#[derive(Debug, Clone, PartialEq)]
+pub struct StyledSpan {
+    pub text: String,
+    pub color: Color,
+    pub bold: bool,
+    pub italic: bool,
+    pub underline: bool,
+}
+
+/// Highlighted text as spans, line by line and without line endings
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct HighlightedText {
+    pub lines: Vec<Vec<StyledSpan>>,
+}
+
+impl HighlightedText {
+    /// Text in a single color
+    pub fn plain(text: &str, color: Color) -> Self {
+        let lines = text
+            .lines()
+            .map(|line| {
+                vec![StyledSpan {
+                    text: line.to_string(),
+                    color,
+                    bold: false,
+                    italic: false,
+                    underline: false,
+                }]
+            })
+            .collect();
+        Self { lines }
+    }
+
+    /// Rough size of the text and its spans in memory
+    fn estimated_bytes(&self) -> usize {
+        let spans = self.lines.iter().map(|line| {
+            std::mem::size_of::<Vec<StyledSpan>>()
+                + line.iter().map(|span| std::mem::size_of::<StyledSpan>() + span.text.len()).sum::<usize>()
+        });
+        std::mem::size_of::<Self>() + spans.sum::<usize>()
+    }
+
+    pub fn view<'a, Message: 'a>(&self, font_size: f32) -> Element<'a, Message> {
+        column(self.lines.iter().map(|line| {
+            if line.iter().all(|span| span.text.is_empty()) {
+                // Keep blank lines at full height
+                return text(" ").size(font_size).font(Font::MONOSPACE).into();
+            }
+            row(line.iter().map(|span| {
+                let color = span.color;
+                let font = Font {
+                    weight: if span.bold { iced::font::Weight::Bold } else { iced::font::Weight::Normal },
+                    style: if span.italic { iced::font::Style::Italic } else { iced::font::Style::Normal },
+                    ..Font::MONOSPACE
+                };
+                text(span.text.clone())
+                    .size(font_size)
+                    .font(font)
+                    .style(move |_theme| text::Appearance { color: Some(color) })
+                    .into()
+            }))
+            .into()
+        }))
+        .into()
+    }
 }
 
 pub struct SyntaxHighlighter {
     syntax_set: syntect::parsing::SyntaxSet,
-    theme_set: syntect::highlighting::ThemeSet,
 }
 
 impl SyntaxHighlighter {
     pub fn new() -> Self {
         Self {
             syntax_set: syntect::parsing::SyntaxSet::load_defaults_newlines(),
-            theme_set: syntect::highlighting::ThemeSet::load_defaults(),
         }
     }
 
-    pub fn highlight(&self, text: &str, language: &str) -> String {
+    /// Whether there is a syntax definition for files with this extension
+    pub fn has_syntax(&self, extension: &str) -> bool {
+        self.syntax_set.find_syntax_by_extension(extension).is_some()
+    }
+
+    /// Highlight `text` as a whole, so constructs spanning lines such as block comments and
+    /// multi-line strings keep their colors, with `language` given as an extension or a name
+    pub fn highlight(&self, text: &str, language: &str, theme: &Theme) -> HighlightedText {
         let syntax = self.syntax_set
             .find_syntax_by_extension(language)
-            .or_else(|| self.syntax_set.find_syntax_by_name(language))
+            .or_else(|| self.syntax_set.find_syntax_by_token(language))
             .unwrap_or_else(|| self.syntax_set.find_syntax_plain_text());
+        let foreground = theme.settings.foreground.map_or(Color::WHITE, to_color);
+
+        let mut highlighter = HighlightLines::new(syntax, theme);
+        let lines = LinesWithEndings::from(text)
+            .map(|line| match highlighter.highlight_line(line, &self.syntax_set) {
+                Ok(ranges) => ranges
+                    .into_iter()
+                    .map(|(style, piece)| StyledSpan {
+                        text: piece.trim_end_matches(['\n', '\r']).to_string(),
+                        color: to_color(style.foreground),
+                        bold: style.font_style.contains(FontStyle::BOLD),
+                        italic: style.font_style.contains(FontStyle::ITALIC),
+                        underline: style.font_style.contains(FontStyle::UNDERLINE),
+                    })
+                    .filter(|span| !span.text.is_empty())
+                    .collect(),
+                // Text the syntax definition cannot parse is still shown, uncolored
+                Err(_) => vec![StyledSpan {
+                    text: line.trim_end_matches(['\n', '\r']).to_string(),
+                    color: foreground,
+                    bold: false,
+                    italic: false,
+                    underline: false,
+                }],
+            })
+            .collect();
+
+        HighlightedText { lines }
+    }
+}
+
+/// Syntax colors taken from a color scheme's ANSI palette, so code matches the terminal output around it
+pub fn syntax_theme(colors: &ColorScheme) -> Theme {
+    let ansi = &colors.ansi_colors;
+    let item = |scopes: &str, color: &ColorValue, font_style: Option<FontStyle>| ThemeItem {
+        scope: ScopeSelectors::from_str(scopes).unwrap_or_default(),
+        style: StyleModifier {
+            foreground: Some(to_syntax_color(color)),
+            background: None,
+            font_style,
+        },
+    };
+
+    Theme {
+        name: Some("NeoTerm".to_string()),
+        settings: ThemeSettings {
+            foreground: Some(to_syntax_color(&colors.terminal_foreground)),
+            background: Some(to_syntax_color(&colors.terminal_background)),
+            caret: Some(to_syntax_color(&colors.terminal_cursor)),
+            selection: Some(to_syntax_color(&colors.terminal_selection)),
+            ..Default::default()
+        },
+        scopes: vec![
+            item("comment, punctuation.definition.comment", &colors.text_secondary, Some(FontStyle::ITALIC)),
+            item("string, constant.character, markup.raw", &ansi.green, None),
+            item("constant, support.constant, entity.other.attribute-name", &ansi.yellow, None),
+            item("keyword, storage, keyword.operator.word", &ansi.magenta, None),
+            item("keyword.operator, punctuation", &colors.terminal_foreground, None),
+            item("entity.name.function, support.function, meta.function-call", &ansi.blue, None),
+            item("entity.name.type, entity.name.class, support.type, support.class, storage.type", &ansi.cyan, None),
+            item("entity.name.tag, variable.language, variable.parameter", &ansi.red, None),
+            item("markup.heading, markup.bold", &ansi.blue, Some(FontStyle::BOLD)),
+            item("markup.italic", &colors.terminal_foreground, Some(FontStyle::ITALIC)),
+            item("markup.underline.link", &ansi.cyan, Some(FontStyle::UNDERLINE)),
+            item("markup.inserted", &ansi.green, None),
+            item("markup.deleted, invalid", &ansi.red, None),
+        ],
+        ..Default::default()
+    }
+}
 
-        let theme = &self.theme_set.themes["base16-ocean.dark"];
-        
-        let mut highlighter = syntect::easy::HighlightLines::new(syntax, theme);
-        let ranges = highlighter.highlight_line(text, &self.syntax_set).unwrap();
-        
-        // Convert to styled text - in a real implementation, you'd convert to Iced's styled text
-        syntect::util::as_24_bit_terminal_escaped(&ranges[..], false)
+fn to_syntax_color(color: &ColorValue) -> syntect::highlighting::Color {
+    let channel = |value: f32| (value.clamp(0.0, 1.0) * 255.0).round() as u8;
+    syntect::highlighting::Color {
+        r: channel(color.r),
+        g: channel(color.g),
+        b: channel(color.b),
+        a: channel(color.a),
     }
 }
 
-/// Memory-efficient virtual scrolling for large outputs
+fn to_color(color: syntect::highlighting::Color) -> Color {
+    Color::from_rgba8(color.r, color.g, color.b, color.a as f32 / 255.0)
+}
+
+/// Memory-efficient virtual scrolling for large outputs: items of varying heights stacked in a
+/// scrollable, of which only those near the viewport need to be drawn
+#[derive(Debug, Clone)]
 pub struct VirtualScroller {
-    total_items: usize,
-    visible_range: std::ops::Range<usize>,
-    item_height: f32,
+    /// Top of each item, followed by the total height
+    offsets: Vec<f32>,
     viewport_height: f32,
     scroll_offset: f32,
 }
 
 impl VirtualScroller {
-    pub fn new(item_height: f32, viewport_height: f32) -> Self {
+    pub fn new(viewport_height: f32) -> Self {
         Self {
-            total_items: 0,
-            visible_range: 0..0,
-            item_height,
+            offsets: vec![0.0],
             viewport_height,
             scroll_offset: 0.0,
         }
     }
 
-    pub fn update(&mut self, total_items: usize, scroll_offset: f32) {
-        self.total_items = total_items;
+    /// Lay out items of the given heights with `spacing` between them
+    pub fn update(&mut self, heights: impl IntoIterator<Item = f32>, spacing: f32) {
+        self.offsets.clear();
+        let mut top = 0.0;
+        for height in heights {
+            self.offsets.push(top);
+            top += height + spacing;
+        }
+        let total = if self.offsets.is_empty() { 0.0 } else { top - spacing };
+        self.offsets.push(total);
+    }
+
+    /// Follow the scrollable as it is scrolled or resized
+    pub fn scroll(&mut self, scroll_offset: f32, viewport_height: f32) {
         self.scroll_offset = scroll_offset;
-        
-        let visible_count = (self.viewport_height / self.item_height).ceil() as usize + 2; // +2 for buffer
-        let start_index = (scroll_offset / self.item_height).floor() as usize;
-        let end_index = (start_index + visible_count).min(total_items);
-        
-        self.visible_range = start_index..end_index;
+        self.viewport_height = viewport_height;
+    }
+
+    pub fn scroll_offset(&self) -> f32 {
+        self.scroll_offset
+    }
+
+    /// The span of content worth drawing: what is in view, and a viewport's height either side of it
+    /// so that scrolling does not uncover empty space before the next frame
+    pub fn viewport(&self) -> std::ops::Range<f32> {
+        self.scroll_offset - self.viewport_height..self.scroll_offset + 2.0 * self.viewport_height
     }
 
+    /// Items that overlap [`VirtualScroller::viewport`]
     pub fn visible_range(&self) -> std::ops::Range<usize> {
-        self.visible_range.clone()
+        let items = self.offsets.len() - 1;
+        let viewport = self.viewport();
+        // Items ending above the viewport, then items starting above its end
+        let start = self.offsets[1..].partition_point(|&bottom| bottom < viewport.start);
+        let end = self.offsets[..items].partition_point(|&top| top < viewport.end);
+        start.min(end)..end
+    }
+
+    /// Top of an item, or the total height for the index past the last item
+    pub fn offset_of(&self, index: usize) -> f32 {
+        self.offsets[index.min(self.offsets.len() - 1)]
     }
 
     pub fn total_height(&self) -> f32 {
-        self.total_items as f32 * self.item_height
+        self.offset_of(usize::MAX)
     }
 }
 
 /// Performance monitoring and optimization
+#[derive(Debug, Clone)]
 pub struct PerformanceMonitor {
+    /// Time between recent frames, which sets the frame rate
+    frame_intervals: Vec<std::time::Duration>,
+    /// Time recent frames spent building the view
     frame_times: Vec<std::time::Duration>,
     memory_usage: Vec<usize>,
     last_gc: std::time::Instant,
+    cache_hits: u64,
+    cache_misses: u64,
+    /// Command output received since the throughput was last sampled
+    pty_bytes: usize,
+    pty_sampled_at: std::time::Instant,
+    /// Bytes per second of command output over the last sampling interval
+    pty_throughput: f64,
 }
 
 impl PerformanceMonitor {
     pub fn new() -> Self {
         Self {
+            frame_intervals: Vec::with_capacity(60),
             frame_times: Vec::with_capacity(60),
             memory_usage: Vec::with_capacity(60),
             last_gc: std::time::Instant::now(),
+            cache_hits: 0,
+            cache_misses: 0,
+            pty_bytes: 0,
+            pty_sampled_at: std::time::Instant::now(),
+            pty_throughput: 0.0,
+        }
+    }
+
+    pub fn record_frame_interval(&mut self, duration: std::time::Duration) {
+        self.frame_intervals.push(duration);
+        if self.frame_intervals.len() > 60 {
+            self.frame_intervals.remove(0);
         }
     }
 
@@ -162,6 +548,52 @@ impl PerformanceMonitor {
         }
     }
 
+    /// The most recent memory usage recorded
+    pub fn memory_usage(&self) -> Option<usize> {
+        self.memory_usage.last().copied()
+    }
+
+    pub fn record_pty_output(&mut self, bytes: usize) {
+        self.pty_bytes += bytes;
+    }
+
+    /// Turn the output received since the last sample into a rate
+    pub fn sample_pty_throughput(&mut self) {
+        let now = std::time::Instant::now();
+        let elapsed = now.duration_since(self.pty_sampled_at).as_secs_f64();
+        if elapsed > 0.0 {
+            self.pty_throughput = self.pty_bytes as f64 / elapsed;
+        }
+        self.pty_bytes = 0;
+        self.pty_sampled_at = now;
+    }
+
+    pub fn pty_throughput(&self) -> f64 {
+        self.pty_throughput
+    }
+
+    pub fn record_cache_hit(&mut self) {
+        self.cache_hits += 1;
+    }
+
+    pub fn record_cache_miss(&mut self) {
+        self.cache_misses += 1;
+    }
+
+    pub fn cache_hits(&self) -> u64 {
+        self.cache_hits
+    }
+
+    pub fn cache_misses(&self) -> u64 {
+        self.cache_misses
+    }
+
+    /// Share of lookups the text cache answered, once there have been any
+    pub fn cache_hit_rate(&self) -> Option<f32> {
+        let lookups = self.cache_hits + self.cache_misses;
+        (lookups > 0).then(|| self.cache_hits as f32 / lookups as f32)
+    }
+
     pub fn average_frame_time(&self) -> Option<std::time::Duration> {
         if self.frame_times.is_empty() {
             None
@@ -172,8 +604,21 @@ impl PerformanceMonitor {
     }
 
     pub fn fps(&self) -> Option<f32> {
-        self.average_frame_time()
-            .map(|avg| 1.0 / avg.as_secs_f32())
+        if self.frame_intervals.is_empty() {
+            return None;
+        }
+        let total: std::time::Duration = self.frame_intervals.iter().sum();
+        Some(self.frame_intervals.len() as f32 / total.as_secs_f32())
+    }
+
+    /// How many recent frames took less than each of `bounds`, in increasing order, but not less
+    /// than the bound before it, followed by how many took longer than all of them
+    pub fn frame_time_histogram(&self, bounds: &[std::time::Duration]) -> Vec<usize> {
+        let mut counts = vec![0; bounds.len() + 1];
+        for frame_time in &self.frame_times {
+            counts[bounds.partition_point(|bound| bound <= frame_time)] += 1;
+        }
+        counts
     }
 
     pub fn should_trigger_gc(&mut self) -> bool {
@@ -185,4 +630,122 @@ impl PerformanceMonitor {
             false
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_block_comments_keep_their_color_across_lines() {
+        let theme = ThemeConfig::default();
+        let comment = to_color(to_syntax_color(&theme.colors.text_secondary));
+        let highlighted = syntax_highlighter().highlight("/* one\ntwo */\nif x {}", "rs", &syntax_theme(&theme.colors));
+
+        assert_eq!(highlighted.lines.len(), 3);
+        for line in &highlighted.lines[..2] {
+            assert!(line.iter().all(|span| span.color == comment && span.italic));
+        }
+        let keyword = highlighted.lines[2].iter().find(|span| span.text == "if").unwrap();
+        assert_eq!(keyword.color, to_color(to_syntax_color(&theme.colors.ansi_colors.magenta)));
+    }
+
+    #[test]
+    fn test_rendered_content_follows_the_theme() {
+        let mut renderer = BlockRenderer::new();
+        let dark = ThemeConfig::default();
+        let light = ThemeConfig {
+            name: "Light".to_string(),
+            colors: ColorScheme::default_light(),
+            ..ThemeConfig::default()
+        };
+
+        let in_dark = renderer.render_block_content("let x = \"a\";", Some("rust"), &dark);
+        let in_light = renderer.render_block_content("let x = \"a\";", Some("rust"), &light);
+        assert_ne!(in_dark, in_light);
+        assert_eq!(renderer.cache_size(), 2);
+        assert!(Arc::ptr_eq(&in_dark, &renderer.render_block_content("let x = \"a\";", Some("rust"), &dark)));
+    }
+
+    #[test]
+    fn test_text_cache_drops_least_recently_used() {
+        let text = |content: &str| Arc::new(HighlightedText::plain(content, Color::WHITE));
+        let size = text("aaaa").estimated_bytes();
+        let mut cache = TextCache::new(size * 2);
+
+        cache.insert(1, 0, text("aaaa"));
+        cache.insert(2, 0, text("bbbb"));
+        assert!(cache.get(1).is_some());
+        cache.insert(3, 0, text("cccc"));
+
+        assert!(cache.get(2).is_none());
+        assert!(cache.get(1).is_some() && cache.get(3).is_some());
+        assert_eq!(cache.bytes, size * 2);
+
+        cache.insert(4, 0, text(&"d".repeat(size * 2)));
+        assert!(cache.get(4).is_none());
+        cache.set_capacity(size);
+        assert_eq!(cache.len(), 1);
+        assert!(cache.get(3).is_some());
+    }
+
+    #[test]
+    fn test_editing_a_theme_invalidates_its_text() {
+        let mut renderer = BlockRenderer::new();
+        let mut theme = ThemeConfig::default();
+
+        let before = renderer.render_block_content("x", None, &theme);
+        renderer.render_block_content("x", None, &theme);
+        renderer.render_block_content("y", Some("rust"), &theme);
+        assert_eq!(renderer.performance().cache_hits(), 1);
+        assert_eq!(renderer.performance().cache_misses(), 2);
+
+        theme.colors.terminal_foreground = Color::from_rgb(1.0, 0.0, 0.0).into();
+        let after = renderer.render_block_content("x", None, &theme);
+        assert_ne!(before, after);
+        assert_eq!(renderer.cache_size(), 1);
+        assert_eq!(renderer.performance().cache_hit_rate(), Some(0.25));
+    }
+
+    #[test]
+    fn test_virtual_scroller_picks_items_near_the_viewport() {
+        let mut scroller = VirtualScroller::new(100.0);
+        scroller.update([50.0, 1000.0, 20.0, 20.0, 500.0, 20.0], 10.0);
+        assert_eq!(scroller.total_height(), 1660.0);
+        assert_eq!(scroller.offset_of(2), 1070.0);
+
+        // In view: 0..100, drawn: -100..200
+        assert_eq!(scroller.visible_range(), 0..2);
+        // In view: 1100..1200, drawn: 1000..1300
+        scroller.scroll(1100.0, 100.0);
+        assert_eq!(scroller.visible_range(), 1..5);
+        scroller.scroll(1600.0, 100.0);
+        assert_eq!(scroller.visible_range(), 4..6);
+
+        scroller.update([], 10.0);
+        assert_eq!(scroller.visible_range(), 0..0);
+        assert_eq!(scroller.total_height(), 0.0);
+    }
+
+    #[test]
+    fn test_frame_times_fall_into_histogram_buckets() {
+        let mut monitor = PerformanceMonitor::new();
+        for ms in [5, 10, 16, 20, 40, 100] {
+            monitor.record_frame_time(std::time::Duration::from_millis(ms));
+        }
+        let bounds = [16, 33].map(std::time::Duration::from_millis);
+        assert_eq!(monitor.frame_time_histogram(&bounds), vec![2, 2, 2]);
+    }
+
+    #[test]
+    fn test_frame_rate_comes_from_intervals_not_frame_times() {
+        let mut monitor = PerformanceMonitor::new();
+        assert_eq!(monitor.fps(), None);
+        for _ in 0..4 {
+            monitor.record_frame_interval(std::time::Duration::from_millis(20));
+            monitor.record_frame_time(std::time::Duration::from_millis(2));
+        }
+        assert_eq!(monitor.fps().map(f32::round), Some(50.0));
+        assert_eq!(monitor.average_frame_time(), Some(std::time::Duration::from_millis(2)));
+    }
+}
@@ -0,0 +1,412 @@
+use regex::{Regex, RegexBuilder};
+use std::collections::HashSet;
+use std::fmt;
+use std::ops::Range;
+use uuid::Uuid;
+
+use crate::block::{Block, BlockContent};
+use crate::preview::Preview;
+
+/// How the find bar's query is matched
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SearchMode {
+    #[default]
+    Plain,
+    CaseInsensitive,
+    Regex,
+}
+
+impl SearchMode {
+    pub const ALL: [SearchMode; 3] = [SearchMode::Plain, SearchMode::CaseInsensitive, SearchMode::Regex];
+}
+
+impl fmt::Display for SearchMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            SearchMode::Plain => "Match case",
+            SearchMode::CaseInsensitive => "Ignore case",
+            SearchMode::Regex => "Regex",
+        })
+    }
+}
+
+/// Which blocks the find bar searches
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SearchScope {
+    #[default]
+    All,
+    Pinned,
+    Bookmarked,
+    Annotated,
+}
+
+impl SearchScope {
+    pub const ALL: [SearchScope; 4] = [
+        SearchScope::All,
+        SearchScope::Pinned,
+        SearchScope::Bookmarked,
+        SearchScope::Annotated,
+    ];
+
+    pub fn includes(self, block: &Block) -> bool {
+        match self {
+            SearchScope::All => true,
+            SearchScope::Pinned => block.pinned,
+            SearchScope::Bookmarked => block.bookmarked,
+            SearchScope::Annotated => block.annotation.is_some(),
+        }
+    }
+}
+
+impl fmt::Display for SearchScope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            SearchScope::All => "All blocks",
+            SearchScope::Pinned => "Pinned",
+            SearchScope::Bookmarked => "Bookmarked",
+            SearchScope::Annotated => "Annotated",
+        })
+    }
+}
+
+/// Where in a block a match was found
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MatchLocation {
+    /// A line of the block's note
+    Annotation(usize),
+    /// The command line
+    Input,
+    /// A line of output, numbered as in [`crate::terminal::Terminal::lines`],
+    /// or a line of the text of blocks that are not commands
+    Line(usize),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Match {
+    pub block: Uuid,
+    pub location: MatchLocation,
+    /// Character columns the match covers
+    pub columns: Range<usize>,
+}
+
+/// State of a pane's find bar
+#[derive(Debug, Clone, Default)]
+pub struct Search {
+    pub query: String,
+    pub mode: SearchMode,
+    pub scope: SearchScope,
+    /// Only show blocks with at least one match, or in scope while there is no query
+    pub filter_blocks: bool,
+    /// Every match, in display order
+    pub matches: Vec<Match>,
+    /// Index into `matches` of the match last jumped to
+    pub current: Option<usize>,
+    /// Why the query is not a valid regex
+    pub error: Option<String>,
+    /// Blocks whose content changed since they were last matched
+    stale: HashSet<Uuid>,
+}
+
+impl Search {
+    /// Match the query against the blocks again, after it or the blocks changed
+    pub fn update(&mut self, blocks: &[Block]) {
+        let previous = self.current_match().cloned();
+        self.matches.clear();
+        self.stale.clear();
+        self.error = None;
+
+        match self.pattern() {
+            Ok(Some(pattern)) => {
+                for block in blocks.iter().filter(|block| self.scope.includes(block)) {
+                    find_in_block(&pattern, block, &mut self.matches);
+                }
+            }
+            Ok(None) => {}
+            Err(e) => self.error = Some(e.to_string()),
+        }
+
+        self.keep_current(previous);
+    }
+
+    /// Match the query against one block again, after only its content changed, such as while its
+    /// output streams in
+    pub fn update_block(&mut self, blocks: &[Block], id: Uuid) {
+        let Ok(Some(pattern)) = self.pattern() else {
+            return;
+        };
+        let Some(index) = blocks.iter().position(|block| block.id == id) else {
+            return;
+        };
+        let previous = self.current_match().cloned();
+
+        // A block's matches are next to each other, before those of the blocks after it, so walk the
+        // matches alongside the earlier blocks until one belongs to neither
+        self.matches.retain(|m| m.block != id);
+        let mut earlier = &blocks[..index];
+        let at = self
+            .matches
+            .iter()
+            .position(|m| match earlier.iter().position(|block| block.id == m.block) {
+                Some(skip) => {
+                    earlier = &earlier[skip..];
+                    false
+                }
+                None => true,
+            })
+            .unwrap_or(self.matches.len());
+        let mut found = Vec::new();
+        if self.scope.includes(&blocks[index]) {
+            find_in_block(&pattern, &blocks[index], &mut found);
+        }
+        self.matches.splice(at..at, found);
+
+        self.keep_current(previous);
+    }
+
+    /// Note that a block's content changed, to match it again on the next `refresh` rather than on
+    /// every chunk of output
+    pub fn mark_stale(&mut self, id: Uuid) {
+        self.stale.insert(id);
+    }
+
+    pub fn is_stale(&self) -> bool {
+        !self.stale.is_empty()
+    }
+
+    /// Match the query again in the blocks that changed since the last refresh
+    pub fn refresh(&mut self, blocks: &[Block]) {
+        for id in std::mem::take(&mut self.stale) {
+            self.update_block(blocks, id);
+        }
+    }
+
+    /// Stay on the same match while output streams in
+    fn keep_current(&mut self, previous: Option<Match>) {
+        self.current = previous
+            .and_then(|previous| self.matches.iter().position(|m| *m == previous))
+            .or_else(|| (!self.matches.is_empty()).then_some(0));
+    }
+
+    pub fn current_match(&self) -> Option<&Match> {
+        self.matches.get(self.current?)
+    }
+
+    /// Move to the next match, or the previous one for a negative `step`, wrapping around at either end
+    pub fn step(&mut self, step: isize) -> Option<&Match> {
+        if self.matches.is_empty() {
+            return None;
+        }
+        let len = self.matches.len() as isize;
+        let index = match self.current {
+            Some(current) => (current as isize + step).rem_euclid(len),
+            None if step < 0 => len - 1,
+            None => 0,
+        };
+        self.current = Some(index as usize);
+        self.current_match()
+    }
+
+    /// The matches in a block, each with whether it is the current one
+    pub fn block_matches(&self, block: Uuid) -> Vec<(&Match, bool)> {
+        self.matches
+            .iter()
+            .enumerate()
+            .filter(|(_, m)| m.block == block)
+            .map(|(index, m)| (m, Some(index) == self.current))
+            .collect()
+    }
+
+    /// Whether a block is shown while the block list is filtered
+    pub fn shows_block(&self, block: &Block) -> bool {
+        !self.filter_blocks
+            || (self.scope.includes(block)
+                && (self.query.is_empty() || self.matches.iter().any(|m| m.block == block.id)))
+    }
+
+    fn pattern(&self) -> Result<Option<Regex>, regex::Error> {
+        if self.query.is_empty() {
+            return Ok(None);
+        }
+        let pattern = match self.mode {
+            SearchMode::Regex => self.query.clone(),
+            SearchMode::Plain | SearchMode::CaseInsensitive => regex::escape(&self.query),
+        };
+        RegexBuilder::new(&pattern)
+            .case_insensitive(self.mode == SearchMode::CaseInsensitive)
+            .build()
+            .map(Some)
+    }
+}
+
+fn find_in_block(pattern: &Regex, block: &Block, matches: &mut Vec<Match>) {
+    let mut push = |location, line: &str| {
+        matches.extend(line_matches(pattern, line).map(|columns| Match {
+            block: block.id,
+            location,
+            columns,
+        }));
+    };
+
+    // The note is shown above everything else in the block
+    for (index, line) in block.annotation.iter().flat_map(|annotation| annotation.lines()).enumerate() {
+        push(MatchLocation::Annotation(index), line);
+    }
+
+    match &block.content {
+        BlockContent::Command { input, terminal, .. } => {
+            push(MatchLocation::Input, input);
+            for (index, row) in terminal.lines().enumerate() {
+                push(MatchLocation::Line(index), &row.text());
+            }
+        }
+        // Previews drawn like output are matched the same way, so their matches can be highlighted
+        BlockContent::FilePreview { preview: Preview::Lines(terminal), .. } => {
+            for (index, row) in terminal.lines().enumerate() {
+                push(MatchLocation::Line(index), &row.text());
+            }
+        }
+        BlockContent::Markdown(content) | BlockContent::FilePreview { content, .. } => {
+            for (index, line) in content.lines().enumerate() {
+                push(MatchLocation::Line(index), line);
+            }
+        }
+        BlockContent::Error { message, details, .. } => {
+            let lines = message.lines().chain(details.iter().flat_map(|details| details.lines()));
+            for (index, line) in lines.enumerate() {
+                push(MatchLocation::Line(index), line);
+            }
+        }
+    }
+}
+
+/// Character ranges of the non-empty matches in a line
+fn line_matches<'a>(pattern: &'a Regex, line: &'a str) -> impl Iterator<Item = Range<usize>> + 'a {
+    pattern
+        .find_iter(line)
+        .filter(|m| !m.is_empty())
+        .map(move |m| {
+            let start = line[..m.start()].chars().count();
+            start..start + m.as_str().chars().count()
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn search(query: &str, mode: SearchMode, blocks: &[Block]) -> Search {
+        let mut search = Search {
+            query: query.to_string(),
+            mode,
+            ..Default::default()
+        };
+        search.update(blocks);
+        search
+    }
+
+    #[test]
+    fn test_modes() {
+        let blocks = [Block::new_markdown("Error: error\nan ERROR (x)".to_string())];
+
+        assert_eq!(search("error", SearchMode::Plain, &blocks).matches.len(), 1);
+        assert_eq!(search("error", SearchMode::CaseInsensitive, &blocks).matches.len(), 3);
+        // Plain queries are not patterns
+        assert_eq!(search("(x)", SearchMode::Plain, &blocks).matches[0].columns, 9..12);
+        assert_eq!(search(r"E\w+", SearchMode::Regex, &blocks).matches.len(), 2);
+
+        let invalid = search("(", SearchMode::Regex, &blocks);
+        assert!(invalid.error.is_some() && invalid.matches.is_empty());
+    }
+
+    #[test]
+    fn test_columns_count_characters() {
+        let blocks = [Block::new_markdown("→ café ok".to_string())];
+        let found = search("ok", SearchMode::Plain, &blocks);
+        assert_eq!(found.matches[0].location, MatchLocation::Line(0));
+        assert_eq!(found.matches[0].columns, 7..9);
+    }
+
+    #[test]
+    fn test_steps_wrap_and_filter_hides_blocks_without_matches() {
+        let blocks = [
+            Block::new_markdown("a b".to_string()),
+            Block::new_markdown("c".to_string()),
+            Block::new_markdown("a".to_string()),
+        ];
+        let mut found = search("a", SearchMode::Plain, &blocks);
+        assert_eq!(found.current, Some(0));
+        assert_eq!(found.step(1).map(|m| m.block), Some(blocks[2].id));
+        assert_eq!(found.step(1).map(|m| m.block), Some(blocks[0].id));
+        assert_eq!(found.step(-1).map(|m| m.block), Some(blocks[2].id));
+
+        assert!(found.shows_block(&blocks[1]));
+        found.filter_blocks = true;
+        assert!(!found.shows_block(&blocks[1]));
+        assert!(found.shows_block(&blocks[2]));
+    }
+
+    #[test]
+    fn test_updating_one_block_keeps_display_order() {
+        let mut blocks = [
+            Block::new_markdown("a".to_string()),
+            Block::new_markdown("b".to_string()),
+            Block::new_markdown("a a".to_string()),
+        ];
+        let mut found = search("a", SearchMode::Plain, &blocks);
+        found.step(1);
+
+        blocks[1] = Block::new_markdown("a".to_string());
+        let id = blocks[1].id;
+        found.update_block(&blocks, id);
+        assert_eq!(found.matches, search("a", SearchMode::Plain, &blocks).matches);
+        // Still on the first match of the last block
+        assert_eq!(found.current, Some(2));
+    }
+
+    #[test]
+    fn test_stale_blocks_are_matched_on_refresh() {
+        let mut blocks = [
+            Block::new_markdown("a".to_string()),
+            Block::new_markdown("b".to_string()),
+        ];
+        let mut found = search("a", SearchMode::Plain, &blocks);
+
+        blocks[1] = Block::new_markdown("a a".to_string());
+        found.mark_stale(blocks[1].id);
+        assert!(found.is_stale());
+        assert_eq!(found.matches.len(), 1);
+
+        found.refresh(&blocks);
+        assert!(!found.is_stale());
+        assert_eq!(found.matches, search("a", SearchMode::Plain, &blocks).matches);
+    }
+
+    #[test]
+    fn test_annotations_are_searched_within_the_scope() {
+        let mut blocks = [
+            Block::new_markdown("deploy".to_string()),
+            Block::new_markdown("deploy".to_string()),
+        ];
+        blocks[1].annotation = Some("prod deploy\nrolled back".to_string());
+        blocks[1].pinned = true;
+
+        let found = search("deploy", SearchMode::Plain, &blocks);
+        assert_eq!(found.matches.len(), 3);
+        assert_eq!(found.matches[1].location, MatchLocation::Annotation(0));
+
+        let mut pinned = Search {
+            query: "deploy".to_string(),
+            scope: SearchScope::Pinned,
+            filter_blocks: true,
+            ..Default::default()
+        };
+        pinned.update(&blocks);
+        assert!(pinned.matches.iter().all(|m| m.block == blocks[1].id));
+        assert!(!pinned.shows_block(&blocks[0]));
+
+        // Without a query the scope alone picks the blocks shown
+        pinned.query.clear();
+        pinned.update(&blocks);
+        assert!(!pinned.shows_block(&blocks[0]) && pinned.shows_block(&blocks[1]));
+    }
+}
@@ -1,13 +1,29 @@
-use std::process::Stdio;
-use tokio::process::Command;
-use tokio::io::{AsyncBufReadExt, BufReader};
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use portable_pty::PtySize;
+use tokio::sync::mpsc;
 use uuid::Uuid;
 
+use crate::config::{GeneralPreferences, ShellProfile, WorkingDirectoryBehavior};
+use crate::diagnostics::{self, Diagnosis};
+use crate::pty::{self, PtyProcess, PtyRegistry, ResourceUsage, Signal};
+use crate::shell_integration::{self, Marker, MarkerScanner, Segment, ShellKind};
+
 #[derive(Debug, Clone)]
 pub struct ShellManager {
     active_sessions: HashMap<Uuid, ShellSession>,
-    default_shell: String,
+    default_shell: ShellProgram,
+    /// Variables every new session gets on top of NeoTerm's own environment
+    environment: HashMap<String, String>,
+    working_directory: WorkingDirectoryBehavior,
+    /// Directory the most recent command finished in, for `WorkingDirectoryBehavior::LastUsed`
+    last_used_dir: Option<PathBuf>,
+    /// Terminal size given to sessions until their pane reports its own
+    pty_size: PtySize,
+    ptys: PtyRegistry,
 }
 
 #[derive(Debug, Clone)]
@@ -15,96 +31,161 @@ pub struct ShellSession {
     id: Uuid,
     working_dir: std::path::PathBuf,
     environment: HashMap<String, String>,
+    /// Terminal size of the pane showing this session
+    size: PtySize,
+    /// The shell this session runs, also used for its one-shot commands
+    program: ShellProgram,
+    shell: Option<Arc<InteractiveShell>>,
+}
+
+/// The shell binary a session runs and how to start it
+#[derive(Debug, Clone)]
+pub struct ShellProgram {
+    pub path: String,
+    /// Passed after the arguments shell integration needs
+    pub args: Vec<String>,
+    /// Start as a login shell, reading the user's login profile
+    pub login: bool,
 }
 
 impl ShellManager {
-    pub fn new() -> Self {
+    pub fn new(preferences: &GeneralPreferences) -> Self {
+        let path = preferences
+            .default_shell
+            .clone()
+            .filter(|shell| !shell.trim().is_empty())
+            .unwrap_or_else(Self::detect_shell);
+
         Self {
             active_sessions: HashMap::new(),
-            default_shell: Self::detect_shell(),
+            default_shell: ShellProgram {
+                path,
+                args: preferences.shell_args.clone(),
+                login: preferences.login_shell,
+            },
+            environment: preferences.environment.clone(),
+            working_directory: preferences.working_directory.clone(),
+            last_used_dir: None,
+            pty_size: pty::default_size(),
+            ptys: PtyRegistry::default(),
         }
     }
 
-    pub async fn execute_command(&self, command: String) -> (String, i32) {
-        let mut cmd = Command::new(&self.default_shell);
-        cmd.arg("-c")
-           .arg(&command)
-           .stdout(Stdio::piped())
-           .stderr(Stdio::piped());
+    /// Run a command line in the given session, streaming its output.
+    ///
+    /// Sessions backed by an integrated interactive shell keep `cd`, exports,
+    /// aliases and functions between commands; otherwise the command runs in a
+    /// one-off pseudo-terminal using the session's directory and environment.
+    ///
+    /// Output arrives as `CommandEvent::Output` chunks while the command runs,
+    /// followed by exactly one `CommandEvent::Finished`. Both stdout and stderr
+    /// are attached to the same terminal, so their writes are interleaved in the
+    /// order the program made them and neither can block on a full pipe.
+    pub fn execute_command(&self, session_id: Uuid, command: String) -> CommandHandle {
+        let session = self.active_sessions.get(&session_id);
+        // While the shell is busy, anything written to it would become the running command's input,
+        // so further commands run alongside it in their own terminal instead
+        let live_shell = session
+            .and_then(|session| session.shell.clone())
+            .filter(|shell| shell.is_alive() && !shell.is_busy());
 
-        match cmd.spawn() {
-            Ok(mut child) => {
-                let stdout = child.stdout.take().unwrap();
-                let stderr = child.stderr.take().unwrap();
+        if let Some(live_shell) = live_shell {
+            return CommandHandle {
+                events: live_shell.run(&command),
+                process: Some(ProcessHandle {
+                    process: live_shell.process.clone(),
+                    shared_shell: true,
+                }),
+            };
+        }
 
-                let stdout_reader = BufReader::new(stdout);
-                let stderr_reader = BufReader::new(stderr);
+        let working_dir = session
+            .map(|session| session.working_dir.clone())
+            .unwrap_or_else(|| std::env::current_dir().unwrap_or_default());
+        let environment = session
+            .map(|session| session.environment.clone())
+            .unwrap_or_else(|| std::env::vars().collect());
+        let size = session.map_or(self.pty_size, |session| session.size);
+        let shell = session.map_or(&self.default_shell.path, |session| &session.program.path);
 
-                let mut output = String::new();
-                let mut error_output = String::new();
+        let (tx, rx) = mpsc::channel(OUTPUT_CHANNEL_SIZE);
+        let process = self
+            .run_one_shot(shell, command, &working_dir, &environment, size, tx)
+            .map(|process| ProcessHandle {
+                process,
+                shared_shell: false,
+            });
+        CommandHandle { events: rx, process }
+    }
 
-                // Read stdout
-                let mut stdout_lines = stdout_reader.lines();
-                while let Ok(Some(line)) = stdout_lines.next_line().await {
-                    output.push_str(&line);
-                    output.push('\n');
+    /// Run a single command inside a pseudo-terminal so that programs checking
+    /// `isatty` (pagers, color output, password prompts) behave normally.
+    fn run_one_shot(
+        &self,
+        shell: &str,
+        command: String,
+        working_dir: &Path,
+        environment: &HashMap<String, String>,
+        size: PtySize,
+        events: mpsc::Sender<CommandEvent>,
+    ) -> Option<Arc<PtyProcess>> {
+        let args = vec!["-c".to_string(), command];
+        let (process, reader) =
+            match PtyProcess::spawn(shell, &args, working_dir, environment, size) {
+                Ok(spawned) => spawned,
+                Err(e) => {
+                    CommandEvent::fail(&events, diagnostics::spawn_failure(shell, working_dir, &e.to_string()));
+                    return None;
                 }
+            };
 
-                // Read stderr
-                let mut stderr_lines = stderr_reader.lines();
-                while let Ok(Some(line)) = stderr_lines.next_line().await {
-                    error_output.push_str(&line);
-                    error_output.push('\n');
-                }
+        let id = Uuid::new_v4();
+        let process = Arc::new(process);
+        let registry = self.ptys.clone();
+        registry.insert(id, process.clone());
 
-                let exit_status = child.wait().await.unwrap_or_else(|_| {
-                    std::process::ExitStatus::from_raw(1)
-                });
+        let child = process.clone();
+        std::thread::spawn(move || {
+            pty::read_chunks(reader, |chunk| {
+                // Keep draining even if nobody is listening so the child can exit
+                let _ = events.blocking_send(CommandEvent::Output(chunk.to_vec()));
+            });
+            let (exit_code, usage) = child.wait();
+            registry.remove(&id);
 
-                let exit_code = exit_status.code().unwrap_or(1);
-                
-                let combined_output = if !error_output.is_empty() {
-                    format!("{}\n{}", output, error_output)
-                } else {
-                    output
-                };
+            let _ = events.blocking_send(CommandEvent::Finished(CommandResult {
+                exit_code,
+                working_dir: None,
+                usage,
+                environment: None,
+            }));
+        });
 
-                (combined_output, exit_code)
-            }
-            Err(e) => {
-                (format!("Failed to execute command: {}", e), 1)
-            }
-        }
+        Some(process)
     }
 
-    pub async fn execute_interactive_command(&mut self, command: String) -> tokio::sync::mpsc::Receiver<String> {
-        let (tx, rx) = tokio::sync::mpsc::channel(100);
-        
-        let shell = self.default_shell.clone();
-        tokio::spawn(async move {
-            let mut cmd = Command::new(shell);
-            cmd.arg("-c")
-               .arg(command)
-               .stdout(Stdio::piped())
-               .stderr(Stdio::piped());
-
-            if let Ok(mut child) = cmd.spawn() {
-                if let Some(stdout) = child.stdout.take() {
-                    let reader = BufReader::new(stdout);
-                    let mut lines = reader.lines();
-                    
-                    while let Ok(Some(line)) = lines.next_line().await {
-                        if tx.send(line).await.is_err() {
-                            break;
-                        }
-                    }
-                }
-                
-                let _ = child.wait().await;
+    /// Update the terminal size of a session's shell and of the commands it starts from now on.
+    ///
+    /// Commands already running in their own terminal are resized through their `ProcessHandle`.
+    pub fn resize_session(&mut self, id: &Uuid, size: PtySize) {
+        let Some(session) = self.active_sessions.get_mut(id) else {
+            return;
+        };
+        if session.size == size {
+            return;
+        }
+        session.size = size;
+
+        if let Some(process) = self.ptys.get(id) {
+            if let Err(e) = process.resize(size) {
+                eprintln!("Failed to resize pty: {}", e);
             }
-        });
+        }
+    }
 
-        rx
+    /// Terminal size of a session, or the default for unknown sessions
+    pub fn pty_size(&self, id: &Uuid) -> PtySize {
+        self.active_sessions.get(id).map_or(self.pty_size, |session| session.size)
     }
 
     fn detect_shell() -> String {
@@ -118,21 +199,433 @@ impl ShellManager {
             })
     }
 
-    pub fn create_session(&mut self) -> Uuid {
-        let session = ShellSession {
+    /// Start a session with the given profile's shell, environment and directory, or the defaults
+    pub fn create_session(&mut self, profile: Option<&ShellProfile>) -> Uuid {
+        let mut environment: HashMap<String, String> = std::env::vars().collect();
+        environment.extend(self.environment.clone());
+        if let Some(profile) = profile {
+            environment.extend(profile.environment.clone());
+        }
+        self.create_session_with(profile, self.startup_dir(profile), environment)
+    }
+
+    /// The shell a profile runs, falling back to the configured default for anything it leaves unset
+    fn program_for(&self, profile: Option<&ShellProfile>) -> ShellProgram {
+        let Some(profile) = profile else {
+            return self.default_shell.clone();
+        };
+
+        let args = if profile.shell.is_some() || !profile.args.is_empty() {
+            profile.args.clone()
+        } else {
+            self.default_shell.args.clone()
+        };
+        ShellProgram {
+            path: profile.shell.clone().unwrap_or_else(|| self.default_shell.path.clone()),
+            args,
+            login: profile.login.unwrap_or(self.default_shell.login),
+        }
+    }
+
+    fn startup_dir(&self, profile: Option<&ShellProfile>) -> PathBuf {
+        let dir = match (profile.and_then(|profile| profile.working_directory.as_deref()), &self.working_directory) {
+            (Some(path), _) => Some(expand_home(path)),
+            (None, WorkingDirectoryBehavior::Home) => dirs::home_dir(),
+            (None, WorkingDirectoryBehavior::LastUsed) => self.last_used_dir.clone(),
+            (None, WorkingDirectoryBehavior::Custom(path)) => Some(expand_home(path)),
+        };
+
+        dir.filter(|dir| dir.is_dir())
+            .or_else(|| std::env::current_dir().ok())
+            .unwrap_or_default()
+    }
+
+    /// Record where a session's shell is now, as reported after each command
+    pub fn set_working_dir(&mut self, id: &Uuid, dir: PathBuf) {
+        if let Some(session) = self.active_sessions.get_mut(id) {
+            session.set_working_dir(dir.clone());
+        }
+        self.last_used_dir = Some(dir);
+    }
+
+    /// Replace a session's environment with what its shell now exports, so one-shot commands and
+    /// saved sessions see the variables exported in it. Variables the shell maintains keep the
+    /// values the session was started with.
+    pub fn set_environment(&mut self, id: &Uuid, mut environment: HashMap<String, String>) {
+        let Some(session) = self.active_sessions.get_mut(id) else {
+            return;
+        };
+        for name in shell_integration::SHELL_MANAGED_VARIABLES {
+            match session.environment.get(name) {
+                Some(value) => environment.insert(name.to_string(), value.clone()),
+                None => environment.remove(name),
+            };
+        }
+        session.environment = environment;
+    }
+
+    pub fn last_used_dir(&self) -> Option<&PathBuf> {
+        self.last_used_dir.as_ref()
+    }
+
+    /// Seed `WorkingDirectoryBehavior::LastUsed` with the directory saved by a previous run
+    pub fn set_last_used_dir(&mut self, dir: PathBuf) {
+        self.last_used_dir.get_or_insert(dir);
+    }
+
+    /// Start a session with a profile's shell in the given directory and environment,
+    /// e.g. one restored from a previous run
+    pub fn create_session_with(
+        &mut self,
+        profile: Option<&ShellProfile>,
+        working_dir: PathBuf,
+        environment: HashMap<String, String>,
+    ) -> Uuid {
+        let mut session = ShellSession {
             id: Uuid::new_v4(),
-            working_dir: std::env::current_dir().unwrap_or_default(),
-            environment: std::env::vars().collect(),
+            working_dir,
+            environment,
+            size: self.pty_size,
+            program: self.program_for(profile),
+            shell: None,
         };
-        
+
+        match InteractiveShell::spawn(&session.program, &session.working_dir, &session.environment, session.size) {
+            Ok(shell) => {
+                self.ptys.insert(session.id, shell.process.clone());
+                session.shell = Some(Arc::new(shell));
+            }
+            Err(e) => {
+                // Commands still work, they just don't share state between blocks
+                eprintln!("Falling back to one-shot commands for {}: {}", session.program.path, e);
+            }
+        }
+
         let id = session.id;
         self.active_sessions.insert(id, session);
         id
     }
 
+    pub fn close_session(&mut self, id: &Uuid) {
+        self.ptys.remove(id);
+        self.active_sessions.remove(id);
+    }
+
     pub fn get_session(&self, id: &Uuid) -> Option<&ShellSession> {
         self.active_sessions.get(id)
     }
+
+    pub fn get_session_mut(&mut self, id: &Uuid) -> Option<&mut ShellSession> {
+        self.active_sessions.get_mut(id)
+    }
+}
+
+/// Expand a leading `~` to the home directory
+fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix('~'), dirs::home_dir()) {
+        (Some(rest), Some(home)) if rest.is_empty() || rest.starts_with('/') => {
+            home.join(rest.trim_start_matches('/'))
+        }
+        _ => PathBuf::from(path),
+    }
+}
+
+/// Number of output chunks buffered per command before the reader waits for the UI
+const OUTPUT_CHANNEL_SIZE: usize = 100;
+
+/// A command started by `ShellManager::execute_command`
+#[derive(Debug)]
+pub struct CommandHandle {
+    pub events: mpsc::Receiver<CommandEvent>,
+    pub process: Option<ProcessHandle>,
+}
+
+/// Access to the terminal a command runs in, for sending it input and signals
+#[derive(Debug, Clone)]
+pub struct ProcessHandle {
+    process: Arc<PtyProcess>,
+    /// The terminal belongs to the session's shell rather than to this command alone
+    shared_shell: bool,
+}
+
+impl ProcessHandle {
+    /// Write bytes to the command as if they had been typed
+    pub fn write(&self, bytes: &[u8]) -> std::io::Result<()> {
+        self.process.write(bytes)
+    }
+
+    /// Signal the command's foreground job
+    pub fn signal(&self, signal: Signal) -> std::io::Result<()> {
+        // With no job in the foreground the shell itself is the target, and killing it ends the session
+        #[cfg(unix)]
+        if self.shared_shell
+            && signal == Signal::Kill
+            && self.process.foreground_process_group().map(|group| group as u32) == self.process.pid()
+        {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::Other,
+                "refusing to kill the session shell",
+            ));
+        }
+
+        self.process.signal_foreground(signal)
+    }
+
+    /// Resize the terminal the command draws on
+    pub fn resize(&self, size: PtySize) {
+        if let Err(e) = self.process.resize(size) {
+            eprintln!("Failed to resize pty: {}", e);
+        }
+    }
+}
+
+/// Progress of a command line run through a `ShellManager`
+#[derive(Debug, Clone)]
+pub enum CommandEvent {
+    /// Bytes the command wrote to its terminal
+    Output(Vec<u8>),
+    /// The command could not be run; `Finished` still follows
+    Failed(Diagnosis),
+    Finished(CommandResult),
+}
+
+impl CommandEvent {
+    /// Report a command that could not run, followed by a failed exit.
+    ///
+    /// Only used before any output was sent, so the channel always has room.
+    fn fail(events: &mpsc::Sender<CommandEvent>, diagnosis: Diagnosis) {
+        let _ = events.try_send(CommandEvent::Failed(diagnosis));
+        let _ = events.try_send(CommandEvent::Finished(CommandResult::failed()));
+    }
+}
+
+/// Outcome of a command line run through a `ShellManager`
+#[derive(Debug, Clone)]
+pub struct CommandResult {
+    pub exit_code: i32,
+    /// The shell's working directory once the command finished, when known
+    pub working_dir: Option<PathBuf>,
+    pub usage: Option<ResourceUsage>,
+    /// The variables the shell exports, when they changed since the last command reported them
+    pub environment: Option<HashMap<String, String>>,
+}
+
+impl CommandResult {
+    fn failed() -> Self {
+        Self {
+            exit_code: 1,
+            working_dir: None,
+            usage: None,
+            environment: None,
+        }
+    }
+}
+
+/// Commands submitted to an interactive shell, in the order it runs them
+#[derive(Default)]
+struct CommandQueue {
+    waiting: VecDeque<mpsc::Sender<CommandEvent>>,
+    /// The command whose output is currently being received
+    current: Option<mpsc::Sender<CommandEvent>>,
+}
+
+/// A long-lived interactive shell that runs a session's commands in sequence.
+///
+/// Block boundaries are recovered from the OSC 133 markers emitted by the
+/// integration scripts: output between `C` and `D` belongs to the command,
+/// and `D` carries its exit status.
+pub struct InteractiveShell {
+    kind: ShellKind,
+    process: Arc<PtyProcess>,
+    queue: Arc<Mutex<CommandQueue>>,
+    alive: Arc<AtomicBool>,
+}
+
+impl std::fmt::Debug for InteractiveShell {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("InteractiveShell")
+            .field("process", &self.process)
+            .field("alive", &self.is_alive())
+            .field("busy", &self.is_busy())
+            .finish()
+    }
+}
+
+impl InteractiveShell {
+    pub fn spawn(
+        shell: &ShellProgram,
+        working_dir: &Path,
+        environment: &HashMap<String, String>,
+        size: PtySize,
+    ) -> anyhow::Result<Self> {
+        let kind = ShellKind::detect(&shell.path);
+        if !kind.supports_integration() {
+            anyhow::bail!("shell integration is not available for {}", shell.path);
+        }
+
+        let mut spec = shell_integration::prepare(kind, shell.login)?;
+        spec.args.extend(shell.args.iter().cloned());
+        let mut environment = environment.clone();
+        environment.extend(spec.env);
+
+        let (process, reader) = PtyProcess::spawn(&shell.path, &spec.args, working_dir, &environment, size)?;
+
+        let interactive = Self {
+            kind,
+            process: Arc::new(process),
+            queue: Arc::new(Mutex::new(CommandQueue::default())),
+            alive: Arc::new(AtomicBool::new(true)),
+        };
+
+        let process = interactive.process.clone();
+        let queue = interactive.queue.clone();
+        let alive = interactive.alive.clone();
+        std::thread::spawn(move || Self::read_loop(reader, process, queue, alive));
+
+        Ok(interactive)
+    }
+
+    pub fn is_alive(&self) -> bool {
+        self.alive.load(Ordering::SeqCst)
+    }
+
+    /// A command is running or queued; anything written now would reach its stdin
+    pub fn is_busy(&self) -> bool {
+        self.queue
+            .lock()
+            .map(|queue| queue.current.is_some() || !queue.waiting.is_empty())
+            .unwrap_or(true)
+    }
+
+    /// Submit a command line; its output streams to the receiver until the shell reports it finished
+    pub fn run(&self, command: &str) -> mpsc::Receiver<CommandEvent> {
+        let (tx, rx) = mpsc::channel(OUTPUT_CHANNEL_SIZE);
+
+        // The reply slot has to exist before the shell answers, but the queue must not be held while
+        // writing: the reader needs it to drain output, and a full PTY would block the write forever
+        match self.queue.lock() {
+            Ok(mut queue) => queue.waiting.push_back(tx.clone()),
+            Err(_) => return rx,
+        }
+
+        if let Err(e) = self.process.write(command_line(self.kind, command).as_bytes()) {
+            if let Ok(mut queue) = self.queue.lock() {
+                queue.waiting.retain(|waiting| !waiting.same_channel(&tx));
+            }
+            CommandEvent::fail(&tx, Diagnosis::new("Failed to write to shell", Some(e.to_string())));
+        }
+
+        rx
+    }
+
+    fn read_loop(
+        mut reader: Box<dyn Read + Send>,
+        process: Arc<PtyProcess>,
+        queue: Arc<Mutex<CommandQueue>>,
+        alive: Arc<AtomicBool>,
+    ) {
+        let mut scanner = MarkerScanner::new();
+        let mut working_dir = None;
+        // Exported variables reported since the last finished command, passed on with the next
+        let mut environment = None;
+        // The shell's children CPU time when the current command started; the
+        // command's own is the increase once the shell has waited for it
+        let mut cpu_at_start = None;
+        // Set once the first prompt is drawn; bash and zsh report a `D` before it that belongs to no command
+        let mut ready = false;
+        let mut buf = [0u8; 8192];
+
+        loop {
+            let n = match reader.read(&mut buf) {
+                Ok(0) => break,
+                Ok(n) => n,
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                Err(_) => break,
+            };
+
+            for segment in scanner.feed(&buf[..n]) {
+                match segment {
+                    Segment::Output(bytes) => {
+                        // Prompts and the echoed command line fall outside C..D and are dropped
+                        let current = queue.lock().ok().and_then(|queue| queue.current.clone());
+                        if let Some(events) = current {
+                            let _ = events.blocking_send(CommandEvent::Output(bytes));
+                        }
+                    }
+                    Segment::Marker(Marker::PromptStart) => ready = true,
+                    Segment::Marker(Marker::OutputStart) => {
+                        cpu_at_start = process.children_cpu_time();
+                        if let Ok(mut queue) = queue.lock() {
+                            queue.current = queue.waiting.pop_front();
+                        }
+                    }
+                    Segment::Marker(Marker::WorkingDirectory(dir)) => {
+                        working_dir = Some(dir);
+                    }
+                    Segment::Marker(Marker::Environment(exported)) => {
+                        environment = Some(exported);
+                    }
+                    Segment::Marker(Marker::CommandFinished(exit_code)) => {
+                        // A `D` without a `C` is a line that never executed, such as a syntax error
+                        let finished = queue.lock().ok().and_then(|mut queue| {
+                            queue.current.take().or_else(|| {
+                                if ready {
+                                    queue.waiting.pop_front()
+                                } else {
+                                    None
+                                }
+                            })
+                        });
+                        // The shell's /proc entry has no memory peak for its children, so only CPU time is known
+                        let usage = cpu_at_start
+                            .take()
+                            .zip(process.children_cpu_time())
+                            .map(|(start, end)| ResourceUsage {
+                                cpu_time: end.saturating_sub(start),
+                                peak_rss_kb: None,
+                            });
+                        if let Some(events) = finished {
+                            let _ = events.blocking_send(CommandEvent::Finished(CommandResult {
+                                exit_code: exit_code.unwrap_or(0),
+                                working_dir: working_dir.clone(),
+                                usage,
+                                environment: environment.take(),
+                            }));
+                        }
+                    }
+                    Segment::Marker(_) => {}
+                }
+            }
+        }
+
+        alive.store(false, Ordering::SeqCst);
+        if let Ok(mut queue) = queue.lock() {
+            if let Some(events) = queue.current.take() {
+                let _ = events.blocking_send(CommandEvent::Finished(CommandResult::failed()));
+            }
+            for events in queue.waiting.drain(..) {
+                CommandEvent::fail(&events, Diagnosis::new("Shell session exited", None));
+            }
+        }
+    }
+}
+
+/// The line to type for `command`. The lines of a multi-line command are grouped so that the shell
+/// runs them as one command, reporting a single start and finish and the last line's exit code.
+fn command_line(kind: ShellKind, command: &str) -> String {
+    if !command.contains('\n') {
+        return format!("{}\n", command);
+    }
+    match kind {
+        ShellKind::Fish => format!("begin\n{}\nend\n", command),
+        _ => format!("{{\n{}\n}}\n", command),
+    }
+}
+
+impl Drop for InteractiveShell {
+    fn drop(&mut self) {
+        self.process.kill();
+    }
 }
 
 impl ShellSession {
@@ -147,4 +640,231 @@ impl ShellSession {
     pub fn get_working_dir(&self) -> &std::path::PathBuf {
         &self.working_dir
     }
-}
\ No newline at end of file
+
+    pub fn environment(&self) -> &HashMap<String, String> {
+        &self.environment
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Bash with the temporary directory as its home, so the user's startup files stay out of it
+    #[cfg(unix)]
+    fn bash_preferences() -> Option<GeneralPreferences> {
+        Path::new("/bin/bash").exists().then(|| GeneralPreferences {
+            default_shell: Some("/bin/bash".to_string()),
+            environment: HashMap::from([("HOME".to_string(), std::env::temp_dir().to_string_lossy().into_owned())]),
+            ..Default::default()
+        })
+    }
+
+    #[cfg(unix)]
+    fn bash() -> Option<InteractiveShell> {
+        let preferences = bash_preferences()?;
+        let shell = ShellProgram {
+            path: "/bin/bash".to_string(),
+            args: Vec::new(),
+            login: false,
+        };
+        Some(InteractiveShell::spawn(&shell, &std::env::temp_dir(), &preferences.environment, pty::default_size()).unwrap())
+    }
+
+    /// A command's output and result, once it finishes
+    fn finish(mut events: mpsc::Receiver<CommandEvent>) -> (String, CommandResult) {
+        let mut output = Vec::new();
+        loop {
+            match events.blocking_recv().expect("the command to finish") {
+                CommandEvent::Output(bytes) => output.extend(bytes),
+                CommandEvent::Failed(diagnosis) => panic!("{:?}", diagnosis),
+                CommandEvent::Finished(result) => return (String::from_utf8_lossy(&output).into_owned(), result),
+            }
+        }
+    }
+
+    #[test]
+    fn test_multi_line_commands_are_grouped() {
+        assert_eq!(command_line(ShellKind::Bash, "ls -l"), "ls -l\n");
+        assert_eq!(command_line(ShellKind::Zsh, "cd /\npwd"), "{\ncd /\npwd\n}\n");
+        assert_eq!(command_line(ShellKind::Fish, "cd /\npwd"), "begin\ncd /\npwd\nend\n");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_two_line_command_finishes_once() {
+        let Some(shell) = bash() else {
+            return;
+        };
+
+        let (output, result) = finish(shell.run("echo first\n(exit 3)"));
+        assert_eq!(result.exit_code, 3);
+        assert!(output.contains("first"));
+
+        // The next command gets its own block rather than the rest of the first
+        let (output, result) = finish(shell.run("echo second"));
+        assert_eq!(result.exit_code, 0);
+        assert!(output.contains("second"));
+    }
+
+    #[test]
+    fn test_configured_shell_is_used_unless_blank() {
+        let manager = ShellManager::new(&GeneralPreferences {
+            default_shell: Some("/usr/bin/fish".to_string()),
+            shell_args: vec!["--private".to_string()],
+            login_shell: true,
+            ..Default::default()
+        });
+        assert_eq!(manager.default_shell.path, "/usr/bin/fish");
+        assert_eq!(manager.default_shell.args, ["--private"]);
+        assert!(manager.default_shell.login);
+
+        let blank = ShellManager::new(&GeneralPreferences {
+            default_shell: Some("  ".to_string()),
+            ..Default::default()
+        });
+        assert_eq!(blank.default_shell.path, ShellManager::detect_shell());
+    }
+
+    #[test]
+    fn test_startup_directory_follows_the_behavior() {
+        let manager = |behavior| {
+            ShellManager::new(&GeneralPreferences {
+                working_directory: behavior,
+                ..Default::default()
+            })
+        };
+        let dir = tempfile::tempdir().unwrap();
+        let current = std::env::current_dir().unwrap();
+
+        let custom = manager(WorkingDirectoryBehavior::Custom(dir.path().to_string_lossy().into_owned()));
+        assert_eq!(custom.startup_dir(None), dir.path());
+        // A directory that is gone falls back to the current one
+        let missing = manager(WorkingDirectoryBehavior::Custom(dir.path().join("gone").to_string_lossy().into_owned()));
+        assert_eq!(missing.startup_dir(None), current);
+
+        let mut last_used = manager(WorkingDirectoryBehavior::LastUsed);
+        assert_eq!(last_used.startup_dir(None), current);
+        last_used.set_working_dir(&Uuid::new_v4(), dir.path().to_path_buf());
+        // The directory saved by a previous run does not replace one used in this run
+        last_used.set_last_used_dir(PathBuf::from("/"));
+        assert_eq!(last_used.startup_dir(None), dir.path());
+
+        if let Some(home) = dirs::home_dir().filter(|home| home.is_dir()) {
+            assert_eq!(manager(WorkingDirectoryBehavior::Home).startup_dir(None), home);
+        }
+    }
+
+    fn profile(name: &str) -> ShellProfile {
+        ShellProfile {
+            name: name.to_string(),
+            shell: None,
+            args: Vec::new(),
+            login: None,
+            environment: HashMap::new(),
+            working_directory: None,
+            theme: None,
+            startup_script: None,
+        }
+    }
+
+    #[test]
+    fn test_profiles_fall_back_to_the_default_shell() {
+        let manager = ShellManager::new(&GeneralPreferences {
+            default_shell: Some("/bin/bash".to_string()),
+            shell_args: vec!["--noprofile".to_string()],
+            ..Default::default()
+        });
+
+        let plain = manager.program_for(Some(&profile("plain")));
+        assert_eq!((plain.path.as_str(), plain.args.as_slice(), plain.login), ("/bin/bash", &["--noprofile".to_string()][..], false));
+
+        // Arguments for the default shell do not carry over to another one
+        let fish = manager.program_for(Some(&ShellProfile {
+            shell: Some("/usr/bin/fish".to_string()),
+            login: Some(true),
+            ..profile("fish")
+        }));
+        assert_eq!((fish.path.as_str(), fish.args.as_slice(), fish.login), ("/usr/bin/fish", &[][..], true));
+    }
+
+    #[test]
+    fn test_profile_environment_and_directory_override_the_general_ones() {
+        let mut manager = ShellManager::new(&GeneralPreferences {
+            default_shell: Some("/bin/sh".to_string()),
+            environment: HashMap::from([
+                ("NEOTERM_TEST_GENERAL".to_string(), "general".to_string()),
+                ("NEOTERM_TEST_SHARED".to_string(), "general".to_string()),
+            ]),
+            ..Default::default()
+        });
+        let dir = tempfile::tempdir().unwrap();
+        let work = ShellProfile {
+            environment: HashMap::from([("NEOTERM_TEST_SHARED".to_string(), "profile".to_string())]),
+            working_directory: Some(dir.path().to_string_lossy().into_owned()),
+            ..profile("work")
+        };
+
+        let id = manager.create_session(Some(&work));
+        let session = manager.get_session(&id).unwrap();
+        assert_eq!(session.environment["NEOTERM_TEST_GENERAL"], "general");
+        assert_eq!(session.environment["NEOTERM_TEST_SHARED"], "profile");
+        assert!(session.environment.contains_key("PATH"));
+        assert_eq!(session.working_dir, dir.path());
+        manager.close_session(&id);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_commands_report_the_directory_they_finish_in() {
+        let Some(shell) = bash() else {
+            return;
+        };
+        let dir = tempfile::tempdir().unwrap();
+        let (_, result) = finish(shell.run(&format!("cd '{}'", dir.path().display())));
+        assert_eq!(result.working_dir.as_deref(), Some(dir.path()));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_queued_commands_keep_their_own_output() {
+        let Some(shell) = bash() else {
+            return;
+        };
+
+        let first = shell.run("sleep 0.2; echo one");
+        assert!(shell.is_busy());
+        let second = shell.run("echo two");
+        // Reports D without C
+        let broken = shell.run("fi");
+        let third = shell.run("echo three");
+
+        let (output, _) = finish(first);
+        assert!(output.contains("one") && !output.contains("two"));
+        let (output, _) = finish(second);
+        assert!(output.contains("two") && !output.contains("one"));
+        assert_ne!(finish(broken).1.exit_code, 0);
+        let (output, _) = finish(third);
+        assert!(output.contains("three") && !output.contains("two"));
+        assert!(!shell.is_busy());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_busy_session_runs_commands_alongside() {
+        let Some(preferences) = bash_preferences() else {
+            return;
+        };
+        let mut manager = ShellManager::new(&preferences);
+        let session = manager.create_session(None);
+
+        let slow = manager.execute_command(session, "sleep 0.5; echo slow".to_string());
+        assert!(slow.process.as_ref().is_some_and(|handle| handle.shared_shell));
+        let fast = manager.execute_command(session, "echo fast".to_string());
+        assert!(fast.process.as_ref().is_some_and(|handle| !handle.shared_shell));
+
+        let (output, _) = finish(fast.events);
+        assert!(output.contains("fast") && !output.contains("slow"));
+        let (output, _) = finish(slow.events);
+        assert!(output.contains("slow") && !output.contains("fast"));
+    }
+}
@@ -0,0 +1,542 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+/// Upper bound on a buffered, unterminated OSC sequence before it is flushed as output
+const MAX_PENDING_OSC: usize = 4096;
+/// Environment reports carry every exported variable, so they may grow far longer than other sequences
+const MAX_PENDING_ENVIRONMENT: usize = 256 * 1024;
+/// Start of the private OSC the integration scripts report the exported environment with, as
+/// `NAME=value` entries each ending in a unit separator
+const ENVIRONMENT_PREFIX: &[u8] = b"\x1b]6973;E;";
+/// Variables the shell or its integration maintain, which a new session must set up afresh
+pub const SHELL_MANAGED_VARIABLES: [&str; 6] = ["PWD", "OLDPWD", "SHLVL", "_", "ZDOTDIR", "NEOTERM_USER_ZDOTDIR"];
+
+const BASH_STARTUP: &str = r#"# NeoTerm shell integration for bash
+if [ -f "$HOME/.bashrc" ]; then
+    . "$HOME/.bashrc"
+fi
+"#;
+
+/// `--rcfile` only applies to non-login shells, so a login shell's profile is read from the rcfile instead
+const BASH_LOGIN_STARTUP: &str = r#"# NeoTerm shell integration for bash login shells
+if [ -f /etc/profile ]; then
+    . /etc/profile
+fi
+for __neoterm_profile in "$HOME/.bash_profile" "$HOME/.bash_login" "$HOME/.profile"; do
+    if [ -f "$__neoterm_profile" ]; then
+        . "$__neoterm_profile"
+        break
+    fi
+done
+unset __neoterm_profile
+"#;
+
+const BASH_INTEGRATION: &str = r#"
+# Report the exported variables when they change; values that would end the sequence are left out
+__neoterm_report_env() {
+    local name value report=
+    for name in $(compgen -e); do
+        value="${!name}"
+        case "$value" in *[$'\a\e\x1f']*) continue ;; esac
+        report+="${name}=${value}"$'\x1f'
+    done
+    if [[ "$report" != "${__neoterm_last_env-}" ]]; then
+        __neoterm_last_env="$report"
+        printf '\033]6973;E;%s\007' "$report"
+    fi
+}
+
+__neoterm_precmd() {
+    local ret=$?
+    printf '\033]7;file://%s%s\007' "${HOSTNAME}" "${PWD}"
+    __neoterm_report_env
+    printf '\033]133;D;%s\007' "$ret"
+    printf '\033]133;A\007'
+}
+
+# Must run first so that $? still holds the status of the user's command
+if [[ "${PROMPT_COMMAND}" != *"__neoterm_precmd"* ]]; then
+    PROMPT_COMMAND="__neoterm_precmd${PROMPT_COMMAND:+;${PROMPT_COMMAND}}"
+fi
+PS1="${PS1}\[\e]133;B\a\]"
+PS0="${PS0}"$'\e]133;C\a'
+"#;
+
+const ZSH_ENV: &str = r#"# NeoTerm shell integration for zsh
+if [[ -f "${NEOTERM_USER_ZDOTDIR:-$HOME}/.zshenv" ]]; then
+    source "${NEOTERM_USER_ZDOTDIR:-$HOME}/.zshenv"
+fi
+"#;
+
+const ZSH_PROFILE: &str = r#"# NeoTerm shell integration for zsh login shells
+if [[ -f "${NEOTERM_USER_ZDOTDIR:-$HOME}/.zprofile" ]]; then
+    source "${NEOTERM_USER_ZDOTDIR:-$HOME}/.zprofile"
+fi
+"#;
+
+/// Login shells read `.zlogin` after `.zshrc`, so `ZDOTDIR` points here until then
+const ZSH_LOGIN: &str = r#"# NeoTerm shell integration for zsh login shells
+ZDOTDIR="${NEOTERM_USER_ZDOTDIR:-$HOME}"
+unset NEOTERM_USER_ZDOTDIR
+if [[ -f "$ZDOTDIR/.zlogin" ]]; then
+    source "$ZDOTDIR/.zlogin"
+fi
+"#;
+
+const ZSH_INTEGRATION: &str = r#"# NeoTerm shell integration for zsh
+__neoterm_zdotdir="$ZDOTDIR"
+ZDOTDIR="${NEOTERM_USER_ZDOTDIR:-$HOME}"
+if [[ -f "$ZDOTDIR/.zshrc" ]]; then
+    source "$ZDOTDIR/.zshrc"
+fi
+if [[ -o login ]]; then
+    ZDOTDIR="$__neoterm_zdotdir"
+else
+    unset NEOTERM_USER_ZDOTDIR
+fi
+unset __neoterm_zdotdir
+
+# Report the exported variables when they change; values that would end the sequence are left out
+__neoterm_report_env() {
+    local name value report=
+    for name in ${(k)parameters[(R)*export*]}; do
+        value="${(P)name}"
+        [[ "$value" == *[$'\a\e\x1f']* ]] && continue
+        report+="${name}=${value}"$'\x1f'
+    done
+    if [[ "$report" != "${__neoterm_last_env-}" ]]; then
+        __neoterm_last_env="$report"
+        printf '\033]6973;E;%s\007' "$report"
+    fi
+}
+
+__neoterm_precmd() {
+    local ret=$?
+    printf '\033]7;file://%s%s\007' "${HOST}" "${PWD}"
+    __neoterm_report_env
+    printf '\033]133;D;%s\007' "$ret"
+    printf '\033]133;A\007'
+}
+
+__neoterm_preexec() {
+    printf '\033]133;C\007'
+}
+
+# Must run first so that $? still holds the status of the user's command
+precmd_functions=(__neoterm_precmd ${precmd_functions[@]})
+preexec_functions+=(__neoterm_preexec)
+PS1="${PS1}%{"$'\e]133;B\a'"%}"
+"#;
+
+const FISH_INTEGRATION: &str = r#"# NeoTerm shell integration for fish
+function __neoterm_preexec --on-event fish_preexec
+    printf '\e]133;C\a'
+end
+
+# Report the exported variables when they change; values that would end the sequence are left out
+function __neoterm_report_env
+    set -l report
+    for name in (set -xn)
+        # fish exports lists joined with colons for path variables and spaces otherwise
+        set -l separator ' '
+        string match -q -- '*PATH' $name; and set separator ':'
+        set -l value (string join -- $separator $$name)
+        string match -q -r -- '[\a\e\x1f]' "$value"; and continue
+        set -a report "$name=$value"
+    end
+    set -l report (string join -- \x1f $report)\x1f
+    if test "$report" != "$__neoterm_last_env"
+        set -g __neoterm_last_env $report
+        printf '\e]6973;E;%s\a' "$report"
+    end
+end
+
+function __neoterm_postexec --on-event fish_postexec
+    set -l ret $status
+    printf '\e]7;file://%s%s\a' (hostname) "$PWD"
+    __neoterm_report_env
+    printf '\e]133;D;%s\a' $ret
+end
+
+function __neoterm_prompt --on-event fish_prompt
+    printf '\e]133;A\a'
+end
+"#;
+
+/// Shells for which NeoTerm can inject prompt markers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShellKind {
+    Bash,
+    Zsh,
+    Fish,
+    Other,
+}
+
+impl ShellKind {
+    pub fn detect(shell: &str) -> Self {
+        let name = Path::new(shell)
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .unwrap_or(shell);
+
+        match name {
+            "bash" => ShellKind::Bash,
+            "zsh" => ShellKind::Zsh,
+            "fish" => ShellKind::Fish,
+            _ => ShellKind::Other,
+        }
+    }
+
+    pub fn supports_integration(&self) -> bool {
+        !matches!(self, ShellKind::Other)
+    }
+}
+
+/// Arguments and environment needed to start a shell with integration enabled
+#[derive(Debug, Clone, Default)]
+pub struct LaunchSpec {
+    pub args: Vec<String>,
+    pub env: HashMap<String, String>,
+}
+
+/// Write the integration scripts for `kind` and describe how to launch the shell with them,
+/// as a login shell if `login` is set
+pub fn prepare(kind: ShellKind, login: bool) -> std::io::Result<LaunchSpec> {
+    let dir = integration_dir()?;
+
+    let mut spec = LaunchSpec::default();
+
+    match kind {
+        ShellKind::Bash => {
+            let rcfile = dir.join(if login { "bash_login" } else { "bashrc" });
+            let startup = if login { BASH_LOGIN_STARTUP } else { BASH_STARTUP };
+            std::fs::write(&rcfile, format!("{}{}", startup, BASH_INTEGRATION))?;
+            spec.args = vec![
+                "--rcfile".to_string(),
+                rcfile.to_string_lossy().into_owned(),
+                "-i".to_string(),
+            ];
+        }
+        ShellKind::Zsh => {
+            let zdotdir = dir.join("zsh");
+            std::fs::create_dir_all(&zdotdir)?;
+            std::fs::write(zdotdir.join(".zshenv"), ZSH_ENV)?;
+            std::fs::write(zdotdir.join(".zprofile"), ZSH_PROFILE)?;
+            std::fs::write(zdotdir.join(".zshrc"), ZSH_INTEGRATION)?;
+            std::fs::write(zdotdir.join(".zlogin"), ZSH_LOGIN)?;
+            if let Ok(user_zdotdir) = std::env::var("ZDOTDIR") {
+                spec.env.insert("NEOTERM_USER_ZDOTDIR".to_string(), user_zdotdir);
+            }
+            spec.env.insert("ZDOTDIR".to_string(), zdotdir.to_string_lossy().into_owned());
+            spec.args = vec!["-i".to_string()];
+            if login {
+                spec.args.push("-l".to_string());
+            }
+        }
+        ShellKind::Fish => {
+            let script = dir.join("neoterm.fish");
+            std::fs::write(&script, FISH_INTEGRATION)?;
+            spec.args = vec![
+                "--interactive".to_string(),
+                "--init-command".to_string(),
+                format!("source '{}'", script.to_string_lossy()),
+            ];
+            if login {
+                spec.args.push("--login".to_string());
+            }
+        }
+        ShellKind::Other => {}
+    }
+
+    Ok(spec)
+}
+
+/// A directory for the scripts that only this user can write to, since every shell sources them.
+/// It is made with a random name so nobody can create it first, and lasts as long as the process.
+fn integration_dir() -> std::io::Result<&'static Path> {
+    static DIR: OnceLock<tempfile::TempDir> = OnceLock::new();
+    if let Some(dir) = DIR.get() {
+        return Ok(dir.path());
+    }
+    let mut builder = tempfile::Builder::new();
+    builder.prefix("neoterm-shell-integration-");
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        builder.permissions(std::fs::Permissions::from_mode(0o700));
+    }
+    // Fails rather than reusing a directory that is already there
+    let dir = builder.tempdir()?;
+    Ok(DIR.get_or_init(|| dir).path())
+}
+
+/// Semantic prompt markers emitted by the integration scripts
+#[derive(Debug, Clone, PartialEq)]
+pub enum Marker {
+    /// OSC 133;A — the shell is about to draw a prompt
+    PromptStart,
+    /// OSC 133;B — the prompt has been drawn, user input follows
+    CommandStart,
+    /// OSC 133;C — the command line was accepted and its output follows
+    OutputStart,
+    /// OSC 133;D[;code] — the command finished
+    CommandFinished(Option<i32>),
+    /// OSC 7 — the shell's current working directory
+    WorkingDirectory(PathBuf),
+    /// OSC 6973;E — the variables the shell exports
+    Environment(HashMap<String, String>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Segment {
+    Output(Vec<u8>),
+    Marker(Marker),
+}
+
+/// Splits a terminal byte stream into plain output and shell-integration markers.
+///
+/// Markers may straddle read boundaries, so an incomplete OSC sequence is
+/// held back until the rest of it arrives.
+#[derive(Debug, Default)]
+pub struct MarkerScanner {
+    pending: Vec<u8>,
+}
+
+impl MarkerScanner {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn feed(&mut self, bytes: &[u8]) -> Vec<Segment> {
+        let mut data = std::mem::take(&mut self.pending);
+        data.extend_from_slice(bytes);
+
+        let mut segments = Vec::new();
+        let mut output = Vec::new();
+        let mut i = 0;
+
+        while i < data.len() {
+            if data[i] == 0x1b && data.get(i + 1) == Some(&b']') {
+                match find_osc_end(&data[i + 2..]) {
+                    Some((payload_len, terminator_len)) => {
+                        let payload = &data[i + 2..i + 2 + payload_len];
+                        let end = i + 2 + payload_len + terminator_len;
+
+                        if let Some(marker) = parse_marker(payload) {
+                            if !output.is_empty() {
+                                segments.push(Segment::Output(std::mem::take(&mut output)));
+                            }
+                            segments.push(Segment::Marker(marker));
+                        } else {
+                            output.extend_from_slice(&data[i..end]);
+                        }
+                        i = end;
+                        continue;
+                    }
+                    None if data.len() - i <= max_pending(&data[i..]) => {
+                        self.pending = data[i..].to_vec();
+                        break;
+                    }
+                    None => {}
+                }
+            } else if data[i] == 0x1b && i + 1 == data.len() {
+                // A lone ESC at the end may be the start of a marker
+                self.pending = vec![0x1b];
+                break;
+            }
+
+            output.push(data[i]);
+            i += 1;
+        }
+
+        if !output.is_empty() {
+            segments.push(Segment::Output(output));
+        }
+
+        segments
+    }
+}
+
+/// How long an unterminated sequence may grow before it is given up on as a marker
+fn max_pending(sequence: &[u8]) -> usize {
+    if sequence.starts_with(ENVIRONMENT_PREFIX) {
+        MAX_PENDING_ENVIRONMENT
+    } else {
+        MAX_PENDING_OSC
+    }
+}
+
+/// Locate the BEL or ST terminating an OSC payload, returning the payload and terminator lengths
+fn find_osc_end(data: &[u8]) -> Option<(usize, usize)> {
+    let mut i = 0;
+    while i < data.len() {
+        match data[i] {
+            0x07 => return Some((i, 1)),
+            0x1b if data.get(i + 1) == Some(&b'\\') => return Some((i, 2)),
+            _ => i += 1,
+        }
+    }
+    None
+}
+
+fn parse_marker(payload: &[u8]) -> Option<Marker> {
+    let payload = std::str::from_utf8(payload).ok()?;
+
+    if let Some(rest) = payload.strip_prefix("133;") {
+        let mut parts = rest.split(';');
+        return match parts.next()? {
+            "A" => Some(Marker::PromptStart),
+            "B" => Some(Marker::CommandStart),
+            "C" => Some(Marker::OutputStart),
+            "D" => Some(Marker::CommandFinished(
+                parts.next().and_then(|code| code.trim().parse().ok()),
+            )),
+            _ => None,
+        };
+    }
+
+    if let Some(report) = payload.strip_prefix("6973;E;") {
+        let environment = report
+            .split('\x1f')
+            .filter_map(|entry| entry.split_once('='))
+            .filter(|(name, _)| !name.is_empty())
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+        return Some(Marker::Environment(environment));
+    }
+
+    if let Some(uri) = payload.strip_prefix("7;") {
+        // The host is the machine name, which `Url::to_file_path` rejects, so only the path is used
+        let url = url::Url::parse(uri).ok()?;
+        if url.scheme() != "file" {
+            return None;
+        }
+        return Some(Marker::WorkingDirectory(PathBuf::from(percent_decode(url.path()))));
+    }
+
+    None
+}
+
+fn percent_decode(input: &str) -> String {
+    let bytes = input.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            if let Ok(byte) = u8::from_str_radix(&input[i + 1..i + 3], 16) {
+                decoded.push(byte);
+                i += 3;
+                continue;
+            }
+        }
+        decoded.push(bytes[i]);
+        i += 1;
+    }
+
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_shell_kind() {
+        assert_eq!(ShellKind::detect("/bin/bash"), ShellKind::Bash);
+        assert_eq!(ShellKind::detect("/usr/local/bin/zsh"), ShellKind::Zsh);
+        assert_eq!(ShellKind::detect("fish"), ShellKind::Fish);
+        assert_eq!(ShellKind::detect("/bin/sh"), ShellKind::Other);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_integration_dir_is_private() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = integration_dir().unwrap();
+        assert_eq!(std::fs::metadata(dir).unwrap().permissions().mode() & 0o777, 0o700);
+        assert_eq!(integration_dir().unwrap(), dir);
+        assert!(!dir.ends_with(format!("neoterm-shell-integration-{}", std::process::id())));
+    }
+
+    #[test]
+    fn test_zsh_startup_files_forward_to_the_users() {
+        let spec = prepare(ShellKind::Zsh, true).unwrap();
+        assert_eq!(spec.args, ["-i", "-l"]);
+        let zdotdir = PathBuf::from(&spec.env["ZDOTDIR"]);
+        for file in [".zshenv", ".zprofile", ".zshrc", ".zlogin"] {
+            let script = std::fs::read_to_string(zdotdir.join(file)).unwrap();
+            // Each sources the user's own file of the same name
+            assert!(script.contains("NEOTERM_USER_ZDOTDIR"), "{}", file);
+            assert!(script.contains(&format!("/{}\"", file)), "{}", file);
+        }
+    }
+
+    #[test]
+    fn test_scanner_extracts_markers() {
+        let mut scanner = MarkerScanner::new();
+        let segments = scanner.feed(b"echo hi\r\n\x1b]133;C\x07hi\r\n\x1b]133;D;0\x07");
+
+        assert_eq!(
+            segments,
+            vec![
+                Segment::Output(b"echo hi\r\n".to_vec()),
+                Segment::Marker(Marker::OutputStart),
+                Segment::Output(b"hi\r\n".to_vec()),
+                Segment::Marker(Marker::CommandFinished(Some(0))),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_scanner_handles_split_sequences() {
+        let mut scanner = MarkerScanner::new();
+        let mut segments = scanner.feed(b"out\x1b]13");
+        segments.extend(scanner.feed(b"3;D;127\x1b\\"));
+
+        assert_eq!(
+            segments,
+            vec![
+                Segment::Output(b"out".to_vec()),
+                Segment::Marker(Marker::CommandFinished(Some(127))),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_scanner_passes_through_other_osc() {
+        let mut scanner = MarkerScanner::new();
+        let title = b"\x1b]0;window title\x07".to_vec();
+        assert_eq!(scanner.feed(&title), vec![Segment::Output(title)]);
+    }
+
+    #[test]
+    fn test_scanner_parses_environment() {
+        let mut scanner = MarkerScanner::new();
+        let mut report = b"\x1b]6973;E;".to_vec();
+        report.extend_from_slice(b"EDITOR=vim\x1fSHLVL=2\x1fLONG=");
+        report.extend(std::iter::repeat_n(b'x', MAX_PENDING_OSC));
+        // Longer than other sequences may be, and split across reads
+        let mut segments = scanner.feed(&report);
+        segments.extend(scanner.feed(b"\x1f\x07"));
+
+        let environment = HashMap::from([
+            ("EDITOR".to_string(), "vim".to_string()),
+            ("SHLVL".to_string(), "2".to_string()),
+            ("LONG".to_string(), "x".repeat(MAX_PENDING_OSC)),
+        ]);
+        assert_eq!(segments, vec![Segment::Marker(Marker::Environment(environment))]);
+    }
+
+    #[test]
+    fn test_scanner_parses_working_directory() {
+        let mut scanner = MarkerScanner::new();
+        let segments = scanner.feed(b"\x1b]7;file://host/tmp/my%20project\x07");
+        assert_eq!(
+            segments,
+            vec![Segment::Marker(Marker::WorkingDirectory(PathBuf::from("/tmp/my project")))]
+        );
+    }
+}
@@ -0,0 +1,75 @@
+use iced::Color;
+
+use crate::config::{AnsiColors, ColorScheme, ColorValue};
+use super::grid::{Cell, TermColor};
+
+/// Levels used by the 6x6x6 color cube of the xterm 256-color palette
+const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+/// Foreground and background for a cell after applying inverse, dim and the theme palette
+pub fn cell_colors(cell: &Cell, colors: &ColorScheme) -> (Color, Color) {
+    // Bold text in one of the eight base colors is shown in its bright variant
+    let fg = match cell.fg {
+        TermColor::Indexed(index) if cell.attrs.bold && index < 8 => TermColor::Indexed(index + 8),
+        fg => fg,
+    };
+
+    let mut fg = resolve(fg, colors, &colors.terminal_foreground);
+    let mut bg = resolve(cell.bg, colors, &colors.terminal_background);
+
+    if cell.attrs.inverse {
+        std::mem::swap(&mut fg, &mut bg);
+    }
+    if cell.attrs.dim {
+        fg = fg.scale_alpha(0.6);
+    }
+    if cell.attrs.hidden {
+        fg = bg;
+    }
+
+    (fg, bg)
+}
+
+/// Map a terminal color through the theme, using `default` for the terminal's default color
+pub fn resolve(color: TermColor, colors: &ColorScheme, default: &ColorValue) -> Color {
+    match color {
+        TermColor::Default => default.clone().into(),
+        TermColor::Indexed(index) => indexed(index, &colors.ansi_colors),
+        TermColor::Rgb(r, g, b) => Color::from_rgb8(r, g, b),
+    }
+}
+
+fn indexed(index: u8, ansi: &AnsiColors) -> Color {
+    let value = match index {
+        0 => &ansi.black,
+        1 => &ansi.red,
+        2 => &ansi.green,
+        3 => &ansi.yellow,
+        4 => &ansi.blue,
+        5 => &ansi.magenta,
+        6 => &ansi.cyan,
+        7 => &ansi.white,
+        8 => &ansi.bright_black,
+        9 => &ansi.bright_red,
+        10 => &ansi.bright_green,
+        11 => &ansi.bright_yellow,
+        12 => &ansi.bright_blue,
+        13 => &ansi.bright_magenta,
+        14 => &ansi.bright_cyan,
+        15 => &ansi.bright_white,
+        16..=231 => {
+            let index = index - 16;
+            return Color::from_rgb8(
+                CUBE_LEVELS[(index / 36) as usize],
+                CUBE_LEVELS[((index / 6) % 6) as usize],
+                CUBE_LEVELS[(index % 6) as usize],
+            );
+        }
+        232..=255 => {
+            let level = 8 + (index - 232) * 10;
+            return Color::from_rgb8(level, level, level);
+        }
+    };
+
+    value.clone().into()
+}
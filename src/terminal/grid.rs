@@ -0,0 +1,473 @@
+use std::collections::VecDeque;
+
+/// A color as requested by the program, resolved against the theme at render time
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TermColor {
+    #[default]
+    Default,
+    Indexed(u8),
+    Rgb(u8, u8, u8),
+}
+
+/// A hyperlink set with OSC 8, as an index plus one into [`crate::terminal::Terminal`]'s table of them
+pub type LinkId = std::num::NonZeroU16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct CellAttributes {
+    pub bold: bool,
+    pub dim: bool,
+    pub italic: bool,
+    pub underline: bool,
+    pub blink: bool,
+    pub inverse: bool,
+    pub hidden: bool,
+    pub strikethrough: bool,
+}
+
+/// Graphic rendition applied to newly printed characters (set via SGR)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Pen {
+    pub fg: TermColor,
+    pub bg: TermColor,
+    pub attrs: CellAttributes,
+}
+
+impl Pen {
+    /// SGR sequence that resets the rendition and then applies this one
+    pub fn sgr(&self) -> String {
+        let mut params = vec!["0".to_string()];
+        let flags = [
+            (self.attrs.bold, "1"),
+            (self.attrs.dim, "2"),
+            (self.attrs.italic, "3"),
+            (self.attrs.underline, "4"),
+            (self.attrs.blink, "5"),
+            (self.attrs.inverse, "7"),
+            (self.attrs.hidden, "8"),
+            (self.attrs.strikethrough, "9"),
+        ];
+        params.extend(flags.iter().filter(|(set, _)| *set).map(|(_, code)| code.to_string()));
+
+        match self.fg {
+            TermColor::Default => {}
+            TermColor::Indexed(n @ 0..=7) => params.push((30 + n).to_string()),
+            TermColor::Indexed(n @ 8..=15) => params.push((90 + n - 8).to_string()),
+            TermColor::Indexed(n) => params.push(format!("38;5;{}", n)),
+            TermColor::Rgb(r, g, b) => params.push(format!("38;2;{};{};{}", r, g, b)),
+        }
+        match self.bg {
+            TermColor::Default => {}
+            TermColor::Indexed(n @ 0..=7) => params.push((40 + n).to_string()),
+            TermColor::Indexed(n @ 8..=15) => params.push((100 + n - 8).to_string()),
+            TermColor::Indexed(n) => params.push(format!("48;5;{}", n)),
+            TermColor::Rgb(r, g, b) => params.push(format!("48;2;{};{};{}", r, g, b)),
+        }
+
+        format!("\x1b[{}m", params.join(";"))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cell {
+    pub ch: char,
+    pub fg: TermColor,
+    pub bg: TermColor,
+    pub attrs: CellAttributes,
+    pub link: Option<LinkId>,
+}
+
+impl Default for Cell {
+    fn default() -> Self {
+        Self {
+            ch: ' ',
+            fg: TermColor::Default,
+            bg: TermColor::Default,
+            attrs: CellAttributes::default(),
+            link: None,
+        }
+    }
+}
+
+impl Cell {
+    fn blank(pen: &Pen) -> Self {
+        // Erased cells keep the current background (xterm's background color erase)
+        Self {
+            bg: pen.bg,
+            ..Self::default()
+        }
+    }
+
+    /// Nothing visible is drawn for this cell
+    pub fn is_blank(&self) -> bool {
+        self.ch == ' ' && self.bg == TermColor::Default && !self.attrs.inverse
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Row {
+    pub cells: Vec<Cell>,
+    /// The line continues on the next row because it hit the right margin
+    pub wrapped: bool,
+}
+
+impl Row {
+    fn new(width: usize, pen: &Pen) -> Self {
+        Self {
+            cells: vec![Cell::blank(pen); width],
+            wrapped: false,
+        }
+    }
+
+    fn resize(&mut self, width: usize) {
+        self.cells.resize(width, Cell::default());
+    }
+
+    pub fn is_blank(&self) -> bool {
+        self.cells.iter().all(Cell::is_blank)
+    }
+
+    /// The row's characters with trailing whitespace removed
+    pub fn text(&self) -> String {
+        let text: String = self.cells.iter().map(|cell| cell.ch).collect();
+        text.trim_end().to_string()
+    }
+
+    /// The row's characters with the SGR sequences needed to reproduce their
+    /// colors and attributes, ending with the rendition reset
+    pub fn ansi(&self) -> String {
+        let end = self.cells.iter().rposition(|cell| !cell.is_blank()).map_or(0, |i| i + 1);
+        let mut ansi = String::new();
+        let mut current = Pen::default();
+
+        for cell in &self.cells[..end] {
+            let pen = Pen {
+                fg: cell.fg,
+                bg: cell.bg,
+                attrs: cell.attrs,
+            };
+            if pen != current {
+                ansi.push_str(&pen.sgr());
+                current = pen;
+            }
+            ansi.push(cell.ch);
+        }
+
+        if current != Pen::default() {
+            ansi.push_str("\x1b[0m");
+        }
+        ansi
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Cursor {
+    pub row: usize,
+    pub col: usize,
+}
+
+/// A screen of `height` rows by `width` columns plus the lines scrolled off its top
+#[derive(Debug, Clone)]
+pub struct Grid {
+    rows: Vec<Row>,
+    scrollback: VecDeque<Row>,
+    scrollback_limit: usize,
+    width: usize,
+    height: usize,
+    pub cursor: Cursor,
+    /// Set after printing in the last column; the next character wraps first
+    wrap_pending: bool,
+    scroll_top: usize,
+    scroll_bottom: usize,
+}
+
+impl Grid {
+    pub fn new(width: usize, height: usize, scrollback_limit: usize) -> Self {
+        let width = width.max(1);
+        let height = height.max(1);
+        let pen = Pen::default();
+
+        Self {
+            rows: (0..height).map(|_| Row::new(width, &pen)).collect(),
+            scrollback: VecDeque::new(),
+            scrollback_limit,
+            width,
+            height,
+            cursor: Cursor::default(),
+            wrap_pending: false,
+            scroll_top: 0,
+            scroll_bottom: height - 1,
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn rows(&self) -> &[Row] {
+        &self.rows
+    }
+
+    pub fn scrollback(&self) -> &VecDeque<Row> {
+        &self.scrollback
+    }
+
+    pub fn resize(&mut self, width: usize, height: usize) {
+        let width = width.max(1);
+        let height = height.max(1);
+
+        for row in self.rows.iter_mut().chain(self.scrollback.iter_mut()) {
+            row.resize(width);
+        }
+
+        // Shrinking keeps the cursor on screen by pushing the top rows into scrollback
+        while self.rows.len() > height {
+            if self.cursor.row > 0 {
+                let row = self.rows.remove(0);
+                self.push_scrollback(row);
+                self.cursor.row -= 1;
+            } else {
+                self.rows.pop();
+            }
+        }
+        while self.rows.len() < height {
+            self.rows.push(Row::new(width, &Pen::default()));
+        }
+
+        self.width = width;
+        self.height = height;
+        self.scroll_top = 0;
+        self.scroll_bottom = height - 1;
+        self.cursor.row = self.cursor.row.min(height - 1);
+        self.cursor.col = self.cursor.col.min(width - 1);
+        self.wrap_pending = false;
+    }
+
+    pub fn put_char(&mut self, ch: char, pen: &Pen, link: Option<LinkId>, autowrap: bool) {
+        if self.wrap_pending {
+            if autowrap {
+                self.rows[self.cursor.row].wrapped = true;
+                self.cursor.col = 0;
+                self.linefeed(pen);
+            }
+            self.wrap_pending = false;
+        }
+
+        let col = self.cursor.col.min(self.width - 1);
+        self.rows[self.cursor.row].cells[col] = Cell {
+            ch,
+            fg: pen.fg,
+            bg: pen.bg,
+            attrs: pen.attrs,
+            link,
+        };
+
+        if col + 1 >= self.width {
+            self.wrap_pending = true;
+        } else {
+            self.cursor.col = col + 1;
+        }
+    }
+
+    pub fn linefeed(&mut self, pen: &Pen) {
+        if self.cursor.row == self.scroll_bottom {
+            self.scroll_up(1, pen);
+        } else if self.cursor.row + 1 < self.height {
+            self.cursor.row += 1;
+        }
+        self.wrap_pending = false;
+    }
+
+    pub fn reverse_index(&mut self, pen: &Pen) {
+        if self.cursor.row == self.scroll_top {
+            self.scroll_down(1, pen);
+        } else if self.cursor.row > 0 {
+            self.cursor.row -= 1;
+        }
+        self.wrap_pending = false;
+    }
+
+    pub fn carriage_return(&mut self) {
+        self.cursor.col = 0;
+        self.wrap_pending = false;
+    }
+
+    pub fn backspace(&mut self) {
+        self.cursor.col = self.cursor.col.saturating_sub(1);
+        self.wrap_pending = false;
+    }
+
+    pub fn tab(&mut self) {
+        let next = (self.cursor.col / 8 + 1) * 8;
+        self.cursor.col = next.min(self.width - 1);
+    }
+
+    /// Move to an absolute zero-based position, clamped to the screen
+    pub fn move_to(&mut self, row: usize, col: usize) {
+        self.cursor.row = row.min(self.height - 1);
+        self.cursor.col = col.min(self.width - 1);
+        self.wrap_pending = false;
+    }
+
+    pub fn move_by(&mut self, rows: isize, cols: isize) {
+        let row = (self.cursor.row as isize + rows).max(0) as usize;
+        let col = (self.cursor.col as isize + cols).max(0) as usize;
+        self.move_to(row, col);
+    }
+
+    /// Scroll the scroll region up, feeding scrollback when it starts at the top of the screen
+    pub fn scroll_up(&mut self, count: usize, pen: &Pen) {
+        for _ in 0..count.min(self.scroll_bottom - self.scroll_top + 1) {
+            let row = self.rows.remove(self.scroll_top);
+            if self.scroll_top == 0 {
+                self.push_scrollback(row);
+            }
+            self.rows.insert(self.scroll_bottom, Row::new(self.width, pen));
+        }
+    }
+
+    pub fn scroll_down(&mut self, count: usize, pen: &Pen) {
+        for _ in 0..count.min(self.scroll_bottom - self.scroll_top + 1) {
+            self.rows.remove(self.scroll_bottom);
+            self.rows.insert(self.scroll_top, Row::new(self.width, pen));
+        }
+    }
+
+    /// Set the scroll region from zero-based inclusive bounds
+    pub fn set_scroll_region(&mut self, top: usize, bottom: usize) {
+        let bottom = bottom.min(self.height - 1);
+        if top < bottom {
+            self.scroll_top = top;
+            self.scroll_bottom = bottom;
+        } else {
+            self.scroll_top = 0;
+            self.scroll_bottom = self.height - 1;
+        }
+        self.move_to(0, 0);
+    }
+
+    pub fn erase_display(&mut self, mode: u16, pen: &Pen) {
+        let Cursor { row, col } = self.cursor;
+        match mode {
+            0 => {
+                self.erase_cells(row, col, self.width, pen);
+                for r in row + 1..self.height {
+                    self.rows[r] = Row::new(self.width, pen);
+                }
+            }
+            1 => {
+                for r in 0..row {
+                    self.rows[r] = Row::new(self.width, pen);
+                }
+                self.erase_cells(row, 0, col + 1, pen);
+            }
+            2 => {
+                for r in 0..self.height {
+                    self.rows[r] = Row::new(self.width, pen);
+                }
+            }
+            3 => self.scrollback.clear(),
+            _ => {}
+        }
+    }
+
+    pub fn erase_line(&mut self, mode: u16, pen: &Pen) {
+        let Cursor { row, col } = self.cursor;
+        match mode {
+            0 => self.erase_cells(row, col, self.width, pen),
+            1 => self.erase_cells(row, 0, col + 1, pen),
+            2 => self.erase_cells(row, 0, self.width, pen),
+            _ => {}
+        }
+        self.rows[row].wrapped = false;
+    }
+
+    /// Blank `count` cells starting at the cursor without moving anything (ECH)
+    pub fn erase_chars(&mut self, count: usize, pen: &Pen) {
+        let Cursor { row, col } = self.cursor;
+        self.erase_cells(row, col, col + count, pen);
+    }
+
+    pub fn insert_chars(&mut self, count: usize, pen: &Pen) {
+        let Cursor { row, col } = self.cursor;
+        let cells = &mut self.rows[row].cells;
+        for _ in 0..count.min(self.width - col) {
+            cells.insert(col, Cell::blank(pen));
+        }
+        cells.truncate(self.width);
+    }
+
+    pub fn delete_chars(&mut self, count: usize, pen: &Pen) {
+        let Cursor { row, col } = self.cursor;
+        let cells = &mut self.rows[row].cells;
+        for _ in 0..count.min(self.width - col) {
+            cells.remove(col);
+            cells.push(Cell::blank(pen));
+        }
+    }
+
+    pub fn insert_lines(&mut self, count: usize, pen: &Pen) {
+        let row = self.cursor.row;
+        if row < self.scroll_top || row > self.scroll_bottom {
+            return;
+        }
+        for _ in 0..count.min(self.scroll_bottom - row + 1) {
+            self.rows.remove(self.scroll_bottom);
+            self.rows.insert(row, Row::new(self.width, pen));
+        }
+        self.cursor.col = 0;
+    }
+
+    pub fn delete_lines(&mut self, count: usize, pen: &Pen) {
+        let row = self.cursor.row;
+        if row < self.scroll_top || row > self.scroll_bottom {
+            return;
+        }
+        for _ in 0..count.min(self.scroll_bottom - row + 1) {
+            self.rows.remove(row);
+            self.rows.insert(self.scroll_bottom, Row::new(self.width, pen));
+        }
+        self.cursor.col = 0;
+    }
+
+    /// Reset every cell and the cursor, keeping scrollback
+    pub fn clear(&mut self) {
+        let pen = Pen::default();
+        for row in self.rows.iter_mut() {
+            *row = Row::new(self.width, &pen);
+        }
+        self.cursor = Cursor::default();
+        self.wrap_pending = false;
+        self.scroll_top = 0;
+        self.scroll_bottom = self.height - 1;
+    }
+
+    /// Index of the last screen row holding content or the cursor
+    pub fn last_used_row(&self) -> usize {
+        self.rows
+            .iter()
+            .rposition(|row| !row.is_blank())
+            .unwrap_or(0)
+            .max(self.cursor.row)
+    }
+
+    fn erase_cells(&mut self, row: usize, from: usize, to: usize, pen: &Pen) {
+        let to = to.min(self.width);
+        for cell in &mut self.rows[row].cells[from.min(to)..to] {
+            *cell = Cell::blank(pen);
+        }
+    }
+
+    fn push_scrollback(&mut self, row: Row) {
+        if self.scrollback_limit == 0 {
+            return;
+        }
+        if self.scrollback.len() >= self.scrollback_limit {
+            self.scrollback.pop_front();
+        }
+        self.scrollback.push_back(row);
+    }
+}
@@ -0,0 +1,198 @@
+use iced::keyboard::key::Named;
+use iced::keyboard::{Key, Modifiers};
+
+use super::{Modes, MouseTracking};
+
+/// Mouse buttons as numbered in xterm mouse reports
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MouseButton {
+    Left = 0,
+    Middle = 1,
+    Right = 2,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MouseAction {
+    Press(MouseButton),
+    Release(MouseButton),
+    /// The pointer moved to a new cell, possibly while a button is held
+    Motion(Option<MouseButton>),
+    ScrollUp,
+    ScrollDown,
+}
+
+/// Bytes a terminal sends for a key press, or `None` for keys with no encoding
+pub fn encode_key(key: &Key, modifiers: Modifiers, text: Option<&str>, modes: &Modes) -> Option<Vec<u8>> {
+    let bytes = match key {
+        Key::Named(named) => encode_named(*named, modifiers, modes)?,
+        Key::Character(ch) if modifiers.control() => control_character(ch)?,
+        Key::Character(ch) => text.unwrap_or(ch.as_str()).as_bytes().to_vec(),
+        Key::Unidentified => text?.as_bytes().to_vec(),
+    };
+
+    // Alt is sent as an ESC prefix, except for sequences that already start with one
+    if modifiers.alt() && bytes.first() != Some(&0x1b) {
+        let mut prefixed = vec![0x1b];
+        prefixed.extend(bytes);
+        return Some(prefixed);
+    }
+
+    Some(bytes)
+}
+
+fn encode_named(key: Named, modifiers: Modifiers, modes: &Modes) -> Option<Vec<u8>> {
+    let modifier = modifier_parameter(modifiers);
+
+    let bytes = match key {
+        Named::Enter => b"\r".to_vec(),
+        Named::Backspace => b"\x7f".to_vec(),
+        Named::Tab if modifiers.shift() => b"\x1b[Z".to_vec(),
+        Named::Tab => b"\t".to_vec(),
+        Named::Escape => b"\x1b".to_vec(),
+        Named::Space if modifiers.control() => vec![0],
+        Named::Space => b" ".to_vec(),
+        Named::ArrowUp => cursor_key(b'A', modifier, modes),
+        Named::ArrowDown => cursor_key(b'B', modifier, modes),
+        Named::ArrowRight => cursor_key(b'C', modifier, modes),
+        Named::ArrowLeft => cursor_key(b'D', modifier, modes),
+        Named::Home => cursor_key(b'H', modifier, modes),
+        Named::End => cursor_key(b'F', modifier, modes),
+        Named::Insert => tilde_key(2, modifier),
+        Named::Delete => tilde_key(3, modifier),
+        Named::PageUp => tilde_key(5, modifier),
+        Named::PageDown => tilde_key(6, modifier),
+        Named::F1 => function_key(b'P', modifier),
+        Named::F2 => function_key(b'Q', modifier),
+        Named::F3 => function_key(b'R', modifier),
+        Named::F4 => function_key(b'S', modifier),
+        Named::F5 => tilde_key(15, modifier),
+        Named::F6 => tilde_key(17, modifier),
+        Named::F7 => tilde_key(18, modifier),
+        Named::F8 => tilde_key(19, modifier),
+        Named::F9 => tilde_key(20, modifier),
+        Named::F10 => tilde_key(21, modifier),
+        Named::F11 => tilde_key(23, modifier),
+        Named::F12 => tilde_key(24, modifier),
+        _ => return None,
+    };
+
+    Some(bytes)
+}
+
+/// xterm's modifier parameter: 1 + shift(1) + alt(2) + ctrl(4), or `None` without modifiers
+fn modifier_parameter(modifiers: Modifiers) -> Option<u8> {
+    let value = modifiers.shift() as u8 | (modifiers.alt() as u8) << 1 | (modifiers.control() as u8) << 2;
+    (value != 0).then_some(value + 1)
+}
+
+fn cursor_key(code: u8, modifier: Option<u8>, modes: &Modes) -> Vec<u8> {
+    match modifier {
+        Some(modifier) => format!("\x1b[1;{}{}", modifier, code as char).into_bytes(),
+        None if modes.application_cursor_keys => vec![0x1b, b'O', code],
+        None => vec![0x1b, b'[', code],
+    }
+}
+
+fn tilde_key(number: u8, modifier: Option<u8>) -> Vec<u8> {
+    match modifier {
+        Some(modifier) => format!("\x1b[{};{}~", number, modifier).into_bytes(),
+        None => format!("\x1b[{}~", number).into_bytes(),
+    }
+}
+
+fn function_key(code: u8, modifier: Option<u8>) -> Vec<u8> {
+    match modifier {
+        Some(modifier) => format!("\x1b[1;{}{}", modifier, code as char).into_bytes(),
+        None => vec![0x1b, b'O', code],
+    }
+}
+
+/// Ctrl+letter and the punctuation keys that map onto the C0 controls
+fn control_character(ch: &str) -> Option<Vec<u8>> {
+    let byte = match ch.chars().next()?.to_ascii_lowercase() {
+        ch @ 'a'..='z' => ch as u8 - b'a' + 1,
+        '@' | '2' => 0x00,
+        '[' | '3' => 0x1b,
+        '\\' | '4' => 0x1c,
+        ']' | '5' => 0x1d,
+        '^' | '6' => 0x1e,
+        '_' | '7' | '-' => 0x1f,
+        '8' | '?' => 0x7f,
+        _ => return None,
+    };
+    Some(vec![byte])
+}
+
+/// Encode a mouse event at a zero-based cell, honoring the tracking mode the program requested
+pub fn encode_mouse(action: MouseAction, col: usize, row: usize, modifiers: Modifiers, modes: &Modes) -> Option<Vec<u8>> {
+    let tracking = modes.mouse_tracking;
+    if tracking == MouseTracking::None {
+        return None;
+    }
+
+    let button = match action {
+        MouseAction::Press(button) => button as u8,
+        // The legacy encoding cannot say which button was released
+        MouseAction::Release(button) if modes.sgr_mouse => button as u8,
+        MouseAction::Release(_) => 3,
+        MouseAction::Motion(held) => match (tracking, held) {
+            (MouseTracking::Motion, None) => 32 + 3,
+            (MouseTracking::Motion, Some(button)) | (MouseTracking::Drag, Some(button)) => 32 + button as u8,
+            _ => return None,
+        },
+        MouseAction::ScrollUp => 64,
+        MouseAction::ScrollDown => 65,
+    };
+    let button = button
+        | (modifiers.shift() as u8) << 2
+        | (modifiers.alt() as u8) << 3
+        | (modifiers.control() as u8) << 4;
+
+    if modes.sgr_mouse {
+        let end = if matches!(action, MouseAction::Release(_)) { 'm' } else { 'M' };
+        return Some(format!("\x1b[<{};{};{}{}", button, col + 1, row + 1, end).into_bytes());
+    }
+
+    // Legacy X10 encoding only reaches column/row 223
+    let coordinate = |value: usize| (value + 1).min(223) as u8 + 32;
+    Some(vec![0x1b, b'[', b'M', button + 32, coordinate(col), coordinate(row)])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cursor_keys_follow_application_mode() {
+        let mut modes = Modes::default();
+        let up = Key::Named(Named::ArrowUp);
+        assert_eq!(encode_key(&up, Modifiers::empty(), None, &modes), Some(b"\x1b[A".to_vec()));
+
+        modes.application_cursor_keys = true;
+        assert_eq!(encode_key(&up, Modifiers::empty(), None, &modes), Some(b"\x1bOA".to_vec()));
+        assert_eq!(encode_key(&up, Modifiers::CTRL, None, &modes), Some(b"\x1b[1;5A".to_vec()));
+    }
+
+    #[test]
+    fn test_control_and_alt_characters() {
+        let modes = Modes::default();
+        let c = Key::Character("c".into());
+        assert_eq!(encode_key(&c, Modifiers::CTRL, Some("c"), &modes), Some(vec![0x03]));
+        assert_eq!(encode_key(&c, Modifiers::ALT, Some("c"), &modes), Some(b"\x1bc".to_vec()));
+    }
+
+    #[test]
+    fn test_mouse_reports() {
+        let mut modes = Modes::default();
+        let press = MouseAction::Press(MouseButton::Left);
+        assert_eq!(encode_mouse(press, 4, 2, Modifiers::empty(), &modes), None);
+
+        modes.mouse_tracking = MouseTracking::Click;
+        assert_eq!(encode_mouse(press, 4, 2, Modifiers::empty(), &modes), Some(b"\x1b[M %#".to_vec()));
+        assert_eq!(encode_mouse(MouseAction::Motion(None), 4, 2, Modifiers::empty(), &modes), None);
+
+        modes.sgr_mouse = true;
+        let release = MouseAction::Release(MouseButton::Left);
+        assert_eq!(encode_mouse(release, 4, 2, Modifiers::empty(), &modes), Some(b"\x1b[<0;5;3m".to_vec()));
+    }
+}
@@ -0,0 +1,693 @@
+pub mod color;
+pub mod grid;
+pub mod input;
+pub mod parser;
+pub mod view;
+
+pub use grid::*;
+pub use parser::{Parser, Perform};
+
+/// Modes toggled by DECSET/DECRST and SM/RM
+#[derive(Debug, Clone)]
+pub struct Modes {
+    pub autowrap: bool,
+    pub cursor_visible: bool,
+    pub application_cursor_keys: bool,
+    pub application_keypad: bool,
+    pub bracketed_paste: bool,
+    pub mouse_tracking: MouseTracking,
+    pub sgr_mouse: bool,
+}
+
+impl Default for Modes {
+    fn default() -> Self {
+        Self {
+            autowrap: true,
+            cursor_visible: true,
+            application_cursor_keys: false,
+            application_keypad: false,
+            bracketed_paste: false,
+            mouse_tracking: MouseTracking::None,
+            sgr_mouse: false,
+        }
+    }
+}
+
+/// Which mouse events the program asked to receive
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MouseTracking {
+    None,
+    /// 1000: button presses and releases
+    Click,
+    /// 1002: presses, releases and motion while a button is held
+    Drag,
+    /// 1003: all motion
+    Motion,
+}
+
+/// G0 character sets a program can designate
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Charset {
+    Ascii,
+    /// DEC special graphics, used for line drawing
+    DecSpecial,
+}
+
+/// A VT100/xterm compatible terminal emulator.
+///
+/// Bytes written by the child are fed in with [`Terminal::feed`]; the result
+/// is a grid of styled cells on either the primary screen (with scrollback)
+/// or the alternate screen used by full-screen programs.
+#[derive(Debug, Clone)]
+pub struct Terminal {
+    parser: Parser,
+    primary: Grid,
+    alternate: Grid,
+    alternate_active: bool,
+    pen: Pen,
+    saved_cursor: Option<(Cursor, Pen)>,
+    charset: Charset,
+    pub modes: Modes,
+    pub title: Option<String>,
+    /// Replies to status queries (DSR, DA) that must be written back to the child
+    responses: Vec<u8>,
+    /// Targets of the OSC 8 hyperlinks printed so far, referred to by cells
+    hyperlinks: Vec<String>,
+    /// The hyperlink newly printed characters belong to; unlike colors it survives SGR resets
+    link: Option<LinkId>,
+}
+
+impl Terminal {
+    pub fn new(cols: usize, rows: usize, scrollback_limit: usize) -> Self {
+        Self {
+            parser: Parser::new(),
+            primary: Grid::new(cols, rows, scrollback_limit),
+            // The alternate screen never has scrollback
+            alternate: Grid::new(cols, rows, 0),
+            alternate_active: false,
+            pen: Pen::default(),
+            saved_cursor: None,
+            charset: Charset::Ascii,
+            modes: Modes::default(),
+            title: None,
+            responses: Vec::new(),
+            hyperlinks: Vec::new(),
+            link: None,
+        }
+    }
+
+    pub fn feed(&mut self, bytes: &[u8]) {
+        let mut parser = std::mem::take(&mut self.parser);
+        parser.advance(self, bytes);
+        self.parser = parser;
+    }
+
+    pub fn resize(&mut self, cols: usize, rows: usize) {
+        self.primary.resize(cols, rows);
+        self.alternate.resize(cols, rows);
+    }
+
+    /// The screen currently being drawn to
+    pub fn grid(&self) -> &Grid {
+        if self.alternate_active {
+            &self.alternate
+        } else {
+            &self.primary
+        }
+    }
+
+    fn grid_mut(&mut self) -> &mut Grid {
+        if self.alternate_active {
+            &mut self.alternate
+        } else {
+            &mut self.primary
+        }
+    }
+
+    pub fn is_alternate_screen(&self) -> bool {
+        self.alternate_active
+    }
+
+    /// Scrollback followed by the primary screen up to its last used row.
+    ///
+    /// This is the transcript shown for a command block, independent of
+    /// whether a full-screen program is currently on the alternate screen.
+    pub fn lines(&self) -> impl Iterator<Item = &Row> {
+        let grid = &self.primary;
+        let used = if grid.is_empty_screen() { 0 } else { grid.last_used_row() + 1 };
+        grid.scrollback().iter().chain(grid.rows()[..used].iter())
+    }
+
+    /// Number of [`Terminal::lines`], without walking them
+    pub fn line_count(&self) -> usize {
+        let grid = &self.primary;
+        let used = if grid.is_empty_screen() { 0 } else { grid.last_used_row() + 1 };
+        grid.scrollback().len() + used
+    }
+
+    /// Nothing has been written to the primary screen yet
+    pub fn is_empty(&self) -> bool {
+        self.primary.scrollback().is_empty() && self.primary.is_empty_screen()
+    }
+
+    /// Plain text of [`Terminal::lines`], joining rows that were soft-wrapped
+    pub fn text(&self) -> String {
+        let mut text = String::new();
+        for row in self.lines() {
+            text.push_str(&row.text());
+            if !row.wrapped {
+                text.push('\n');
+            }
+        }
+        text.trim_end_matches('\n').to_string()
+    }
+
+    /// Like [`Terminal::text`], but keeping colors and attributes as SGR sequences
+    pub fn ansi_text(&self) -> String {
+        let mut text = String::new();
+        for row in self.lines() {
+            text.push_str(&row.ansi());
+            if !row.wrapped {
+                text.push('\n');
+            }
+        }
+        text.trim_end_matches('\n').to_string()
+    }
+
+    /// The target of a hyperlink a cell belongs to
+    pub fn hyperlink(&self, id: LinkId) -> Option<&str> {
+        self.hyperlinks.get(usize::from(id.get()) - 1).map(String::as_str)
+    }
+
+    /// Start or, for an empty URI, end an OSC 8 hyperlink
+    fn set_hyperlink(&mut self, uri: &str) {
+        if uri.is_empty() {
+            self.link = None;
+            return;
+        }
+        let index = match self.hyperlinks.iter().position(|known| known == uri) {
+            Some(index) => index,
+            // Past the table's capacity, text is printed without its link
+            None if self.hyperlinks.len() < usize::from(u16::MAX) => {
+                self.hyperlinks.push(uri.to_string());
+                self.hyperlinks.len() - 1
+            }
+            None => {
+                self.link = None;
+                return;
+            }
+        };
+        self.link = LinkId::new(index as u16 + 1);
+    }
+
+    /// Take any pending replies for the child process
+    pub fn take_responses(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.responses)
+    }
+
+    fn set_alternate_screen(&mut self, enabled: bool, save_cursor: bool) {
+        if enabled == self.alternate_active {
+            return;
+        }
+        if enabled {
+            if save_cursor {
+                self.save_cursor();
+            }
+            self.alternate_active = true;
+            self.alternate.clear();
+        } else {
+            self.alternate_active = false;
+            if save_cursor {
+                self.restore_cursor();
+            }
+        }
+    }
+
+    fn save_cursor(&mut self) {
+        self.saved_cursor = Some((self.grid().cursor, self.pen));
+    }
+
+    fn restore_cursor(&mut self) {
+        if let Some((cursor, pen)) = self.saved_cursor {
+            self.pen = pen;
+            self.grid_mut().move_to(cursor.row, cursor.col);
+        }
+    }
+
+    fn reset(&mut self) {
+        self.alternate_active = false;
+        self.pen = Pen::default();
+        self.link = None;
+        self.saved_cursor = None;
+        self.charset = Charset::Ascii;
+        self.modes = Modes::default();
+        self.primary.clear();
+        self.alternate.clear();
+    }
+
+    fn set_mode(&mut self, params: &[Vec<u16>], private: bool, enabled: bool) {
+        for param in params {
+            match (private, param[0]) {
+                (true, 1) => self.modes.application_cursor_keys = enabled,
+                (true, 7) => self.modes.autowrap = enabled,
+                (true, 25) => self.modes.cursor_visible = enabled,
+                (true, 47) | (true, 1047) => self.set_alternate_screen(enabled, false),
+                (true, 1049) => self.set_alternate_screen(enabled, true),
+                (true, 1048) => {
+                    if enabled {
+                        self.save_cursor()
+                    } else {
+                        self.restore_cursor()
+                    }
+                }
+                (true, 9) | (true, 1000) => self.set_mouse_tracking(MouseTracking::Click, enabled),
+                (true, 1002) => self.set_mouse_tracking(MouseTracking::Drag, enabled),
+                (true, 1003) => self.set_mouse_tracking(MouseTracking::Motion, enabled),
+                (true, 1006) => self.modes.sgr_mouse = enabled,
+                (true, 2004) => self.modes.bracketed_paste = enabled,
+                _ => {}
+            }
+        }
+    }
+
+    fn set_mouse_tracking(&mut self, mode: MouseTracking, enabled: bool) {
+        self.modes.mouse_tracking = if enabled { mode } else { MouseTracking::None };
+    }
+
+    fn set_graphic_rendition(&mut self, params: &[Vec<u16>]) {
+        if params.is_empty() {
+            self.pen = Pen::default();
+            return;
+        }
+
+        let mut i = 0;
+        while i < params.len() {
+            let param = &params[i];
+            let attrs = &mut self.pen.attrs;
+            match param[0] {
+                0 => self.pen = Pen::default(),
+                1 => attrs.bold = true,
+                2 => attrs.dim = true,
+                3 => attrs.italic = true,
+                4 => attrs.underline = param.get(1) != Some(&0),
+                5 | 6 => attrs.blink = true,
+                7 => attrs.inverse = true,
+                8 => attrs.hidden = true,
+                9 => attrs.strikethrough = true,
+                21 => attrs.underline = true,
+                22 => {
+                    attrs.bold = false;
+                    attrs.dim = false;
+                }
+                23 => attrs.italic = false,
+                24 => attrs.underline = false,
+                25 => attrs.blink = false,
+                27 => attrs.inverse = false,
+                28 => attrs.hidden = false,
+                29 => attrs.strikethrough = false,
+                30..=37 => self.pen.fg = TermColor::Indexed((param[0] - 30) as u8),
+                39 => self.pen.fg = TermColor::Default,
+                40..=47 => self.pen.bg = TermColor::Indexed((param[0] - 40) as u8),
+                49 => self.pen.bg = TermColor::Default,
+                90..=97 => self.pen.fg = TermColor::Indexed((param[0] - 90 + 8) as u8),
+                100..=107 => self.pen.bg = TermColor::Indexed((param[0] - 100 + 8) as u8),
+                38 | 48 => {
+                    let (color, consumed) = extended_color(params, i);
+                    if let Some(color) = color {
+                        if param[0] == 38 {
+                            self.pen.fg = color;
+                        } else {
+                            self.pen.bg = color;
+                        }
+                    }
+                    i += consumed;
+                }
+                _ => {}
+            }
+            i += 1;
+        }
+    }
+
+    fn report_status(&mut self, params: &[Vec<u16>]) {
+        match first(params, 0) {
+            5 => self.responses.extend_from_slice(b"\x1b[0n"),
+            6 => {
+                let cursor = self.grid().cursor;
+                self.responses
+                    .extend_from_slice(format!("\x1b[{};{}R", cursor.row + 1, cursor.col + 1).as_bytes());
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Perform for Terminal {
+    fn print(&mut self, ch: char) {
+        let ch = match self.charset {
+            Charset::DecSpecial => dec_special(ch),
+            Charset::Ascii => ch,
+        };
+        let pen = self.pen;
+        let link = self.link;
+        let autowrap = self.modes.autowrap;
+        self.grid_mut().put_char(ch, &pen, link, autowrap);
+    }
+
+    fn execute(&mut self, byte: u8) {
+        let pen = self.pen;
+        let grid = self.grid_mut();
+        match byte {
+            0x08 => grid.backspace(),
+            0x09 => grid.tab(),
+            0x0a..=0x0c => grid.linefeed(&pen),
+            0x0d => grid.carriage_return(),
+            _ => {}
+        }
+    }
+
+    fn csi_dispatch(&mut self, params: &[Vec<u16>], private: Option<u8>, intermediates: &[u8], action: char) {
+        if !intermediates.is_empty() {
+            return;
+        }
+
+        let pen = self.pen;
+        let count = first(params, 1).max(1) as usize;
+
+        match (private, action) {
+            (None, '@') => self.grid_mut().insert_chars(count, &pen),
+            (None, 'A') => self.grid_mut().move_by(-(count as isize), 0),
+            (None, 'B') | (None, 'e') => self.grid_mut().move_by(count as isize, 0),
+            (None, 'C') | (None, 'a') => self.grid_mut().move_by(0, count as isize),
+            (None, 'D') => self.grid_mut().move_by(0, -(count as isize)),
+            (None, 'E') => {
+                let grid = self.grid_mut();
+                grid.move_by(count as isize, 0);
+                grid.carriage_return();
+            }
+            (None, 'F') => {
+                let grid = self.grid_mut();
+                grid.move_by(-(count as isize), 0);
+                grid.carriage_return();
+            }
+            (None, 'G') | (None, '`') => {
+                let grid = self.grid_mut();
+                let row = grid.cursor.row;
+                grid.move_to(row, count - 1);
+            }
+            (None, 'H') | (None, 'f') => {
+                let row = first(params, 1).max(1) as usize;
+                let col = nth(params, 1, 1).max(1) as usize;
+                self.grid_mut().move_to(row - 1, col - 1);
+            }
+            (None, 'J') | (Some(b'?'), 'J') => self.grid_mut().erase_display(first(params, 0), &pen),
+            (None, 'K') | (Some(b'?'), 'K') => self.grid_mut().erase_line(first(params, 0), &pen),
+            (None, 'L') => self.grid_mut().insert_lines(count, &pen),
+            (None, 'M') => self.grid_mut().delete_lines(count, &pen),
+            (None, 'P') => self.grid_mut().delete_chars(count, &pen),
+            (None, 'S') => self.grid_mut().scroll_up(count, &pen),
+            (None, 'T') => self.grid_mut().scroll_down(count, &pen),
+            (None, 'X') => self.grid_mut().erase_chars(count, &pen),
+            (None, 'd') => {
+                let grid = self.grid_mut();
+                let col = grid.cursor.col;
+                grid.move_to(count - 1, col);
+            }
+            (None, 'm') => self.set_graphic_rendition(params),
+            (None, 'n') => self.report_status(params),
+            (None, 'c') => self.responses.extend_from_slice(b"\x1b[?6c"),
+            (Some(b'>'), 'c') => self.responses.extend_from_slice(b"\x1b[>0;10;1c"),
+            (None, 'r') => {
+                let height = self.grid().height();
+                let top = first(params, 1).max(1) as usize;
+                let bottom = match nth(params, 1, 0) {
+                    0 => height,
+                    bottom => bottom as usize,
+                };
+                self.grid_mut().set_scroll_region(top - 1, bottom - 1);
+            }
+            (None, 's') => self.save_cursor(),
+            (None, 'u') => self.restore_cursor(),
+            (Some(b'?'), 'h') => self.set_mode(params, true, true),
+            (Some(b'?'), 'l') => self.set_mode(params, true, false),
+            (None, 'h') => self.set_mode(params, false, true),
+            (None, 'l') => self.set_mode(params, false, false),
+            _ => {}
+        }
+    }
+
+    fn esc_dispatch(&mut self, intermediates: &[u8], byte: u8) {
+        let pen = self.pen;
+        match (intermediates, byte) {
+            ([], b'7') => self.save_cursor(),
+            ([], b'8') => self.restore_cursor(),
+            ([], b'D') => self.grid_mut().linefeed(&pen),
+            ([], b'E') => {
+                let grid = self.grid_mut();
+                grid.linefeed(&pen);
+                grid.carriage_return();
+            }
+            ([], b'M') => self.grid_mut().reverse_index(&pen),
+            ([], b'c') => self.reset(),
+            ([], b'=') => self.modes.application_keypad = true,
+            ([], b'>') => self.modes.application_keypad = false,
+            ([b'('], b'0') => self.charset = Charset::DecSpecial,
+            ([b'('], _) => self.charset = Charset::Ascii,
+            _ => {}
+        }
+    }
+
+    fn osc_dispatch(&mut self, params: &[&[u8]]) {
+        match params {
+            [b"0", title, ..] | [b"2", title, ..] => {
+                self.title = Some(String::from_utf8_lossy(title).into_owned());
+            }
+            // `8;params;uri`, where the URI may itself contain semicolons
+            [b"8", _params, uri @ ..] => {
+                let uri = String::from_utf8_lossy(&uri.join(&b';')).into_owned();
+                self.set_hyperlink(&uri);
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Grid {
+    fn is_empty_screen(&self) -> bool {
+        self.cursor == Cursor::default() && self.rows().iter().all(Row::is_blank)
+    }
+}
+
+/// First value of parameter `index`, or `default` when absent or zero
+fn nth(params: &[Vec<u16>], index: usize, default: u16) -> u16 {
+    match params.get(index).map(|param| param[0]) {
+        Some(0) | None => default,
+        Some(value) => value,
+    }
+}
+
+fn first(params: &[Vec<u16>], default: u16) -> u16 {
+    nth(params, 0, default)
+}
+
+/// Decode `38`/`48` colors in either `38;5;n` / `38;2;r;g;b` or colon sub-parameter form,
+/// returning the color and how many extra semicolon parameters were consumed
+fn extended_color(params: &[Vec<u16>], index: usize) -> (Option<TermColor>, usize) {
+    let param = &params[index];
+
+    if param.len() > 1 {
+        let color = match param[1] {
+            5 => param.get(2).map(|&n| TermColor::Indexed(n as u8)),
+            // `38:2:<colorspace>:r:g:b`, though the colorspace is often omitted
+            2 if param.len() >= 6 => Some(TermColor::Rgb(param[3] as u8, param[4] as u8, param[5] as u8)),
+            2 if param.len() == 5 => Some(TermColor::Rgb(param[2] as u8, param[3] as u8, param[4] as u8)),
+            _ => None,
+        };
+        return (color, 0);
+    }
+
+    let value = |offset: usize| params.get(index + offset).map(|p| p[0]);
+    match value(1) {
+        Some(5) => (value(2).map(|n| TermColor::Indexed(n as u8)), 2),
+        Some(2) => match (value(2), value(3), value(4)) {
+            (Some(r), Some(g), Some(b)) => (Some(TermColor::Rgb(r as u8, g as u8, b as u8)), 4),
+            _ => (None, params.len() - index - 1),
+        },
+        _ => (None, 0),
+    }
+}
+
+/// Map DEC special graphics characters to their Unicode box-drawing equivalents
+fn dec_special(ch: char) -> char {
+    match ch {
+        '`' => '◆',
+        'a' => '▒',
+        'f' => '°',
+        'g' => '±',
+        'j' => '┘',
+        'k' => '┐',
+        'l' => '┌',
+        'm' => '└',
+        'n' => '┼',
+        'q' => '─',
+        't' => '├',
+        'u' => '┤',
+        'v' => '┴',
+        'w' => '┬',
+        'x' => '│',
+        'y' => '≤',
+        'z' => '≥',
+        '~' => '·',
+        _ => ch,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn terminal(input: &str) -> Terminal {
+        let mut terminal = Terminal::new(20, 5, 100);
+        terminal.feed(input.as_bytes());
+        terminal
+    }
+
+    #[test]
+    fn test_plain_text_and_newlines() {
+        let terminal = terminal("hello\r\nworld\r\n");
+        assert_eq!(terminal.text(), "hello\nworld");
+    }
+
+    #[test]
+    fn test_carriage_return_overwrites_progress() {
+        let terminal = terminal("progress 10%\rprogress 100%\r\n");
+        assert_eq!(terminal.text(), "progress 100%");
+    }
+
+    #[test]
+    fn test_erase_line() {
+        let terminal = terminal("abcdef\r\x1b[2Kxy");
+        assert_eq!(terminal.text(), "xy");
+    }
+
+    #[test]
+    fn test_sgr_colors() {
+        let terminal = terminal("\x1b[1;31mA\x1b[38;5;208mB\x1b[38;2;1;2;3mC\x1b[48:2::4:5:6mD\x1b[0mE");
+        let cells = &terminal.grid().rows()[0].cells;
+
+        assert_eq!(cells[0].fg, TermColor::Indexed(1));
+        assert!(cells[0].attrs.bold);
+        assert_eq!(cells[1].fg, TermColor::Indexed(208));
+        assert_eq!(cells[2].fg, TermColor::Rgb(1, 2, 3));
+        assert_eq!(cells[3].bg, TermColor::Rgb(4, 5, 6));
+        assert_eq!(cells[4].fg, TermColor::Default);
+        assert!(!cells[4].attrs.bold);
+    }
+
+    #[test]
+    fn test_osc8_hyperlinks() {
+        let terminal = terminal("\x1b]8;id=1;https://a.example/?x=1;y=2\x1b\\a\x1b[0mb\x1b]8;;\x07c\x1b]8;;https://a.example/?x=1;y=2\x07d");
+        let cells = &terminal.grid().rows()[0].cells;
+
+        let link = cells[0].link.unwrap();
+        assert_eq!(terminal.hyperlink(link), Some("https://a.example/?x=1;y=2"));
+        // Colors resetting does not end the link, and the same target is stored once
+        assert_eq!(cells[1].link, Some(link));
+        assert_eq!(cells[2].link, None);
+        assert_eq!(cells[3].link, Some(link));
+    }
+
+    #[test]
+    fn test_ansi_text_reproduces_styles() {
+        let original = terminal("\x1b[1;31mred\x1b[0m plain\r\n\x1b[48;5;208m bg \x1b[0m end");
+        let replayed = terminal(&original.ansi_text().replace('\n', "\r\n"));
+
+        assert_eq!(replayed.text(), original.text());
+        let rows = |terminal: &Terminal| terminal.lines().cloned().collect::<Vec<_>>();
+        assert_eq!(rows(&replayed), rows(&original));
+    }
+
+    #[test]
+    fn test_scrolling_feeds_scrollback() {
+        let terminal = terminal("1\r\n2\r\n3\r\n4\r\n5\r\n6\r\n7");
+        assert_eq!(terminal.grid().scrollback().len(), 2);
+        assert_eq!(terminal.text(), "1\n2\n3\n4\n5\n6\n7");
+    }
+
+    #[test]
+    fn test_autowrap_joins_lines() {
+        let terminal = terminal(&"x".repeat(25));
+        assert_eq!(terminal.text(), "x".repeat(25));
+        assert!(terminal.grid().rows()[0].wrapped);
+    }
+
+    #[test]
+    fn test_alternate_screen_preserves_primary() {
+        let mut terminal = terminal("before\r\n");
+        terminal.feed(b"\x1b[?1049h\x1b[2J\x1b[Hfullscreen");
+        assert!(terminal.is_alternate_screen());
+        assert_eq!(terminal.grid().rows()[0].text(), "fullscreen");
+
+        terminal.feed(b"\x1b[?1049lafter");
+        assert!(!terminal.is_alternate_screen());
+        assert_eq!(terminal.text(), "before\nafter");
+    }
+
+    #[test]
+    fn test_alternate_screen_starts_blank_and_keeps_the_transcript() {
+        let mut terminal = terminal("$ vim\r\n");
+        terminal.feed(b"\x1b[?1049hfirst");
+        // The block's transcript is the primary screen, whatever the program draws
+        assert_eq!(terminal.text(), "$ vim");
+        terminal.feed(b"\x1b[?1049l\x1b[?1049h");
+        assert!(terminal.grid().rows().iter().all(|row| row.text().is_empty()));
+        terminal.feed(b"\x1b[?1049l");
+        assert!(!terminal.is_alternate_screen());
+    }
+
+    #[test]
+    fn test_only_1049_saves_the_cursor_and_pen() {
+        let mut saved = terminal("a\x1b[31m");
+        saved.feed(b"\x1b[?1049h\x1b[0m\x1b[4;6Hx\x1b[?1049lb");
+        assert_eq!(saved.text(), "ab");
+        assert_eq!(saved.grid().rows()[0].cells[1].fg, TermColor::Indexed(1));
+
+        let mut unsaved = terminal("a\x1b[31m");
+        unsaved.feed(b"\x1b[?1047h\x1b[0mx\x1b[?1047lb");
+        assert_eq!(unsaved.text(), "ab");
+        assert_eq!(unsaved.grid().rows()[0].cells[1].fg, TermColor::Default);
+    }
+
+    #[test]
+    fn test_resizing_applies_to_both_screens() {
+        let mut terminal = terminal("\x1b[?1049h");
+        terminal.resize(40, 12);
+        assert_eq!(terminal.grid().rows().len(), 12);
+        terminal.feed(b"\x1b[?1049l");
+        assert_eq!(terminal.grid().rows().len(), 12);
+        assert_eq!(terminal.grid().rows()[0].cells.len(), 40);
+    }
+
+    #[test]
+    fn test_scroll_region() {
+        let mut terminal = terminal("a\r\nb\r\nc\r\nd\r\ne");
+        terminal.feed(b"\x1b[2;4r\x1b[4;1H\n");
+        let rows: Vec<String> = terminal.grid().rows().iter().map(Row::text).collect();
+        assert_eq!(rows, vec!["a", "c", "d", "", "e"]);
+        assert!(terminal.grid().scrollback().is_empty());
+    }
+
+    #[test]
+    fn test_cursor_position_report() {
+        let mut terminal = terminal("\x1b[3;4H\x1b[6n");
+        assert_eq!(terminal.take_responses(), b"\x1b[3;4R".to_vec());
+    }
+
+    #[test]
+    fn test_utf8_split_across_feeds() {
+        let mut terminal = Terminal::new(20, 5, 100);
+        let bytes = "héllo ✓".as_bytes();
+        terminal.feed(&bytes[..2]);
+        terminal.feed(&bytes[2..]);
+        assert_eq!(terminal.text(), "héllo ✓");
+    }
+}
@@ -0,0 +1,280 @@
+/// Longest OSC payload kept before the rest is discarded
+const MAX_OSC_LEN: usize = 4096;
+/// Maximum number of CSI parameters tracked; extra parameters are ignored
+const MAX_PARAMS: usize = 32;
+
+/// Receiver for the actions decoded by a `Parser`
+pub trait Perform {
+    /// A printable character
+    fn print(&mut self, ch: char);
+    /// A C0 control character
+    fn execute(&mut self, byte: u8);
+    /// `ESC [ <private> <params> <intermediates> <action>`
+    ///
+    /// Each parameter is a list so that colon-separated sub-parameters
+    /// (`38:2::255:0:0`) are preserved.
+    fn csi_dispatch(&mut self, params: &[Vec<u16>], private: Option<u8>, intermediates: &[u8], action: char);
+    /// `ESC <intermediates> <byte>`
+    fn esc_dispatch(&mut self, intermediates: &[u8], byte: u8);
+    /// `ESC ] <params separated by ';'> BEL|ST`
+    fn osc_dispatch(&mut self, params: &[&[u8]]);
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Ground,
+    Escape,
+    EscapeIntermediate,
+    CsiParam,
+    CsiIgnore,
+    OscString,
+    /// DCS, SOS, PM and APC strings are consumed without interpretation
+    StringIgnore,
+}
+
+/// Byte-at-a-time decoder for UTF-8 text interleaved with VT100/xterm control sequences
+#[derive(Debug, Clone)]
+pub struct Parser {
+    state: State,
+    params: Vec<Vec<u16>>,
+    current_param: Vec<u16>,
+    private: Option<u8>,
+    intermediates: Vec<u8>,
+    osc: Vec<u8>,
+    /// An ESC seen inside a string, which may begin the ST terminator
+    string_escape: bool,
+    utf8: Vec<u8>,
+    utf8_len: usize,
+}
+
+impl Default for Parser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Parser {
+    pub fn new() -> Self {
+        Self {
+            state: State::Ground,
+            params: Vec::new(),
+            current_param: Vec::new(),
+            private: None,
+            intermediates: Vec::new(),
+            osc: Vec::new(),
+            string_escape: false,
+            utf8: Vec::with_capacity(4),
+            utf8_len: 0,
+        }
+    }
+
+    pub fn advance<P: Perform>(&mut self, performer: &mut P, bytes: &[u8]) {
+        for &byte in bytes {
+            self.advance_byte(performer, byte);
+        }
+    }
+
+    fn advance_byte<P: Perform>(&mut self, performer: &mut P, byte: u8) {
+        match self.state {
+            State::OscString => return self.advance_osc(performer, byte),
+            State::StringIgnore => return self.advance_string_ignore(byte),
+            _ => {}
+        }
+
+        // These controls take effect in every remaining state
+        match byte {
+            0x18 | 0x1a => {
+                self.state = State::Ground;
+                return;
+            }
+            0x1b => {
+                self.enter_escape();
+                return;
+            }
+            _ => {}
+        }
+
+        match self.state {
+            State::Ground => self.advance_ground(performer, byte),
+            State::Escape => self.advance_escape(performer, byte),
+            State::EscapeIntermediate => match byte {
+                0x00..=0x1f => performer.execute(byte),
+                0x20..=0x2f => self.intermediates.push(byte),
+                _ => {
+                    performer.esc_dispatch(&self.intermediates, byte);
+                    self.state = State::Ground;
+                }
+            },
+            State::CsiParam => self.advance_csi(performer, byte),
+            State::CsiIgnore => match byte {
+                0x00..=0x1f => performer.execute(byte),
+                0x40..=0x7e => self.state = State::Ground,
+                _ => {}
+            },
+            State::OscString | State::StringIgnore => unreachable!(),
+        }
+    }
+
+    fn advance_ground<P: Perform>(&mut self, performer: &mut P, byte: u8) {
+        if self.utf8_len > 0 {
+            if byte & 0xc0 == 0x80 {
+                self.utf8.push(byte);
+                if self.utf8.len() == self.utf8_len {
+                    let ch = std::str::from_utf8(&self.utf8)
+                        .ok()
+                        .and_then(|s| s.chars().next())
+                        .unwrap_or(char::REPLACEMENT_CHARACTER);
+                    self.utf8.clear();
+                    self.utf8_len = 0;
+                    performer.print(ch);
+                }
+                return;
+            }
+            // Truncated sequence: report it and reprocess this byte on its own
+            self.utf8.clear();
+            self.utf8_len = 0;
+            performer.print(char::REPLACEMENT_CHARACTER);
+        }
+
+        match byte {
+            0x00..=0x1f => performer.execute(byte),
+            0x20..=0x7e => performer.print(byte as char),
+            0x7f => {}
+            0xc2..=0xdf => self.begin_utf8(byte, 2),
+            0xe0..=0xef => self.begin_utf8(byte, 3),
+            0xf0..=0xf4 => self.begin_utf8(byte, 4),
+            _ => performer.print(char::REPLACEMENT_CHARACTER),
+        }
+    }
+
+    fn begin_utf8(&mut self, byte: u8, len: usize) {
+        self.utf8.clear();
+        self.utf8.push(byte);
+        self.utf8_len = len;
+    }
+
+    fn advance_escape<P: Perform>(&mut self, performer: &mut P, byte: u8) {
+        match byte {
+            0x00..=0x1f => performer.execute(byte),
+            b'[' => {
+                self.params.clear();
+                self.current_param.clear();
+                self.private = None;
+                self.state = State::CsiParam;
+            }
+            b']' => {
+                self.osc.clear();
+                self.string_escape = false;
+                self.state = State::OscString;
+            }
+            b'P' | b'X' | b'^' | b'_' => {
+                self.string_escape = false;
+                self.state = State::StringIgnore;
+            }
+            0x20..=0x2f => {
+                self.intermediates.push(byte);
+                self.state = State::EscapeIntermediate;
+            }
+            _ => {
+                performer.esc_dispatch(&self.intermediates, byte);
+                self.state = State::Ground;
+            }
+        }
+    }
+
+    fn advance_csi<P: Perform>(&mut self, performer: &mut P, byte: u8) {
+        match byte {
+            0x00..=0x1f => performer.execute(byte),
+            b'0'..=b'9' => {
+                if self.current_param.is_empty() {
+                    self.current_param.push(0);
+                }
+                if let Some(value) = self.current_param.last_mut() {
+                    *value = value.saturating_mul(10).saturating_add((byte - b'0') as u16);
+                }
+            }
+            b':' => {
+                if self.current_param.is_empty() {
+                    self.current_param.push(0);
+                }
+                self.current_param.push(0);
+            }
+            b';' => self.finish_param(),
+            0x3c..=0x3f => {
+                // Private markers are only valid before any parameter
+                if self.params.is_empty() && self.current_param.is_empty() && self.private.is_none() {
+                    self.private = Some(byte);
+                } else {
+                    self.state = State::CsiIgnore;
+                }
+            }
+            0x20..=0x2f => self.intermediates.push(byte),
+            0x40..=0x7e => {
+                if !self.current_param.is_empty() || !self.params.is_empty() {
+                    self.finish_param();
+                }
+                performer.csi_dispatch(&self.params, self.private, &self.intermediates, byte as char);
+                self.state = State::Ground;
+            }
+            _ => self.state = State::CsiIgnore,
+        }
+    }
+
+    fn finish_param(&mut self) {
+        let param = std::mem::take(&mut self.current_param);
+        if self.params.len() < MAX_PARAMS {
+            // An empty parameter means "default", represented as 0
+            self.params.push(if param.is_empty() { vec![0] } else { param });
+        }
+    }
+
+    fn advance_osc<P: Perform>(&mut self, performer: &mut P, byte: u8) {
+        if self.string_escape {
+            self.string_escape = false;
+            if byte == b'\\' {
+                self.dispatch_osc(performer);
+                self.state = State::Ground;
+                return;
+            }
+            // Any other byte after ESC aborts the string and starts a new sequence
+            self.dispatch_osc(performer);
+            self.enter_escape();
+            return self.advance_byte(performer, byte);
+        }
+
+        match byte {
+            0x07 => {
+                self.dispatch_osc(performer);
+                self.state = State::Ground;
+            }
+            0x1b => self.string_escape = true,
+            0x18 | 0x1a => self.state = State::Ground,
+            _ => {
+                if self.osc.len() < MAX_OSC_LEN {
+                    self.osc.push(byte);
+                }
+            }
+        }
+    }
+
+    fn dispatch_osc<P: Perform>(&mut self, performer: &mut P) {
+        let params: Vec<&[u8]> = self.osc.split(|&b| b == b';').collect();
+        performer.osc_dispatch(&params);
+    }
+
+    fn advance_string_ignore(&mut self, byte: u8) {
+        match byte {
+            0x07 | 0x18 | 0x1a => self.state = State::Ground,
+            0x1b => self.string_escape = true,
+            b'\\' if self.string_escape => self.state = State::Ground,
+            _ => self.string_escape = false,
+        }
+    }
+
+    fn enter_escape(&mut self) {
+        self.intermediates.clear();
+        self.utf8.clear();
+        self.utf8_len = 0;
+        self.state = State::Escape;
+    }
+}
@@ -0,0 +1,201 @@
+use iced::font::{Style, Weight};
+use iced::widget::{column, container, mouse_area, text, Row as RowWidget};
+use iced::{Color, Element, Font};
+use std::ops::Range;
+
+use crate::config::ColorScheme;
+use super::color::cell_colors;
+use super::grid::{Cell, CellAttributes, Grid, Row};
+
+/// Consecutive cells sharing the same colors and attributes
+pub struct Run {
+    pub text: String,
+    pub fg: Color,
+    pub bg: Color,
+    pub attrs: CellAttributes,
+    /// Index of the [`LinkSpan`] the run belongs to
+    pub link: Option<usize>,
+}
+
+/// Cells of a row that send a message when clicked, such as a URL in the output
+#[derive(Debug, Clone)]
+pub struct LinkSpan<Message> {
+    pub row: usize,
+    pub columns: Range<usize>,
+    pub on_press: Message,
+}
+
+/// Cells of a row drawn in the selection color, such as a search match
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Highlight {
+    pub row: usize,
+    pub columns: Range<usize>,
+    /// Drawn more prominently than the other highlights
+    pub current: bool,
+}
+
+/// Render numbered terminal rows as monospace text, one widget per run of identically styled cells,
+/// with `highlights` drawn in the selection colors and `links` in the primary color and clickable
+pub fn view<'a, Message: Clone + 'a>(
+    rows: impl Iterator<Item = (usize, &'a Row)>,
+    colors: &ColorScheme,
+    font_size: f32,
+    highlights: &[Highlight],
+    links: &[LinkSpan<Message>],
+) -> Element<'a, Message> {
+    let default_bg: Color = colors.terminal_background.clone().into();
+    let link_fg: Color = colors.primary.clone().into();
+
+    column(rows.map(|(index, line)| {
+        let highlights: Vec<&Highlight> = highlights.iter().filter(|h| h.row == index).collect();
+        let row_links: Vec<(usize, &LinkSpan<Message>)> =
+            links.iter().enumerate().filter(|(_, link)| link.row == index).collect();
+        if highlights.is_empty() && row_links.is_empty() {
+            return view_row(line, colors, default_bg, font_size);
+        }
+
+        // The current match stands out from the others the way a cursor would
+        let current = (default_bg, colors.warning.clone().into());
+        let other = (colors.terminal_foreground.clone().into(), colors.terminal_selection.clone().into());
+        let link_at = |col| {
+            row_links
+                .iter()
+                .find(|(_, link)| link.columns.contains(&col))
+                .map(|(index, _)| *index)
+        };
+        let runs = styled_runs(
+            line,
+            colors,
+            |col, cell| {
+                let highlight = highlights.iter().find(|h| h.columns.contains(&col));
+                match highlight {
+                    Some(h) => Some(if h.current { current } else { other }),
+                    None => link_at(col).map(|_| (link_fg, cell_colors(cell, colors).1)),
+                }
+            },
+            link_at,
+        );
+        view_runs(runs, default_bg, font_size, links)
+    }))
+    .into()
+}
+
+/// Render every row of a screen, as used for full-screen programs, with the cursor drawn inverted
+pub fn screen<'a, Message: Clone + 'a>(
+    grid: &Grid,
+    colors: &ColorScheme,
+    font_size: f32,
+    show_cursor: bool,
+) -> Element<'a, Message> {
+    let default_bg: Color = colors.terminal_background.clone().into();
+    let cursor = grid.cursor;
+
+    column(grid.rows().iter().enumerate().map(|(index, line)| {
+        if show_cursor && index == cursor.row {
+            let mut line = line.clone();
+            if let Some(cell) = line.cells.get_mut(cursor.col) {
+                cell.attrs.inverse = !cell.attrs.inverse;
+            }
+            view_row(&line, colors, default_bg, font_size)
+        } else {
+            view_row(line, colors, default_bg, font_size)
+        }
+    }))
+    .into()
+}
+
+fn view_row<'a, Message: Clone + 'a>(
+    line: &Row,
+    colors: &ColorScheme,
+    default_bg: Color,
+    font_size: f32,
+) -> Element<'a, Message> {
+    view_runs(runs(line, colors), default_bg, font_size, &[])
+}
+
+fn view_runs<'a, Message: Clone + 'a>(
+    runs: Vec<Run>,
+    default_bg: Color,
+    font_size: f32,
+    links: &[LinkSpan<Message>],
+) -> Element<'a, Message> {
+    if runs.is_empty() {
+        // Keep blank lines at full height
+        return text(" ").size(font_size).font(Font::MONOSPACE).into();
+    }
+
+    let spans = runs.into_iter().map(|run| {
+        let fg = run.fg;
+        let span = text(run.text)
+            .size(font_size)
+            .font(font_for(&run.attrs))
+            .style(move |_theme| text::Appearance { color: Some(fg) });
+
+        let span: Element<'a, Message> = if run.bg == default_bg {
+            span.into()
+        } else {
+            let bg = run.bg;
+            container(span)
+                .style(move |_theme| container::Appearance {
+                    background: Some(bg.into()),
+                    ..Default::default()
+                })
+                .into()
+        };
+
+        match run.link.and_then(|index| links.get(index)) {
+            Some(link) => mouse_area(span).on_press(link.on_press.clone()).into(),
+            None => span,
+        }
+    });
+
+    RowWidget::with_children(spans.collect::<Vec<Element<'a, Message>>>()).into()
+}
+
+/// Split a row into styled runs, leaving out its trailing blank cells
+pub fn runs(line: &Row, colors: &ColorScheme) -> Vec<Run> {
+    styled_runs(line, colors, |_, _| None, |_| None)
+}
+
+/// Like [`runs`], with `highlight` giving the foreground and background to use instead for a column,
+/// and runs split where the link given by `link` changes
+fn styled_runs(
+    line: &Row,
+    colors: &ColorScheme,
+    highlight: impl Fn(usize, &Cell) -> Option<(Color, Color)>,
+    link: impl Fn(usize) -> Option<usize>,
+) -> Vec<Run> {
+    // Trailing blank cells only add width
+    let used = line
+        .cells
+        .iter()
+        .rposition(|cell| !cell.is_blank())
+        .map_or(0, |last| last + 1);
+
+    let mut runs: Vec<Run> = Vec::new();
+    for (col, cell) in line.cells[..used].iter().enumerate() {
+        let (fg, bg) = highlight(col, cell).unwrap_or_else(|| cell_colors(cell, colors));
+        let link = link(col);
+        match runs.last_mut() {
+            Some(run) if run.fg == fg && run.bg == bg && run.attrs == cell.attrs && run.link == link => {
+                run.text.push(cell.ch)
+            }
+            _ => runs.push(Run {
+                text: cell.ch.to_string(),
+                fg,
+                bg,
+                attrs: cell.attrs,
+                link,
+            }),
+        }
+    }
+    runs
+}
+
+fn font_for(attrs: &CellAttributes) -> Font {
+    Font {
+        weight: if attrs.bold { Weight::Bold } else { Weight::Normal },
+        style: if attrs.italic { Style::Italic } else { Style::Normal },
+        ..Font::MONOSPACE
+    }
+}
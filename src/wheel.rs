@@ -0,0 +1,146 @@
+use iced::advanced::widget::{Operation, Tree};
+use iced::advanced::{layout, overlay, renderer, Clipboard, Layout, Shell, Widget};
+use iced::{event, mouse, Element, Event, Length, Rectangle, Size, Vector};
+
+/// Scales the mouse wheel for the scrollables inside it, by the scroll sensitivity, before they
+/// see it; everything else passes through untouched
+pub struct ScaledWheel<'a, Message, Theme, Renderer> {
+    content: Element<'a, Message, Theme, Renderer>,
+    scale: f32,
+}
+
+pub fn scaled_wheel<'a, Message, Theme, Renderer>(
+    content: impl Into<Element<'a, Message, Theme, Renderer>>,
+    scale: f32,
+) -> ScaledWheel<'a, Message, Theme, Renderer> {
+    ScaledWheel {
+        content: content.into(),
+        scale,
+    }
+}
+
+impl<'a, Message, Theme, Renderer> Widget<Message, Theme, Renderer> for ScaledWheel<'a, Message, Theme, Renderer>
+where
+    Renderer: renderer::Renderer,
+{
+    fn size(&self) -> Size<Length> {
+        self.content.as_widget().size()
+    }
+
+    fn size_hint(&self) -> Size<Length> {
+        self.content.as_widget().size_hint()
+    }
+
+    fn layout(&self, tree: &mut Tree, renderer: &Renderer, limits: &layout::Limits) -> layout::Node {
+        self.content.as_widget().layout(&mut tree.children[0], renderer, limits)
+    }
+
+    fn draw(
+        &self,
+        tree: &Tree,
+        renderer: &mut Renderer,
+        theme: &Theme,
+        style: &renderer::Style,
+        layout: Layout<'_>,
+        cursor: mouse::Cursor,
+        viewport: &Rectangle,
+    ) {
+        self.content
+            .as_widget()
+            .draw(&tree.children[0], renderer, theme, style, layout, cursor, viewport);
+    }
+
+    fn children(&self) -> Vec<Tree> {
+        vec![Tree::new(&self.content)]
+    }
+
+    fn diff(&self, tree: &mut Tree) {
+        tree.diff_children(std::slice::from_ref(&self.content));
+    }
+
+    fn operate(
+        &self,
+        tree: &mut Tree,
+        layout: Layout<'_>,
+        renderer: &Renderer,
+        operation: &mut dyn Operation<Message>,
+    ) {
+        self.content
+            .as_widget()
+            .operate(&mut tree.children[0], layout, renderer, operation);
+    }
+
+    fn on_event(
+        &mut self,
+        tree: &mut Tree,
+        event: Event,
+        layout: Layout<'_>,
+        cursor: mouse::Cursor,
+        renderer: &Renderer,
+        clipboard: &mut dyn Clipboard,
+        shell: &mut Shell<'_, Message>,
+        viewport: &Rectangle,
+    ) -> event::Status {
+        let event = match event {
+            Event::Mouse(mouse::Event::WheelScrolled { delta }) => Event::Mouse(mouse::Event::WheelScrolled {
+                delta: match delta {
+                    mouse::ScrollDelta::Lines { x, y } => mouse::ScrollDelta::Lines {
+                        x: x * self.scale,
+                        y: y * self.scale,
+                    },
+                    mouse::ScrollDelta::Pixels { x, y } => mouse::ScrollDelta::Pixels {
+                        x: x * self.scale,
+                        y: y * self.scale,
+                    },
+                },
+            }),
+            event => event,
+        };
+        self.content.as_widget_mut().on_event(
+            &mut tree.children[0],
+            event,
+            layout,
+            cursor,
+            renderer,
+            clipboard,
+            shell,
+            viewport,
+        )
+    }
+
+    fn mouse_interaction(
+        &self,
+        tree: &Tree,
+        layout: Layout<'_>,
+        cursor: mouse::Cursor,
+        viewport: &Rectangle,
+        renderer: &Renderer,
+    ) -> mouse::Interaction {
+        self.content
+            .as_widget()
+            .mouse_interaction(&tree.children[0], layout, cursor, viewport, renderer)
+    }
+
+    fn overlay<'b>(
+        &'b mut self,
+        tree: &'b mut Tree,
+        layout: Layout<'_>,
+        renderer: &Renderer,
+        translation: Vector,
+    ) -> Option<overlay::Element<'b, Message, Theme, Renderer>> {
+        self.content
+            .as_widget_mut()
+            .overlay(&mut tree.children[0], layout, renderer, translation)
+    }
+}
+
+impl<'a, Message, Theme, Renderer> From<ScaledWheel<'a, Message, Theme, Renderer>> for Element<'a, Message, Theme, Renderer>
+where
+    Message: 'a,
+    Theme: 'a,
+    Renderer: renderer::Renderer + 'a,
+{
+    fn from(wheel: ScaledWheel<'a, Message, Theme, Renderer>) -> Self {
+        Element::new(wheel)
+    }
+}